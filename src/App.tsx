@@ -300,6 +300,10 @@ function App() {
       let result;
       const { format, quality, targetSize } = compressParams;
 
+      // Target-size compression may downscale
+      let outputWidth = currentImage.width;
+      let outputHeight = currentImage.height;
+
       if (targetSize) {
         result = await compressToSize(
           rgbaBuffer,
@@ -308,6 +312,8 @@ function App() {
           targetSize,
           format
         );
+        outputWidth = result.width;
+        outputHeight = result.height;
       } else if (format === CompressionFormat.WebP) {
         result = await compressWebp(
           rgbaBuffer,
@@ -356,7 +362,7 @@ function App() {
           mode: targetSize ? 'target_size' : 'quality',
         },
         timestamp: Date.now()
-      }, currentImage.width, currentImage.height); // Compression doesn't change dimensions
+      }, outputWidth, outputHeight);
 
       const mode = targetSize ? 'Target Size' : 'Quality';
       const formatLabel = format === CompressionFormat.WebP ? 'WebP' : format === CompressionFormat.JPEG ? 'JPEG' : 'PNG';
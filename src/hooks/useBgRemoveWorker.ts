@@ -8,6 +8,9 @@ import { MessageType } from '../types';
 import type {
   RemoveSolidColorPayload,
   MagicWandSelectPayload,
+  GrabCutSegmentPayload,
+  ProgressData,
+  ProgressOptions,
   WorkerMessage,
   WorkerResponse,
 } from '../types';
@@ -56,7 +59,8 @@ interface UseBgRemoveWorkerReturn {
     rectY: number,
     rectWidth: number,
     rectHeight: number,
-    iterations?: number
+    iterations?: number,
+    options?: ProgressOptions
   ) => Promise<GrabCutResult>;
   loading: boolean;
   error: Error | null;
@@ -73,6 +77,7 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
   const [initialized, setInitialized] = useState(false);
 
   const messageHandlersRef = useRef<Map<string, (response: WorkerResponse) => void>>(new Map());
+  const progressHandlersRef = useRef<Map<string, (progress: number) => void>>(new Map());
 
   // Initialize worker
   useEffect(() => {
@@ -87,11 +92,18 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
       worker.onmessage = (event: MessageEvent<WorkerResponse>) => {
         const response = event.data;
 
+        // Progress updates arrive before the result of the same message
+        if (response.type === MessageType.PROGRESS) {
+          progressHandlersRef.current.get(response.id)?.((response.data as ProgressData).progress);
+          return;
+        }
+
         // Call message handler if exists (for promise-based requests)
         const handler = messageHandlersRef.current.get(response.id);
         if (handler) {
           handler(response);
           messageHandlersRef.current.delete(response.id);
+          progressHandlersRef.current.delete(response.id);
         }
 
         // Update initialized state
@@ -158,7 +170,8 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
    * Send message to worker
    */
   const sendMessage = useCallback(<TPayload = unknown, TResponse = unknown>(
-    message: Omit<WorkerMessage<TPayload>, 'id' | 'timestamp'>
+    message: Omit<WorkerMessage<TPayload>, 'id' | 'timestamp'>,
+    onProgress?: (progress: number) => void
   ): Promise<WorkerResponse<TResponse>> => {
     return new Promise((resolve, reject) => {
       if (!workerRef.current) {
@@ -177,10 +190,15 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
       const timeoutId = setTimeout(() => {
         if (messageHandlersRef.current.has(id)) {
           messageHandlersRef.current.delete(id);
+          progressHandlersRef.current.delete(id);
           reject(new Error('Worker response timeout'));
         }
       }, 30000);
 
+      if (onProgress) {
+        progressHandlersRef.current.set(id, onProgress);
+      }
+
       // Set up handler for this message
       messageHandlersRef.current.set(id, (response: WorkerResponse<unknown>) => {
         clearTimeout(timeoutId); // Clear timeout on successful response
@@ -295,10 +313,11 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
       rectY: number,
       rectWidth: number,
       rectHeight: number,
-      iterations?: number
+      iterations?: number,
+      options?: ProgressOptions
     ): Promise<GrabCutResult> => {
       try {
-        const response = await sendMessage<{ imageData: Uint8Array; width: number; height: number; rectX: number; rectY: number; rectWidth: number; rectHeight: number; iterations: number }, GrabCutResult>({
+        const response = await sendMessage<GrabCutSegmentPayload, GrabCutResult>({
           type: MessageType.GRABCUT_SEGMENT,
           payload: {
             imageData,
@@ -309,8 +328,9 @@ export function useBgRemoveWorker(): UseBgRemoveWorkerReturn {
             rectWidth,
             rectHeight,
             iterations: iterations ?? 5,
+            cancelFlag: options?.cancelFlag,
           },
-        });
+        }, options?.onProgress);
 
         if (!response.success || !response.data) {
           throw new Error(response.error || 'GrabCut segmentation failed');
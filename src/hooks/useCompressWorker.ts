@@ -9,10 +9,12 @@ import type {
   CompressJpegPayload,
   CompressWebpPayload,
   CompressToSizePayload,
+  ProgressData,
+  ProgressOptions,
   WorkerMessage,
   WorkerResponse,
 } from '../types';
-import type { JpegAdvancedParams, WebPAdvancedParams } from '../types/wasm';
+import type { JpegAdvancedParams, WebPAdvancedParams, SizeStrategy } from '../types/wasm';
 
 interface CompressResult {
   imageData: Uint8Array;
@@ -21,6 +23,14 @@ interface CompressResult {
   quality?: number;
 }
 
+type CompressToSizeResult = CompressResult & {
+  quality: number;
+  strategy: SizeStrategy;
+  colors: number;
+  width: number;
+  height: number;
+};
+
 interface UseCompressWorkerReturn {
   compressJpeg: (
     imageData: Uint8Array,
@@ -42,8 +52,10 @@ interface UseCompressWorkerReturn {
     width: number,
     height: number,
     targetSize: number,
-    format: CompressionFormat
-  ) => Promise<CompressResult & { quality: number }>;
+    format: CompressionFormat,
+    minQuality?: number,
+    options?: ProgressOptions
+  ) => Promise<CompressToSizeResult>;
   loading: boolean;
   error: Error | null;
   initialized: boolean;
@@ -59,6 +71,7 @@ export function useCompressWorker(): UseCompressWorkerReturn {
   const [initialized, setInitialized] = useState(false);
 
   const messageHandlersRef = useRef<Map<string, (response: WorkerResponse) => void>>(new Map());
+  const progressHandlersRef = useRef<Map<string, (progress: number) => void>>(new Map());
 
   // Initialize worker
   useEffect(() => {
@@ -71,10 +84,17 @@ export function useCompressWorker(): UseCompressWorkerReturn {
 
       worker.onmessage = (event: MessageEvent<WorkerResponse>) => {
         const response = event.data;
+
+        // Progress updates arrive before the result of the same message
+        if (response.type === MessageType.PROGRESS) {
+          progressHandlersRef.current.get(response.id)?.((response.data as ProgressData).progress);
+          return;
+        }
         const handler = messageHandlersRef.current.get(response.id);
         if (handler) {
           handler(response);
           messageHandlersRef.current.delete(response.id);
+          progressHandlersRef.current.delete(response.id);
         }
 
         // Handle initialization response
@@ -127,7 +147,8 @@ export function useCompressWorker(): UseCompressWorkerReturn {
    * Send message to worker
    */
   const sendMessage = useCallback(<TPayload = unknown, TResponse = unknown>(
-    message: Omit<WorkerMessage<TPayload>, 'id' | 'timestamp'>
+    message: Omit<WorkerMessage<TPayload>, 'id' | 'timestamp'>,
+    onProgress?: (progress: number) => void
   ): Promise<WorkerResponse<TResponse>> => {
     return new Promise((resolve, reject) => {
       if (!workerRef.current) {
@@ -146,10 +167,15 @@ export function useCompressWorker(): UseCompressWorkerReturn {
       const timeoutId = setTimeout(() => {
         if (messageHandlersRef.current.has(id)) {
           messageHandlersRef.current.delete(id);
+          progressHandlersRef.current.delete(id);
           reject(new Error('Worker response timeout'));
         }
       }, 30000);
 
+      if (onProgress) {
+        progressHandlersRef.current.set(id, onProgress);
+      }
+
       messageHandlersRef.current.set(id, (response: WorkerResponse) => {
         clearTimeout(timeoutId); // Clear timeout on successful response
         if (response.success) {
@@ -245,7 +271,8 @@ export function useCompressWorker(): UseCompressWorkerReturn {
 
   /**
    * Compress image to target file size
-   * Uses binary search to find optimal quality parameter
+   * Uses binary search to find optimal quality parameter; with minQuality,
+   * the image is downscaled rather than compressed below that quality
    */
   const compressToSize = useCallback(
     async (
@@ -253,10 +280,12 @@ export function useCompressWorker(): UseCompressWorkerReturn {
       width: number,
       height: number,
       targetSize: number,
-      format: CompressionFormat
-    ): Promise<CompressResult & { quality: number }> => {
+      format: CompressionFormat,
+      minQuality?: number,
+      options?: ProgressOptions
+    ): Promise<CompressToSizeResult> => {
       try {
-        const response = await sendMessage<CompressToSizePayload, CompressResult & { quality: number }>({
+        const response = await sendMessage<CompressToSizePayload, CompressToSizeResult>({
           type: MessageType.COMPRESS_TO_SIZE,
           payload: {
             imageData,
@@ -264,8 +293,10 @@ export function useCompressWorker(): UseCompressWorkerReturn {
             height,
             targetSize,
             format,
+            minQuality,
+            cancelFlag: options?.cancelFlag,
           },
-        });
+        }, options?.onProgress);
 
         if (!response.success || !response.data) {
           throw new Error(response.error || 'Compress to size failed');
@@ -276,6 +307,10 @@ export function useCompressWorker(): UseCompressWorkerReturn {
           size: response.data.size,
           format: response.data.format,
           quality: response.data.quality,
+          strategy: response.data.strategy,
+          colors: response.data.colors,
+          width: response.data.width,
+          height: response.data.height,
         };
       } catch (err) {
         throw err instanceof Error ? err : new Error('Compress to size failed');
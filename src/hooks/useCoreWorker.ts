@@ -5,7 +5,7 @@
 
 import { useCallback, useRef, useEffect, useState } from 'react';
 import { MessageType, FlipDirection, ResizeQuality } from '../types';
-import type { WorkerMessage, WorkerResponse, CropRect } from '../types';
+import type { WorkerMessage, WorkerResponse, CropRect, ProgressData, ProgressOptions } from '../types';
 import { generateMessageId } from '../types/worker';
 
 interface CropResult {
@@ -52,7 +52,8 @@ export interface UseCoreWorkerReturn {
     height: number,
     newWidth: number,
     newHeight: number,
-    quality: ResizeQuality
+    quality: ResizeQuality,
+    options?: ProgressOptions
   ) => Promise<ResizeResult>;
   loading: boolean;
   error: Error | null;
@@ -69,6 +70,7 @@ export function useCoreWorker(): UseCoreWorkerReturn {
   const [initialized, setInitialized] = useState(false);
 
   const messageHandlersRef = useRef<Map<string, (response: WorkerResponse) => void>>(new Map());
+  const progressHandlersRef = useRef<Map<string, (progress: number) => void>>(new Map());
 
   // Initialize worker
   useEffect(() => {
@@ -81,10 +83,18 @@ export function useCoreWorker(): UseCoreWorkerReturn {
 
       worker.onmessage = (event: MessageEvent<WorkerResponse>) => {
         const response = event.data;
+
+        // Progress updates arrive before the result of the same message
+        if (response.type === MessageType.PROGRESS) {
+          progressHandlersRef.current.get(response.id)?.((response.data as ProgressData).progress);
+          return;
+        }
+
         const handler = messageHandlersRef.current.get(response.id);
         if (handler) {
           handler(response);
           messageHandlersRef.current.delete(response.id);
+          progressHandlersRef.current.delete(response.id);
         }
 
         // Handle initialization response
@@ -128,7 +138,7 @@ export function useCoreWorker(): UseCoreWorkerReturn {
 
   // Helper to send message and wait for response
   const sendMessage = useCallback(
-    <T>(message: WorkerMessage): Promise<WorkerResponse<T>> => {
+    <T>(message: WorkerMessage, onProgress?: (progress: number) => void): Promise<WorkerResponse<T>> => {
       return new Promise((resolve, reject) => {
         if (!workerRef.current) {
           reject(new Error('Worker not initialized'));
@@ -137,9 +147,14 @@ export function useCoreWorker(): UseCoreWorkerReturn {
 
         const timeout = setTimeout(() => {
           messageHandlersRef.current.delete(message.id);
+          progressHandlersRef.current.delete(message.id);
           reject(new Error(`Worker message timeout: ${message.type}`));
         }, 30000); // 30 second timeout
 
+        if (onProgress) {
+          progressHandlersRef.current.set(message.id, onProgress);
+        }
+
         messageHandlersRef.current.set(message.id, (response: WorkerResponse) => {
           clearTimeout(timeout);
           if (response.success) {
@@ -255,7 +270,8 @@ export function useCoreWorker(): UseCoreWorkerReturn {
       height: number,
       newWidth: number,
       newHeight: number,
-      quality: ResizeQuality
+      quality: ResizeQuality,
+      options?: ProgressOptions
     ): Promise<ResizeResult> => {
       setLoading(true);
       setError(null);
@@ -263,9 +279,17 @@ export function useCoreWorker(): UseCoreWorkerReturn {
         const response = await sendMessage<ResizeResult>({
           id: generateMessageId(),
           type: MessageType.RESIZE_IMAGE,
-          payload: { imageData: imageData.buffer, width, height, newWidth, newHeight, quality },
+          payload: {
+            imageData: imageData.buffer,
+            width,
+            height,
+            newWidth,
+            newHeight,
+            quality,
+            cancelFlag: options?.cancelFlag,
+          },
           timestamp: Date.now(),
-        });
+        }, options?.onProgress);
 
         if (!response.data) {
           throw new Error('No data returned from resize operation');
@@ -13,6 +13,7 @@ export enum BatchOperation {
   RESIZE = 'resize',
   COMPRESS = 'compress',
   REMOVE_BACKGROUND = 'remove_background',
+  REDACT = 'redact',
 }
 
 /**
@@ -40,9 +41,25 @@ export interface BatchParams {
     targetColor?: [number, number, number];
     tolerance?: number;
     feather?: number;
+    // Redaction params (serialized into the core `redact_regions` spec)
+    redaction?: RedactionSpec;
   };
 }
 
+/**
+ * Region redaction spec, mirrors `RedactionSpec` in wasm-src/core/src/redact.rs
+ */
+export interface RedactionSpec {
+  mode:
+    | { type: 'pixelate'; blockSize: number }
+    | { type: 'blur'; radius: number }
+    | { type: 'fill'; color: [number, number, number] };
+  regions: Array<
+    | { type: 'rect'; x: number; y: number; width: number; height: number }
+    | { type: 'polygon'; points: Array<[number, number]> }
+  >;
+}
+
 /**
  * Batch processing status for a single image
  */
@@ -45,8 +45,59 @@ export enum CompressionFormat {
   JPEG = 'jpeg',
   WebP = 'webp',
   PNG = 'png',
+  AVIF = 'avif',
 }
 
+/**
+ * Error category of errors thrown by the WASM modules
+ * (mirrors photo_editor_common::ErrorCode; the values are stable)
+ */
+export enum ErrorCode {
+  InvalidDimensions = 1, // Zero, overflowing or too large width/height
+  InputSizeMismatch = 2, // Input length doesn't match the dimensions
+  OutputTooSmall = 3,    // Output buffer can't hold the result
+  InvalidParameter = 4,  // Quality, options or other argument out of range
+  OutOfBounds = 5,       // Coordinates or region outside the image
+  EncodeFailed = 6,      // Encoder error
+  DecodeFailed = 7,      // Input file can't be decoded
+  Unsupported = 8,       // Valid input this build can't handle
+  Cancelled = 9,         // Stopped through a ProgressToken
+}
+
+/**
+ * Error thrown by every fallible WASM function
+ */
+export interface PhotoEditorError extends Error {
+  name: 'PhotoEditorError';
+  code: ErrorCode;
+}
+
+/**
+ * Check whether a caught value was thrown by a WASM module
+ */
+export function isPhotoEditorError(error: unknown): error is PhotoEditorError {
+  return error instanceof Error && error.name === 'PhotoEditorError' && 'code' in error;
+}
+
+/**
+ * Progress callback and cancellation for long-running WASM calls
+ * (resize_image, compress_to_size, grabcut_segment)
+ *
+ * The callback gets the fraction done (0-1) and returns false to cancel.
+ * The worker is busy during the call, so other threads cancel through an
+ * Int32Array over a SharedArrayBuffer: store a non-zero value in [0].
+ * A cancelled call throws a PhotoEditorError with ErrorCode.Cancelled.
+ * Calls consume the token; create one per call.
+ */
+export interface ProgressToken {
+  set_cancel_flag: (flag: Int32Array) => void;
+  free: () => void;
+}
+
+export type ProgressTokenConstructor = new (
+  callback?: (fraction: number) => boolean | void
+) => ProgressToken;
+
 // ============================================================================
 // WASM Class Constructors
 // ============================================================================
@@ -77,7 +128,7 @@ export interface WasmCropRect {
 
 /**
  * Core WASM module API
- * Provides basic image manipulation functions
+ * Provides basic image manipulation functions; failures throw a PhotoEditorError
  */
 export interface CoreWasmApi {
   /**
@@ -144,6 +195,7 @@ export interface CoreWasmApi {
    * @param newHeight - Target height in pixels
    * @param quality - Resize quality (0=fast/bilinear, 1=high/bicubic)
    * @param output - Output buffer (must be pre-allocated)
+   * @param progress - Optional progress/cancellation token (consumed)
    * @returns Number of bytes written to output
    */
   resize_image: (
@@ -153,8 +205,11 @@ export interface CoreWasmApi {
     newWidth: number,
     newHeight: number,
     quality: ResizeQuality,
-    output: Uint8Array
+    output: Uint8Array,
+    progress?: ProgressToken
   ) => Promise<number>;
+
+  ProgressToken: ProgressTokenConstructor;
 }
 
 // ============================================================================
@@ -162,12 +217,69 @@ export interface CoreWasmApi {
 // Functions: JPEG compression, WebP compression, target size optimization
 // ============================================================================
 
+/**
+ * How compress_to_size reached its target (mirrors the Rust enum)
+ */
+export enum SizeStrategy {
+  Quality = 0,  // Encoder quality search (JPEG, WebP, AVIF)
+  Lossless = 1, // Lossless PNG already fit
+  Palette = 2,  // Quantized PNG, palette size search
+}
+
+/**
+ * Compress module log verbosity (mirrors the Rust enum)
+ */
+export enum LogLevel {
+  Off = 0,   // Nothing
+  Error = 1, // Encoder failures (default)
+  Info = 2,  // One line per exported call
+  Debug = 3, // Encoder steps and every search attempt
+}
+
+/**
+ * Result of compress_to_size, compress_to_size_with_downscale and
+ * compress_to_quality_target
+ */
+export interface CompressToSizeResult {
+  data: Uint8Array;       // Encoded file (a fresh copy on every read)
+  size: number;
+  quality: number;        // Encoder quality, or PNG compression level
+  strategy: SizeStrategy;
+  colors: number;         // Palette size for SizeStrategy.Palette, otherwise 0
+  width: number;          // Encoded dimensions (smaller if downscaled)
+  height: number;
+  ssim: number;           // SSIM reached by compress_to_quality_target, otherwise 0
+}
+
+/**
+ * Image quality metrics from compare_images / compare_encoded
+ */
+export interface QualityMetrics {
+  psnr: number;        // dB over RGB, Infinity for identical images
+  ssim: number;        // 0-1, 1 = identical
+  ms_ssim: number;     // 0-1, multi-scale SSIM
+  heatmap: Uint8Array; // RGBA difference map, empty unless requested
+}
+
+/**
+ * JPEG chroma subsampling (mirrors the Rust enum)
+ */
+export enum ChromaSubsampling {
+  Yuv444 = 0, // Full color resolution
+  Yuv422 = 1, // Half horizontal color resolution
+  Yuv420 = 2, // Half color resolution in both directions
+}
+
 /**
  * JPEG advanced compression parameters
  */
 export interface JpegAdvancedParams {
-  optimize: boolean;     // Optimize Huffman tables
+  optimize: boolean;     // Optimize Huffman tables (ignored with restart markers)
   progressive: boolean;  // Progressive JPEG encoding
+  chroma_subsampling?: ChromaSubsampling; // Defaults to 4:2:0
+  restart_interval?: number;  // MCUs between restart markers, 0 disables them
+  luma_quant_table?: Uint16Array;   // 64 values (1-255), row-major; overrides quality
+  chroma_quant_table?: Uint16Array; // 64 values (1-255), row-major; overrides quality
 }
 
 /**
@@ -178,12 +290,32 @@ export interface WebPAdvancedParams {
   filter_strength: number; // 0-100, filter strength
   filter_sharpness: number;// 0-100, filter sharpness
   sns_strength: number;    // 0-100, spatial noise shielding
+  lossless?: boolean;      // VP8L lossless encoding (quality is ignored)
+}
+
+/**
+ * AVIF advanced compression parameters
+ */
+export interface AvifAdvancedParams {
+  speed: number;         // 1-10, encoder speed (1=slowest best, 10=fastest)
+  alpha_quality: number; // 1-100, alpha plane quality
+}
+
+/**
+ * PNG advanced compression parameters
+ */
+export interface PngAdvancedParams {
+  quantize: boolean;  // Lossy palette quantization to an indexed PNG
+  max_colors: number; // 2-256, palette size limit when quantizing
+  dither: boolean;    // Floyd-Steinberg dithering when quantizing
+  optimize?: boolean; // Lossless optimization pass (slower, smaller output)
 }
 
 /**
  * Compress WASM module API
  * Provides high-quality image compression functions
- * Note: These are synchronous functions (not async/Promise)
+ * Note: These are synchronous functions (not async/Promise); failures throw
+ * a PhotoEditorError
  */
 export interface CompressWasmApi {
   /**
@@ -192,16 +324,14 @@ export interface CompressWasmApi {
    * @param width - Image width in pixels
    * @param height - Image height in pixels
    * @param quality - JPEG quality (1-100)
-   * @param output - Output buffer for JPEG data
-   * @returns Number of bytes written to output
+   * @returns Encoded file
    */
   compress_jpeg: (
     input: Uint8Array,
     width: number,
     height: number,
-    quality: number,
-    output: Uint8Array
-  ) => number;
+    quality: number
+  ) => Uint8Array;
 
   /**
    * Compress image as JPEG with advanced parameters
@@ -210,17 +340,21 @@ export interface CompressWasmApi {
    * @param height - Image height in pixels
    * @param quality - JPEG quality (1-100)
    * @param params - Advanced parameters
-   * @param output - Output buffer for JPEG data
-   * @returns Number of bytes written to output
+   * @returns Encoded file
    */
   compress_jpeg_advanced: (
     input: Uint8Array,
     width: number,
     height: number,
     quality: number,
-    params: JpegAdvancedParams,
-    output: Uint8Array
-  ) => number;
+    params: JpegAdvancedParams
+  ) => Uint8Array;
+
+  /**
+   * Constructor for the params object expected by compress_jpeg_advanced
+   * (starts from the defaults: optimized, baseline, 4:2:0, no restart markers)
+   */
+  JpegAdvancedParams: new () => JpegAdvancedParams & { free(): void };
 
   /**
    * Compress image as WebP
@@ -228,16 +362,14 @@ export interface CompressWasmApi {
    * @param width - Image width in pixels
    * @param height - Image height in pixels
    * @param quality - WebP quality (1-100)
-   * @param output - Output buffer for WebP data
-   * @returns Number of bytes written to output
+   * @returns Encoded file
    */
   compress_webp: (
     input: Uint8Array,
     width: number,
     height: number,
-    quality: number,
-    output: Uint8Array
-  ) => number;
+    quality: number
+  ) => Uint8Array;
 
   /**
    * Compress image as WebP with advanced parameters
@@ -246,17 +378,59 @@ export interface CompressWasmApi {
    * @param height - Image height in pixels
    * @param quality - WebP quality (1-100)
    * @param params - Advanced parameters
-   * @param output - Output buffer for WebP data
-   * @returns Number of bytes written to output
+   * @returns Encoded file
    */
   compress_webp_advanced: (
     input: Uint8Array,
     width: number,
     height: number,
     quality: number,
-    params: WebPAdvancedParams,
-    output: Uint8Array
-  ) => number;
+    params: WebPAdvancedParams
+  ) => Uint8Array;
+
+  /**
+   * Constructor for the params object expected by compress_webp_advanced
+   * (starts from the defaults: method 4, filter 60, sharpness 0, sns 50)
+   */
+  WebPAdvancedParams: new () => WebPAdvancedParams & { free(): void };
+
+  /**
+   * Compress image as AVIF
+   * @param input - Input image data (RGBA format)
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param quality - AVIF quality (1-100)
+   * @returns Encoded file
+   */
+  compress_avif: (
+    input: Uint8Array,
+    width: number,
+    height: number,
+    quality: number
+  ) => Uint8Array;
+
+  /**
+   * Compress image as AVIF with advanced parameters
+   * @param input - Input image data (RGBA format)
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param quality - AVIF quality (1-100)
+   * @param params - Advanced parameters (construct with `new AvifAdvancedParams()`)
+   * @returns Encoded file
+   */
+  compress_avif_advanced: (
+    input: Uint8Array,
+    width: number,
+    height: number,
+    quality: number,
+    params: AvifAdvancedParams
+  ) => Uint8Array;
+
+  /**
+   * Constructor for the params object expected by compress_avif_advanced
+   * (starts from the defaults: speed 6, alpha quality 80)
+   */
+  AvifAdvancedParams: new () => AvifAdvancedParams & { free(): void };
 
   /**
    * Compress image as PNG
@@ -264,16 +438,45 @@ export interface CompressWasmApi {
    * @param width - Image width in pixels
    * @param height - Image height in pixels
    * @param quality - PNG compression level (1-100, maps to Fast/Default/Best)
-   * @param output - Output buffer for PNG data
-   * @returns Number of bytes written to output
+   * @returns Encoded file
    */
   compress_png: (
+    input: Uint8Array,
+    width: number,
+    height: number,
+    quality: number
+  ) => Uint8Array;
+
+  /**
+   * Compress image as PNG with advanced parameters
+   * @param input - Input image data (RGBA format)
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param quality - PNG compression level (1-100, maps to Fast/Default/Best)
+   * @param params - Advanced parameters (construct with `new PngAdvancedParams()`)
+   * @returns Encoded file
+   */
+  compress_png_advanced: (
     input: Uint8Array,
     width: number,
     height: number,
     quality: number,
-    output: Uint8Array
-  ) => number;
+    params: PngAdvancedParams
+  ) => Uint8Array;
+
+  /**
+   * Constructor for the params object expected by compress_png_advanced
+   * (starts from the defaults: lossless, 256 colors, dithering on)
+   */
+  PngAdvancedParams: new () => PngAdvancedParams & { free(): void };
+
+  /**
+   * Losslessly shrink an existing PNG (color type/bit depth reduction,
+   * filter search, zopfli recompression, non-metadata chunks stripped)
+   * @param bytes - Complete PNG file
+   * @returns Optimized PNG (the input if it can't be made smaller)
+   */
+  optimize_png: (bytes: Uint8Array) => Uint8Array;
 
   /**
    * Compress image to target file size using binary search
@@ -281,9 +484,10 @@ export interface CompressWasmApi {
    * @param width - Image width in pixels
    * @param height - Image height in pixels
    * @param targetSize - Target file size in bytes
-   * @param format - Compression format enum (Jpeg=0, WebP=1, Png=2)
-   * @param output - Output buffer for compressed data
-   * @returns Object with actual size and quality used
+   * @param format - Compression format enum (Jpeg=0, WebP=1, Png=2, Avif=3)
+   * @param progress - Optional progress/cancellation token (consumed)
+   * @returns Encoded file with its size, quality and strategy used
+   *   (PNG searches the palette size, reported in `colors`)
    */
   compress_to_size: (
     input: Uint8Array,
@@ -291,8 +495,94 @@ export interface CompressWasmApi {
     height: number,
     targetSize: number,
     format: number,
-    output: Uint8Array
-  ) => { size: number; quality: number };
+    progress?: ProgressToken
+  ) => CompressToSizeResult;
+
+  /**
+   * Compress image to target file size, downscaling once quality reaches a floor
+   * @param input - Input image data (RGBA format)
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param targetSize - Target file size in bytes
+   * @param format - Compression format enum (Jpeg=0, WebP=1, Png=2, Avif=3)
+   * @param minQuality - Quality floor (1-100; for PNG, percent of 256 palette colors)
+   * @param progress - Optional progress/cancellation token (consumed)
+   * @returns Result with the encoded (possibly smaller) width and height
+   */
+  compress_to_size_with_downscale: (
+    input: Uint8Array,
+    width: number,
+    height: number,
+    targetSize: number,
+    format: number,
+    minQuality: number,
+    progress?: ProgressToken
+  ) => CompressToSizeResult;
+
+  /**
+   * Compress to the smallest size whose decoded result keeps a minimum SSIM
+   * @param input - Input image data (RGBA format)
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param format - Compression format enum (Jpeg=0, WebP=1, Png=2; Avif unsupported)
+   * @param minSsim - Required structural similarity (0-1, e.g. 0.95)
+   * @returns Result with size, quality and the SSIM reached
+   */
+  compress_to_quality_target: (
+    input: Uint8Array,
+    width: number,
+    height: number,
+    format: number,
+    minSsim: number
+  ) => CompressToSizeResult;
+
+  /**
+   * Measure PSNR, SSIM and MS-SSIM between two RGBA images
+   * @param reference - Original RGBA data
+   * @param distorted - RGBA data to score, same dimensions
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param heatmap - Also return a per-pixel difference heatmap
+   * @returns Metrics
+   */
+  compare_images: (
+    reference: Uint8Array,
+    distorted: Uint8Array,
+    width: number,
+    height: number,
+    heatmap: boolean
+  ) => QualityMetrics;
+
+  /**
+   * Measure the quality lost by compression (decodes the file first)
+   * @param reference - Original RGBA data
+   * @param encoded - JPEG, PNG or WebP produced from it
+   * @param width - Image width in pixels
+   * @param height - Image height in pixels
+   * @param heatmap - Also return a per-pixel difference heatmap
+   * @returns Metrics
+   */
+  compare_encoded: (
+    reference: Uint8Array,
+    encoded: Uint8Array,
+    width: number,
+    height: number,
+    heatmap: boolean
+  ) => QualityMetrics;
+
+  /**
+   * Set how much the module logs to the console
+   * @param level - LogLevel value
+   */
+  set_log_level: (level: LogLevel) => void;
+
+  /**
+   * Log how long each compress call took (at LogLevel.Info)
+   * @param enabled - Whether timings are logged
+   */
+  set_log_timings: (enabled: boolean) => void;
+
+  ProgressToken: ProgressTokenConstructor;
 }
 
 // ============================================================================
@@ -302,7 +592,7 @@ export interface CompressWasmApi {
 
 /**
  * BgRemove WASM module API
- * Provides background removal algorithms
+ * Provides background removal algorithms; failures throw a PhotoEditorError
  */
 export interface BgRemoveWasmApi {
   /**
@@ -360,6 +650,7 @@ export interface BgRemoveWasmApi {
    * @param rectHeight - Initial bounding rectangle height
    * @param iterations - Number of iterations (default: 5)
    * @param maskOutput - Output mask buffer (1 byte per pixel)
+   * @param progress - Optional progress/cancellation token (consumed)
    * @returns Number of bytes written to mask output
    */
   grabcut_segment: (
@@ -371,8 +662,11 @@ export interface BgRemoveWasmApi {
     rectWidth: number,
     rectHeight: number,
     iterations: number,
-    maskOutput: Uint8Array
+    maskOutput: Uint8Array,
+    progress?: ProgressToken
   ) => Promise<number>;
+
+  ProgressToken: ProgressTokenConstructor;
 }
 
 // ============================================================================
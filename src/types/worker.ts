@@ -4,7 +4,7 @@
 
 import type { CropRect, RotateAngle, FlipDirection, ResizeQuality } from './wasm';
 import type { CompressionFormat } from './image';
-import type { JpegAdvancedParams, WebPAdvancedParams } from './wasm';
+import type { JpegAdvancedParams, WebPAdvancedParams, SizeStrategy } from './wasm';
 
 // ============================================================================
 // Message Type Definitions
@@ -35,6 +35,7 @@ export enum MessageType {
   INIT_WORKER = 'INIT_WORKER',
   TERMINATE_WORKER = 'TERMINATE_WORKER',
   HEALTH_CHECK = 'HEALTH_CHECK',
+  PROGRESS = 'PROGRESS', // Sent with the id of a running operation, before its result
 }
 
 // ============================================================================
@@ -107,6 +108,7 @@ export interface ResizeImagePayload {
   newWidth: number;
   newHeight: number;
   quality: ResizeQuality;
+  cancelFlag?: Int32Array; // Over a SharedArrayBuffer; store non-zero in [0] to cancel
 }
 
 /**
@@ -140,6 +142,8 @@ export interface CompressToSizePayload {
   height: number;
   targetSize: number;
   format: CompressionFormat;
+  minQuality?: number; // Downscale instead of going below this quality
+  cancelFlag?: Int32Array; // Over a SharedArrayBuffer; store non-zero in [0] to cancel
 }
 
 /**
@@ -179,6 +183,7 @@ export interface GrabCutSegmentPayload {
   rectWidth: number;
   rectHeight: number;
   iterations: number;
+  cancelFlag?: Int32Array; // Over a SharedArrayBuffer; store non-zero in [0] to cancel
 }
 
 /**
@@ -192,6 +197,18 @@ export interface InitWorkerPayload {
 /**
  * Worker response data types
  */
+export interface ProgressData {
+  progress: number; // Fraction done, 0-1
+}
+
+/**
+ * Progress and cancellation for long-running operations
+ */
+export interface ProgressOptions {
+  onProgress?: (progress: number) => void; // Fraction done, 0-1
+  cancelFlag?: Int32Array; // Over a SharedArrayBuffer; store non-zero in [0] to cancel
+}
+
 export interface CropImageData {
   imageData: Uint8Array;
   width: number;
@@ -203,6 +220,10 @@ export interface CompressedImageData {
   size: number;
   format: string;
   quality?: number;
+  strategy?: SizeStrategy; // Set by compress-to-size
+  colors?: number;         // Palette size when strategy is SizeStrategy.Palette
+  width?: number;          // Encoded dimensions, set by compress-to-size
+  height?: number;
 }
 
 export interface MaskData {
@@ -4,7 +4,7 @@
  * Simulates WASM operations with JavaScript implementations
  */
 
-import { CoreWasmApi, CropRect, RotateAngle, FlipDirection, ResizeQuality, WasmCropRect } from '../types';
+import { CoreWasmApi, CropRect, RotateAngle, FlipDirection, ResizeQuality, WasmCropRect, ProgressToken } from '../types';
 
 /**
  * Mock CropRect class that mimics the WASM CropRect
@@ -27,6 +27,22 @@ class MockCropRect implements WasmCropRect {
   }
 }
 
+/**
+ * Mock ProgressToken; the JavaScript implementations run in one step, so
+ * they never report progress or check for cancellation
+ */
+class MockProgressToken implements ProgressToken {
+  constructor(_callback?: (fraction: number) => boolean | void) {}
+
+  set_cancel_flag(_flag: Int32Array): void {
+    // No-op for mock implementation
+  }
+
+  free(): void {
+    // No-op for mock implementation
+  }
+}
+
 /**
  * Create mock CoreWasmApi
  * Simulates image operations with JavaScript implementations
@@ -38,6 +54,7 @@ export function createMockCoreWasm(): CoreWasmApi {
     rotate_image: mockRotateImage,
     flip_image: mockFlipImage,
     resize_image: mockResizeImage,
+    ProgressToken: MockProgressToken,
   };
 }
 
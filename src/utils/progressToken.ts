@@ -0,0 +1,36 @@
+/**
+ * Progress Token
+ * Creates the WASM modules' ProgressToken for a worker operation
+ */
+
+import { MessageType } from '../types';
+import type { ProgressToken, ProgressTokenConstructor, WorkerMessage, WorkerResponse } from '../types';
+
+/**
+ * Progress token that reports under the id of the message being handled;
+ * a non-zero cancelFlag[0] stops the operation with ErrorCode.Cancelled
+ *
+ * @param Token - The loaded module's ProgressToken class
+ * @param send - The worker's sendMessage
+ */
+export function createProgressToken(
+  Token: ProgressTokenConstructor,
+  message: WorkerMessage,
+  startTime: number,
+  send: (response: WorkerResponse) => void,
+  cancelFlag?: Int32Array
+): ProgressToken {
+  const token = new Token((fraction: number) => {
+    send({
+      id: message.id,
+      type: MessageType.PROGRESS,
+      success: true,
+      data: { progress: fraction },
+      processingTime: performance.now() - startTime,
+    });
+  });
+  if (cancelFlag) {
+    token.set_cancel_flag(cancelFlag);
+  }
+  return token;
+}
@@ -4,7 +4,7 @@
  */
 
 import type { WorkerResponse } from '../types';
-import { WasmModuleType } from '../types';
+import { ErrorCode, WasmModuleType, isPhotoEditorError } from '../types';
 
 // ============================================================================
 // Error Context Types
@@ -93,6 +93,20 @@ export class WasmErrorHandler {
   ): string {
     const errorMessage = this.extractErrorMessage(error);
 
+    // Errors thrown by the WASM modules carry a code
+    if (isPhotoEditorError(error)) {
+      switch (error.code) {
+        case ErrorCode.DecodeFailed:
+          return `The image file appears to be invalid or corrupted. Please try another image.`;
+        case ErrorCode.InvalidDimensions:
+          return `The image dimensions are not supported. Try resizing the image first.`;
+        case ErrorCode.Unsupported:
+          return `This operation is not supported for this image: ${errorMessage}.`;
+        case ErrorCode.Cancelled:
+          return `The operation was cancelled.`;
+      }
+    }
+
     // Out of memory errors
     if (errorMessage.includes('out of memory') ||
         errorMessage.includes('memory') ||
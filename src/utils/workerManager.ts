@@ -110,6 +110,12 @@ export class WorkerManager {
    */
   private handleMessage(event: MessageEvent<WorkerResponse>): void {
     const response = event.data;
+
+    // Progress updates precede the result; they don't settle the request
+    if (response.type === MessageType.PROGRESS) {
+      return;
+    }
+
     const pending = this.workerInstance?.pendingMessages.get(response.id);
 
     if (pending) {
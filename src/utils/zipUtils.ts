@@ -26,6 +26,8 @@ export function getMimeTypeFromFileName(fileName: string): string {
       return 'image/png';
     case 'webp':
       return 'image/webp';
+    case 'avif':
+      return 'image/avif';
     case 'gif':
       return 'image/gif';
     default:
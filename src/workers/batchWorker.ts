@@ -4,7 +4,7 @@
  */
 
 import { expose } from 'comlink';
-import type { BatchParams, BatchItemStatus } from '../types/batch';
+import type { BatchParams, BatchItemStatus, RedactionSpec } from '../types/batch';
 
 /**
  * Batch task item for queue processing
@@ -178,6 +178,16 @@ async function processImage(
         break;
       }
 
+      case 'redact': {
+        // Extract redaction spec with validation
+        const { redaction } = batchParams.params;
+        if (!redaction) {
+          throw new Error('Redact operation requires redaction parameter');
+        }
+        result = await processRedact(imageData, width, height, redaction);
+        break;
+      }
+
       default:
         throw new Error(`Unknown operation: ${batchParams.operation}`);
     }
@@ -378,6 +388,11 @@ async function processBgRemove(_imageData: Uint8Array, _width: number, _height:
   throw new Error('Not implemented - will integrate with bgremove worker');
 }
 
+async function processRedact(_imageData: Uint8Array, _width: number, _height: number, _spec: RedactionSpec): Promise<ArrayBuffer> {
+  // TODO: Call core worker (redact_regions with JSON.stringify(spec))
+  throw new Error('Not implemented - will integrate with core worker');
+}
+
 // Expose functions using Comlink
 const workerApi = {
   init,
@@ -14,6 +14,7 @@ import {
   GrabCutSegmentPayload,
 } from '../types';
 import type { BgRemoveWasmApi } from '../types';
+import { createProgressToken } from '../utils/progressToken';
 
 // Worker startup log
 console.log('👋 [BgRemoveWorker] Worker script loaded');
@@ -257,7 +258,8 @@ async function handleGrabCutSegment(message: WorkerMessage<GrabCutSegmentPayload
       rectY,
       rectWidth,
       rectHeight,
-      iterations
+      iterations,
+      cancelFlag
     } = message.payload;
 
     // Validate inputs
@@ -308,7 +310,8 @@ async function handleGrabCutSegment(message: WorkerMessage<GrabCutSegmentPayload
       rectWidth,
       rectHeight,
       iterations ?? 5, // Default: 5 iterations
-      maskOutput
+      maskOutput,
+      createProgressToken(wasmModule.ProgressToken, message, startTime, sendMessage, cancelFlag)
     );
 
     const processingTime = performance.now() - startTime;
@@ -12,17 +12,21 @@ import {
   CompressJpegPayload,
   CompressWebpPayload,
   CompressToSizePayload,
+  ErrorCode,
+  isPhotoEditorError,
 } from '../types';
-import type { CompressWasmApi } from '../types';
+import type { CompressWasmApi, PngAdvancedParams } from '../types';
+import { createProgressToken } from '../utils/progressToken';
 
 /**
- * PNG compression payload (same structure as JPEG without advanced params)
+ * PNG compression payload (same structure as JPEG, with PNG advanced params)
  */
 interface CompressPngPayload {
   imageData: Uint8Array;
   width: number;
   height: number;
   quality: number;
+  advancedParams?: PngAdvancedParams;
 }
 
 /**
@@ -157,37 +161,38 @@ async function handleCompressJpeg(message: WorkerMessage<CompressJpegPayload>):
     // Convert ImageData to Uint8Array (RGBA format)
     const input = new Uint8Array(imageData);
 
-    // Allocate output buffer (start with same size)
-    const output = new Uint8Array(input.length);
-
     // Call WASM function (synchronous)
-    let compressedSize: number;
+    let compressedData: Uint8Array;
 
     // Check if advanced parameters are provided
     if (advancedParams && wasmModule.compress_jpeg_advanced) {
       console.log('📊 [CompressWorker] Using advanced JPEG compression with params:', advancedParams);
 
-      compressedSize = wasmModule.compress_jpeg_advanced(
-        input,
-        width,
-        height,
-        quality,
-        advancedParams,
-        output
-      );
+      // wasm-bindgen structs must be constructed on the WASM side
+      const params = new wasmModule.JpegAdvancedParams();
+      Object.assign(params, advancedParams);
+      try {
+        compressedData = wasmModule.compress_jpeg_advanced(
+          input,
+          width,
+          height,
+          quality,
+          params
+        );
+      } finally {
+        params.free();
+      }
     } else {
       // Use basic compression
-      compressedSize = wasmModule.compress_jpeg(
+      compressedData = wasmModule.compress_jpeg(
         input,
         width,
         height,
-        quality,
-        output
+        quality
       );
     }
 
-    // Trim output buffer to actual compressed size
-    const compressedData = output.slice(0, compressedSize);
+    const compressedSize = compressedData.length;
 
     const processingTime = performance.now() - startTime;
 
@@ -254,46 +259,56 @@ async function handleCompressWebp(message: WorkerMessage<CompressWebpPayload>):
       expectedPixels: width * height * 4
     });
 
-    // Allocate output buffer
-    const output = new Uint8Array(input.length);
-
     console.log('📦 [CompressWorker] Calling WASM compress_webp...');
 
     // Call WASM function (synchronous)
-    let compressedSize: number;
+    let compressedData: Uint8Array;
 
-    // Check if advanced parameters are provided
-    if (advancedParams && wasmModule.compress_webp_advanced) {
-      console.log('📊 [CompressWorker] Using advanced WebP compression with params:', advancedParams);
-
-      compressedSize = wasmModule.compress_webp_advanced(
-        input,
-        width,
-        height,
-        quality,
-        advancedParams,
-        output
-      );
-    } else {
-      // Use basic compression
-      compressedSize = wasmModule.compress_webp(
-        input,
-        width,
-        height,
-        quality,
-        output
-      );
+    // Only encoder failures fall back to canvas; bad input is reported
+    try {
+      // Check if advanced parameters are provided
+      if (advancedParams && wasmModule.compress_webp_advanced) {
+        console.log('📊 [CompressWorker] Using advanced WebP compression with params:', advancedParams);
+
+        // wasm-bindgen structs must be constructed on the WASM side
+        const params = new wasmModule.WebPAdvancedParams();
+        Object.assign(params, advancedParams);
+        try {
+          compressedData = wasmModule.compress_webp_advanced(
+            input,
+            width,
+            height,
+            quality,
+            params
+          );
+        } finally {
+          params.free();
+        }
+      } else {
+        // Use basic compression
+        compressedData = wasmModule.compress_webp(
+          input,
+          width,
+          height,
+          quality
+        );
+      }
+    } catch (error) {
+      if (!isPhotoEditorError(error) || error.code !== ErrorCode.EncodeFailed) {
+        throw error;
+      }
+      console.warn('⚠️ [CompressWorker] WASM encoding failed:', error.message);
+      compressedData = new Uint8Array(0);
     }
 
     console.log('📊 [CompressWorker] WASM returned:', {
-      compressedSize,
-      type: typeof compressedSize,
-      outputFirst10: Array.from(output.slice(0, 10))
+      compressedSize: compressedData.length,
+      outputFirst10: Array.from(compressedData.slice(0, 10))
     });
 
-    // Check if WASM compression failed (returned 0)
-    if (compressedSize === 0) {
-      console.warn('⚠️ [CompressWorker] WASM compression returned 0, using Canvas fallback');
+    // Check if WASM encoding failed
+    if (compressedData.length === 0) {
+      console.warn('⚠️ [CompressWorker] Using Canvas fallback');
 
       // Fallback: Use OffscreenCanvas to compress the image
       try {
@@ -333,8 +348,7 @@ async function handleCompressWebp(message: WorkerMessage<CompressWebpPayload>):
       }
     }
 
-    // Trim output buffer to actual compressed size
-    const compressedData = output.slice(0, compressedSize);
+    const compressedSize = compressedData.length;
 
     const processingTime = performance.now() - startTime;
 
@@ -378,7 +392,7 @@ async function handleCompressPng(message: WorkerMessage<CompressPngPayload>): Pr
       throw new Error('WASM module not initialized. Call INIT_WORKER first.');
     }
 
-    const { imageData, width, height, quality } = message.payload;
+    const { imageData, width, height, quality, advancedParams } = message.payload;
 
     // Validate inputs
     if (!imageData || !width || !height || quality === undefined) {
@@ -401,29 +415,54 @@ async function handleCompressPng(message: WorkerMessage<CompressPngPayload>): Pr
       expectedPixels: width * height * 4
     });
 
-    // Allocate output buffer
-    const output = new Uint8Array(input.length);
-
     console.log('📦 [CompressWorker] Calling WASM compress_png...');
 
     // Call WASM function (synchronous)
-    const compressedSize = wasmModule.compress_png(
-      input,
-      width,
-      height,
-      quality,
-      output
-    );
+    let compressedData: Uint8Array;
+
+    // Only encoder failures fall back to canvas; bad input is reported
+    try {
+      if (advancedParams && wasmModule.compress_png_advanced) {
+        console.log('📊 [CompressWorker] Using advanced PNG compression with params:', advancedParams);
+
+        // wasm-bindgen structs must be constructed on the WASM side
+        const params = new wasmModule.PngAdvancedParams();
+        Object.assign(params, advancedParams);
+        try {
+          compressedData = wasmModule.compress_png_advanced(
+            input,
+            width,
+            height,
+            quality,
+            params
+          );
+        } finally {
+          params.free();
+        }
+      } else {
+        compressedData = wasmModule.compress_png(
+          input,
+          width,
+          height,
+          quality
+        );
+      }
+    } catch (error) {
+      if (!isPhotoEditorError(error) || error.code !== ErrorCode.EncodeFailed) {
+        throw error;
+      }
+      console.warn('⚠️ [CompressWorker] WASM encoding failed:', error.message);
+      compressedData = new Uint8Array(0);
+    }
 
     console.log('📊 [CompressWorker] WASM returned:', {
-      compressedSize,
-      type: typeof compressedSize,
-      outputFirst10: Array.from(output.slice(0, 10))
+      compressedSize: compressedData.length,
+      outputFirst10: Array.from(compressedData.slice(0, 10))
     });
 
-    // Check if WASM compression failed (returned 0)
-    if (compressedSize === 0) {
-      console.warn('⚠️ [CompressWorker] WASM compression returned 0, using Canvas fallback');
+    // Check if WASM encoding failed
+    if (compressedData.length === 0) {
+      console.warn('⚠️ [CompressWorker] Using Canvas fallback');
 
       // Fallback: Use OffscreenCanvas to compress the image
       try {
@@ -463,8 +502,7 @@ async function handleCompressPng(message: WorkerMessage<CompressPngPayload>): Pr
       }
     }
 
-    // Trim output buffer to actual compressed size
-    const compressedData = output.slice(0, compressedSize);
+    const compressedSize = compressedData.length;
 
     const processingTime = performance.now() - startTime;
 
@@ -509,7 +547,7 @@ async function handleCompressToSize(message: WorkerMessage<CompressToSizePayload
       throw new Error('WASM module not initialized. Call INIT_WORKER first.');
     }
 
-    const { imageData, width, height, targetSize, format } = message.payload;
+    const { imageData, width, height, targetSize, format, minQuality, cancelFlag } = message.payload;
 
     // Validate inputs
     if (!imageData || !width || !height || !targetSize || !format) {
@@ -524,38 +562,54 @@ async function handleCompressToSize(message: WorkerMessage<CompressToSizePayload
     // Convert ImageData to Uint8Array
     const input = new Uint8Array(imageData);
 
-    // Allocate output buffer
-    const output = new Uint8Array(input.length);
-
     // Map format string to WASM enum
     const formatMap: Record<string, any> = {
       jpeg: 0, // CompressionFormat::Jpeg
       webp: 1, // CompressionFormat::WebP
       png: 2,  // CompressionFormat::Png
+      avif: 3, // CompressionFormat::Avif
     };
 
     const wasmFormat = formatMap[format] || 0;
 
-    // Call WASM compress_to_size function (synchronous)
-    const result = wasmModule.compress_to_size(
-      input,
-      width,
-      height,
-      targetSize,
-      wasmFormat,
-      output
-    );
+    // Reported per encoding attempt
+    const progress = createProgressToken(wasmModule.ProgressToken, message, startTime, sendMessage, cancelFlag);
+
+    // Call WASM compress_to_size function (synchronous); with a quality
+    // floor, resolution is reduced instead of going below it
+    const result = minQuality
+      ? wasmModule.compress_to_size_with_downscale(
+          input,
+          width,
+          height,
+          targetSize,
+          wasmFormat,
+          minQuality,
+          progress
+        )
+      : wasmModule.compress_to_size(
+          input,
+          width,
+          height,
+          targetSize,
+          wasmFormat,
+          progress
+        );
 
     // Extract result from WASM
     const size = result.size;
     const quality = result.quality;
+    const strategy = result.strategy;
+    const colors = result.colors;
+    const encodedWidth = result.width;
+    const encodedHeight = result.height;
 
-    // Trim output buffer to actual compressed size
-    const compressedData = output.slice(0, size);
+    const compressedData = result.data;
 
     // Determine MIME type
     const mimeType = format === 'jpeg' ? 'image/jpeg' :
-                    format === 'png' ? 'image/png' : 'image/webp';
+                    format === 'png' ? 'image/png' :
+                    format === 'avif' ? 'image/avif' : 'image/webp';
 
     const processingTime = performance.now() - startTime;
 
@@ -570,6 +624,10 @@ async function handleCompressToSize(message: WorkerMessage<CompressToSizePayload
         size,
         format: mimeType,
         quality,
+        strategy,
+        colors,
+        width: encodedWidth,
+        height: encodedHeight,
       },
       processingTime,
     });
@@ -15,6 +15,7 @@ import {
   ResizeImagePayload,
 } from '../types';
 import type { CoreWasmApi } from '../types';
+import { createProgressToken } from '../utils/progressToken';
 
 // Worker state
 let wasmModule: CoreWasmApi | null = null;
@@ -334,7 +335,7 @@ async function handleResizeImage(message: WorkerMessage<ResizeImagePayload>): Pr
   const startTime = performance.now();
 
   try {
-    const { imageData, width, height, newWidth, newHeight, quality } = message.payload;
+    const { imageData, width, height, newWidth, newHeight, quality, cancelFlag } = message.payload;
 
     // Validate inputs
     if (!imageData || !width || !height || !newWidth || !newHeight || !quality) {
@@ -360,7 +361,8 @@ async function handleResizeImage(message: WorkerMessage<ResizeImagePayload>): Pr
       newWidth,
       newHeight,
       quality,
-      output
+      output,
+      createProgressToken(wasmModule.ProgressToken, message, startTime, sendMessage, cancelFlag)
     );
 
     const processingTime = performance.now() - startTime;
@@ -1,4 +1,4 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::Error;
 
 pub fn remove_color(
     input: &[u8],
@@ -8,14 +8,14 @@ pub fn remove_color(
     tolerance: u8,
     feather: u8,
     output: &mut [u8],
-) -> Result<usize, JsValue> {
+) -> Result<usize, Error> {
     if input.is_empty() || width == 0 || height == 0 {
-        return Err(JsValue::from_str("Invalid input dimensions"));
+        return Err(Error::invalid_dimensions("Invalid input dimensions"));
     }
 
     let expected_len = (width * height * 4) as usize;
     if input.len() != expected_len || output.len() < expected_len {
-        return Err(JsValue::from_str("Buffer length mismatch"));
+        return Err(Error::input_size_mismatch("Buffer length mismatch"));
     }
 
     // Copy input to output first
@@ -30,9 +30,9 @@ pub fn remove_color(
             let b = input[idx + 2];
 
             // Calculate color distance
-            let dr = (r as i16 - target_color[0] as i16).abs();
-            let dg = (g as i16 - target_color[1] as i16).abs();
-            let db = (b as i16 - target_color[2] as i16).abs();
+            let dr = (r as i32 - target_color[0] as i32).abs();
+            let dg = (g as i32 - target_color[1] as i32).abs();
+            let db = (b as i32 - target_color[2] as i32).abs();
 
             // Check if within tolerance (using Euclidean distance approximation)
             let distance = ((dr * dr + dg * dg + db * db) as f32).sqrt();
@@ -51,6 +51,7 @@ pub fn remove_color(
     Ok(expected_len)
 }
 
+#[allow(clippy::unnecessary_min_or_max)]
 fn apply_feathering(data: &mut [u8], width: u32, height: u32, feather: u8) {
     let feather = feather.min(50) as u32; // Cap feather radius
 
@@ -1,4 +1,4 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::{Error, Progress};
 use std::f32;
 
 const GMM_COMPONENTS: usize = 5;
@@ -29,6 +29,7 @@ impl GMMComponent {
 }
 
 /// Gaussian Mixture Model for foreground/background
+#[allow(clippy::upper_case_acronyms)]
 struct GMM {
     components: [GMMComponent; GMM_COMPONENTS],
 }
@@ -72,6 +73,7 @@ impl GMM {
     }
 
     /// Learn GMM parameters from color samples using k-means initialization
+    #[allow(unused_variables, unused_mut, clippy::needless_range_loop)]
     fn learn(&mut self, samples: &[[u8; 3]], assign: &mut [usize]) {
         let n = samples.len();
         if n == 0 {
@@ -130,6 +132,7 @@ impl GMM {
     }
 
     /// K-means++ initialization for better clustering
+    #[allow(unused_variables, unused_mut, clippy::needless_range_loop)]
     fn kmeans_init(&mut self, samples: &[[u8; 3]], assign: &mut [usize]) {
         let n = samples.len();
         if n == 0 {
@@ -213,6 +216,7 @@ struct Dinic {
     graph: Vec<Vec<Edge>>,
     level: Vec<i32>,
     iter: Vec<usize>,
+    #[allow(dead_code)]
     n: usize,
 }
 
@@ -283,9 +287,11 @@ impl Dinic {
         0
     }
 
-    fn max_flow(&mut self, s: usize, t: usize) -> i32 {
+    fn max_flow(&mut self, s: usize, t: usize, progress: &mut Progress) -> Result<i32, Error> {
         let mut flow = 0;
         while self.bfs(s, t) {
+            // The number of phases isn't known up front
+            progress.check()?;
             self.iter.iter_mut().for_each(|i| *i = 0);
             loop {
                 let f = self.dfs(s, t, i32::MAX);
@@ -295,11 +301,15 @@ impl Dinic {
                 flow += f;
             }
         }
-        flow
+        Ok(flow)
     }
 }
 
 /// GrabCut segmentation algorithm
+///
+/// Refinement iterations report the first half of `progress`, building the
+/// graph the next 10%; the max-flow phases only check for cancellation.
+#[allow(clippy::too_many_arguments, clippy::manual_clamp, clippy::needless_range_loop)]
 pub fn grabcut_segment(
     input: &[u8],
     width: u32,
@@ -310,15 +320,16 @@ pub fn grabcut_segment(
     rect_height: u32,
     iterations: u8,
     mask_output: &mut [u8],
-) -> Result<usize, JsValue> {
+    progress: &mut Progress,
+) -> Result<usize, Error> {
     // Validation
     if input.is_empty() || width == 0 || height == 0 {
-        return Err(JsValue::from_str("Invalid input dimensions"));
+        return Err(Error::invalid_dimensions("Invalid input dimensions"));
     }
 
     let expected_len = (width * height) as usize;
     if input.len() != expected_len * 4 || mask_output.len() < expected_len {
-        return Err(JsValue::from_str("Buffer length mismatch"));
+        return Err(Error::input_size_mismatch("Buffer length mismatch"));
     }
 
     let pixel_count = (width * height) as usize;
@@ -371,9 +382,10 @@ pub fn grabcut_segment(
     // Iterative refinement
     let max_iter = iterations.min(MAX_ITERATIONS).max(1);
 
-    for _iter in 0..max_iter {
+    for iter in 0..max_iter {
         // Assign GMM components to pixels
         for y in 0..height {
+            progress.report((iter as f64 + y as f64 / height as f64) / max_iter as f64 * 0.5)?;
             for x in 0..width {
                 let idx = (y * width + x) as usize;
                 let pixel_idx = idx * 4;
@@ -438,6 +450,7 @@ pub fn grabcut_segment(
 
     // Add t-links (terminal edges)
     for y in 0..height {
+        progress.report(0.5 + y as f64 / height as f64 * 0.05)?;
         for x in 0..width {
             let idx = (y * width + x) as usize;
             let pixel_idx = idx * 4;
@@ -474,6 +487,7 @@ pub fn grabcut_segment(
     // Add n-links (neighbor edges) for smoothness
     let beta = 0.5; // Smoothness parameter
     for y in 0..height {
+        progress.report(0.55 + y as f64 / height as f64 * 0.05)?;
         for x in 0..width {
             let idx = (y * width + x) as usize;
 
@@ -520,7 +534,7 @@ pub fn grabcut_segment(
     }
 
     // Run maxflow and determine segmentation
-    dinic.max_flow(source, sink);
+    dinic.max_flow(source, sink, progress)?;
 
     // Update mask based on which side of cut pixels fall
     for idx in 0..num_pixels {
@@ -541,3 +555,37 @@ fn color_distance(r1: u8, g1: u8, b1: u8, r2: u8, g2: u8, b2: u8) -> f32 {
     let db = b1 as f32 - b2 as f32;
     (dr * dr + dg * dg + db * db).sqrt()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use photo_editor_common::ErrorCode;
+
+    #[test]
+    fn reports_progress_and_stops_when_cancelled() {
+        // Dark square on a light background
+        let (width, height) = (24u32, 24u32);
+        let mut input = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let inside = (6..18).contains(&x) && (6..18).contains(&y);
+                input.extend_from_slice(if inside { &[40, 50, 60, 255] } else { &[230, 230, 220, 255] });
+            }
+        }
+        let mut mask = vec![0u8; (width * height) as usize];
+
+        let mut seen = Vec::new();
+        let mut progress = Progress::new(|fraction| {
+            seen.push(fraction);
+            true
+        });
+        grabcut_segment(&input, width, height, 4, 4, 16, 16, 3, &mut mask, &mut progress).unwrap();
+        drop(progress);
+        assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(*seen.last().unwrap() >= 0.55);
+
+        let mut progress = Progress::new(|fraction| fraction < 0.3);
+        let error = grabcut_segment(&input, width, height, 4, 4, 16, 16, 3, &mut mask, &mut progress).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::Cancelled);
+    }
+}
@@ -1,51 +1,114 @@
-use wasm_bindgen::prelude::*;
-
 mod color_threshold;
 mod magic_wand;
 mod grabcut;
+mod redeye;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-#[wasm_bindgen]
-pub fn remove_solid_color(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    target_color: &[u8],
-    tolerance: u8,
-    feather: u8,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
-    if target_color.len() != 3 {
-        return Err(JsValue::from_str("target_color must have exactly 3 elements (R, G, B)"));
-    }
-    let color = [target_color[0], target_color[1], target_color[2]];
-    color_threshold::remove_color(input, width, height, color, tolerance, feather, output)
+pub use photo_editor_common::{Error, ErrorCode, Image, Progress, Result};
+
+// Native API: the algorithms behind the JavaScript exports in wasm.rs,
+// taking and returning owned images instead of filling output buffers
+
+/// Make `target_color` transparent, along with colors within `tolerance`;
+/// alpha fades in over `feather` more levels of distance.
+pub fn remove_solid_color(image: &Image, target_color: [u8; 3], tolerance: u8, feather: u8) -> Result<Image> {
+    same_size(image, |output| {
+        color_threshold::remove_color(
+            image.data(), image.width(), image.height(), target_color, tolerance, feather, output,
+        )
+    })
 }
 
-#[wasm_bindgen]
+/// Select pixels within `tolerance` of the seed pixel, only those connected
+/// to it if `connected` is set. Returns the mask, one byte per pixel.
 pub fn magic_wand_select(
-    input: &[u8],
-    width: u32,
-    height: u32,
+    image: &Image,
     seed_x: u32,
     seed_y: u32,
     tolerance: u8,
     connected: bool,
-    mask_output: &mut [u8],
-) -> Result<usize, JsValue> {
-    magic_wand::magic_wand(input, width, height, seed_x, seed_y, tolerance, connected, mask_output)
+) -> Result<Vec<u8>> {
+    let mut mask = vec![0; image.data().len() / 4];
+    magic_wand::magic_wand(
+        image.data(), image.width(), image.height(), seed_x, seed_y, tolerance, connected, &mut mask,
+    )?;
+    Ok(mask)
 }
 
-#[wasm_bindgen]
+/// Separate the foreground inside a rectangle from the background around
+/// it. Returns the mask, one byte per pixel; fails with
+/// `ErrorCode::Cancelled` once `progress` asks to stop.
 pub fn grabcut_segment(
-    input: &[u8],
-    width: u32,
-    height: u32,
+    image: &Image,
     rect_x: u32,
     rect_y: u32,
     rect_width: u32,
     rect_height: u32,
     iterations: u8,
-    mask_output: &mut [u8],
-) -> Result<usize, JsValue> {
-    grabcut::grabcut_segment(input, width, height, rect_x, rect_y, rect_width, rect_height, iterations, mask_output)
+    progress: &mut Progress,
+) -> Result<Vec<u8>> {
+    let mut mask = vec![0; image.data().len() / 4];
+    grabcut::grabcut_segment(
+        image.data(), image.width(), image.height(), rect_x, rect_y, rect_width, rect_height, iterations,
+        &mut mask, progress,
+    )?;
+    Ok(mask)
+}
+
+/// Correct a red pupil inside a rectangle; pixels outside it are unchanged.
+pub fn remove_red_eye(image: &Image, rect_x: u32, rect_y: u32, rect_width: u32, rect_height: u32) -> Result<Image> {
+    same_size(image, |output| {
+        redeye::remove_red_eye(
+            image.data(), image.width(), image.height(), rect_x, rect_y, rect_width, rect_height, output,
+        )
+    })
+}
+
+/// Correct a red pupil within `radius` pixels of a clicked point.
+pub fn remove_red_eye_at(image: &Image, x: u32, y: u32, radius: u32) -> Result<Image> {
+    let (rect_x, rect_y, size) = eye_rect(x, y, radius);
+    remove_red_eye(image, rect_x, rect_y, size, size)
+}
+
+/// Detect and correct red pupils across the whole image. Returns the
+/// corrected image and the number of pupils found.
+pub fn auto_remove_red_eye(image: &Image, sensitivity: u8) -> Result<(Image, u32)> {
+    let mut output = vec![0; image.data().len()];
+    let count = redeye::auto_remove_red_eye(image.data(), image.width(), image.height(), sensitivity, &mut output)?;
+    Ok((Image::new(image.width(), image.height(), output)?, count))
+}
+
+/// Square around a clicked point: left, top and side length
+fn eye_rect(x: u32, y: u32, radius: u32) -> (u32, u32, u32) {
+    let size = radius.saturating_mul(2).saturating_add(1);
+    (x.saturating_sub(radius), y.saturating_sub(radius), size)
+}
+
+/// Run an operation that writes an image of the same size as `image`
+fn same_size(image: &Image, fill: impl FnOnce(&mut [u8]) -> Result<usize>) -> Result<Image> {
+    let mut output = vec![0; image.data().len()];
+    fill(&mut output)?;
+    Image::new(image.width(), image.height(), output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_api_returns_owned_results() {
+        // Red left half, blue right half
+        let data = (0..16).flat_map(|i| if i % 4 < 2 { [255, 0, 0, 255] } else { [0, 0, 255, 255] }).collect();
+        let image = Image::new(4, 4, data).unwrap();
+
+        let keyed = remove_solid_color(&image, [255, 0, 0], 10, 0).unwrap();
+        assert_eq!((keyed.width(), keyed.height()), (4, 4));
+        assert_eq!(keyed.data()[3], 0);
+        assert_eq!(keyed.data()[4 * 2 + 3], 255);
+
+        let mask = magic_wand_select(&image, 0, 0, 10, true).unwrap();
+        assert_eq!(mask.len(), 16);
+        assert!(mask[0] != 0 && mask[3] == 0);
+    }
 }
@@ -1,5 +1,6 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::Error;
 
+#[allow(clippy::too_many_arguments, clippy::needless_range_loop)]
 pub fn magic_wand(
     input: &[u8],
     width: u32,
@@ -9,18 +10,18 @@ pub fn magic_wand(
     tolerance: u8,
     connected: bool,
     mask_output: &mut [u8],
-) -> Result<usize, JsValue> {
+) -> Result<usize, Error> {
     if input.is_empty() || width == 0 || height == 0 {
-        return Err(JsValue::from_str("Invalid input dimensions"));
+        return Err(Error::invalid_dimensions("Invalid input dimensions"));
     }
 
     let expected_len = (width * height) as usize;
     if input.len() != (expected_len * 4) || mask_output.len() < expected_len {
-        return Err(JsValue::from_str("Buffer length mismatch"));
+        return Err(Error::input_size_mismatch("Buffer length mismatch"));
     }
 
     if seed_x >= width || seed_y >= height {
-        return Err(JsValue::from_str("Seed coordinates out of bounds"));
+        return Err(Error::out_of_bounds("Seed coordinates out of bounds"));
     }
 
     // Initialize mask to all zeros
@@ -65,6 +66,7 @@ pub fn magic_wand(
     Ok(expected_len)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn flood_fill_connected(
     input: &[u8],
     width: u32,
@@ -77,23 +79,42 @@ fn flood_fill_connected(
     tolerance: u8,
     mask: &mut [u8],
 ) {
+    flood_fill(width, height, seed_x, seed_y, mask, |x, y| {
+        let pixel_idx = ((y * width + x) * 4) as usize;
+        let r = input[pixel_idx];
+        let g = input[pixel_idx + 1];
+        let b = input[pixel_idx + 2];
+        color_within_tolerance(r, g, b, seed_r, seed_g, seed_b, tolerance)
+    });
+}
+
+/// 4-connected flood fill from a seed, marking every reachable pixel that
+/// satisfies `accept` with 255 in `mask`. Pixels already set to 255 are
+/// treated as visited, so repeated fills over one mask label disjoint regions.
+/// Returns the number of pixels filled.
+pub(crate) fn flood_fill<F>(
+    width: u32,
+    height: u32,
+    seed_x: u32,
+    seed_y: u32,
+    mask: &mut [u8],
+    mut accept: F,
+) -> usize
+where
+    F: FnMut(u32, u32) -> bool,
+{
     let mut stack = vec![(seed_x, seed_y)];
-    let mut visited = vec![false; (width * height) as usize];
+    let mut filled = 0;
 
     while let Some((x, y)) = stack.pop() {
         let idx = (y * width + x) as usize;
-        if visited[idx] {
+        if mask[idx] == 255 {
             continue;
         }
 
-        let pixel_idx = (idx * 4) as usize;
-        let r = input[pixel_idx];
-        let g = input[pixel_idx + 1];
-        let b = input[pixel_idx + 2];
-
-        if color_within_tolerance(r, g, b, seed_r, seed_g, seed_b, tolerance) {
-            visited[idx] = true;
+        if accept(x, y) {
             mask[idx] = 255;
+            filled += 1;
 
             // Add neighbors (4-connected)
             if x > 0 {
@@ -110,8 +131,11 @@ fn flood_fill_connected(
             }
         }
     }
+
+    filled
 }
 
+#[allow(clippy::too_many_arguments)]
 fn flood_fill_global(
     input: &[u8],
     width: u32,
@@ -146,9 +170,9 @@ fn color_within_tolerance(
     target_b: u8,
     tolerance: u8,
 ) -> bool {
-    let dr = (r as i16 - target_r as i16).abs();
-    let dg = (g as i16 - target_g as i16).abs();
-    let db = (b as i16 - target_b as i16).abs();
+    let dr = (r as i32 - target_r as i32).abs();
+    let dg = (g as i32 - target_g as i32).abs();
+    let db = (b as i32 - target_b as i32).abs();
 
     // Use Euclidean distance
     let distance = ((dr * dr + dg * dg + db * db) as f32).sqrt();
@@ -0,0 +1,288 @@
+use photo_editor_common::Error;
+
+use crate::magic_wand::flood_fill;
+
+/// Minimum red channel value for a pupil pixel (dark pixels have unstable ratios)
+const MIN_RED: u8 = 50;
+
+/// Redness ratio used to grow the pupil from a user-selected seed
+const FILL_REDNESS: f32 = 1.6;
+
+/// How much the corrected pupil is darkened after desaturation
+const DARKEN: f32 = 0.8;
+
+/// Smallest blob (in pixels) accepted by auto-detection
+const MIN_EYE_AREA: usize = 9;
+
+/// Blobs whose bounding box covers less of its area are not round enough
+const MIN_FILL_RATIO: f32 = 0.5;
+
+/// Axis-aligned bounds (x0, y0, x1, y1) with exclusive end
+type Bounds = (u32, u32, u32, u32);
+
+/// Red-to-other-channels ratio: 1.0 for neutral gray, higher for red
+fn redness(r: u8, g: u8, b: u8) -> f32 {
+    2.0 * r as f32 / (g as f32 + b as f32 + 1.0)
+}
+
+fn is_red(input: &[u8], idx: usize, threshold: f32) -> bool {
+    let (r, g, b) = (input[idx], input[idx + 1], input[idx + 2]);
+    r >= MIN_RED && redness(r, g, b) >= threshold
+}
+
+fn validate(input: &[u8], width: u32, height: u32, output: &[u8]) -> Result<usize, Error> {
+    if input.is_empty() || width == 0 || height == 0 {
+        return Err(Error::invalid_dimensions("Invalid input dimensions"));
+    }
+
+    // Pixel offsets below are computed in u32
+    let expected_len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or_else(|| Error::invalid_dimensions("Image dimensions overflow"))? as usize;
+    if input.len() != expected_len || output.len() < expected_len {
+        return Err(Error::input_size_mismatch("Buffer length mismatch"));
+    }
+
+    Ok(expected_len)
+}
+
+/// Correct a red pupil inside a user-selected rectangle.
+///
+/// The reddest pixel in the rectangle seeds a flood fill over connected
+/// pixels whose redness ratio passes the threshold; the filled pupil is then
+/// desaturated and darkened with a feathered edge. Pixels outside the
+/// rectangle are never modified.
+#[allow(clippy::too_many_arguments)]
+pub fn remove_red_eye(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    rect_x: u32,
+    rect_y: u32,
+    rect_width: u32,
+    rect_height: u32,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let expected_len = validate(input, width, height, output)?;
+    output[..expected_len].copy_from_slice(input);
+
+    let x1 = rect_x.saturating_add(rect_width).min(width);
+    let y1 = rect_y.saturating_add(rect_height).min(height);
+    if rect_x >= x1 || rect_y >= y1 {
+        return Err(Error::out_of_bounds("Red-eye region out of bounds"));
+    }
+    let bounds = (rect_x, rect_y, x1, y1);
+
+    // Seed from the reddest pixel in the selection
+    let mut seed = None;
+    let mut best = FILL_REDNESS;
+    for y in rect_y..y1 {
+        for x in rect_x..x1 {
+            let idx = ((y * width + x) * 4) as usize;
+            let score = redness(input[idx], input[idx + 1], input[idx + 2]);
+            if input[idx] >= MIN_RED && score >= best {
+                best = score;
+                seed = Some((x, y));
+            }
+        }
+    }
+
+    let Some((seed_x, seed_y)) = seed else {
+        return Ok(expected_len);
+    };
+
+    let mut mask = vec![0u8; (width * height) as usize];
+    flood_fill(width, height, seed_x, seed_y, &mut mask, |x, y| {
+        x >= rect_x
+            && x < x1
+            && y >= rect_y
+            && y < y1
+            && is_red(input, ((y * width + x) * 4) as usize, FILL_REDNESS)
+    });
+
+    correct_pupil(&mut output[..expected_len], width, bounds, |x, y| {
+        mask[(y * width + x) as usize] == 255
+    });
+
+    Ok(expected_len)
+}
+
+/// Detect and correct round, strongly red blobs across the whole image.
+///
+/// `sensitivity` (0-100) lowers the redness threshold as it increases.
+/// Returns the number of pupils corrected.
+pub fn auto_remove_red_eye(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    sensitivity: u8,
+    output: &mut [u8],
+) -> Result<u32, Error> {
+    let expected_len = validate(input, width, height, output)?;
+    output[..expected_len].copy_from_slice(input);
+
+    let threshold = 2.6 - 1.2 * (sensitivity.min(100) as f32 / 100.0);
+    let max_side = (width.min(height) / 8).max(3);
+
+    let pixel_count = (width * height) as usize;
+    let mut mask = vec![0u8; pixel_count];
+    let mut labels = vec![0u32; pixel_count];
+    let mut next_label = 0u32;
+    let mut corrected = 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = (y * width + x) as usize;
+            if mask[idx] == 255 || !is_red(input, idx * 4, threshold) {
+                continue;
+            }
+
+            next_label += 1;
+            let label = next_label;
+            let mut bounds: Bounds = (x, y, x + 1, y + 1);
+            let area = flood_fill(width, height, x, y, &mut mask, |px, py| {
+                let pidx = (py * width + px) as usize;
+                if !is_red(input, pidx * 4, threshold) {
+                    return false;
+                }
+                labels[pidx] = label;
+                bounds = (bounds.0.min(px), bounds.1.min(py), bounds.2.max(px + 1), bounds.3.max(py + 1));
+                true
+            });
+
+            if is_pupil_shaped(area, bounds, max_side) {
+                correct_pupil(&mut output[..expected_len], width, bounds, |px, py| {
+                    labels[(py * width + px) as usize] == label
+                });
+                corrected += 1;
+            }
+        }
+    }
+
+    Ok(corrected)
+}
+
+fn is_pupil_shaped(area: usize, bounds: Bounds, max_side: u32) -> bool {
+    let (x0, y0, x1, y1) = bounds;
+    let (w, h) = (x1 - x0, y1 - y0);
+    if area < MIN_EYE_AREA || w > max_side || h > max_side {
+        return false;
+    }
+
+    let aspect = w as f32 / h as f32;
+    let fill_ratio = area as f32 / (w * h) as f32;
+    (0.5..=2.0).contains(&aspect) && fill_ratio >= MIN_FILL_RATIO
+}
+
+/// Desaturate and darken the pixels selected by `in_pupil`.
+///
+/// Each pixel's strength is the share of selected pixels in its 3x3
+/// neighbourhood, so the pupil boundary fades into the iris instead of
+/// leaving a hard gray ring.
+fn correct_pupil<F>(data: &mut [u8], width: u32, bounds: Bounds, in_pupil: F)
+where
+    F: Fn(u32, u32) -> bool,
+{
+    let (x0, y0, x1, y1) = bounds;
+    let mut weights = Vec::with_capacity(((x1 - x0) * (y1 - y0)) as usize);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            if !in_pupil(x, y) {
+                weights.push(0.0);
+                continue;
+            }
+
+            // Neighbours outside the bounds are never part of the pupil
+            let mut selected = 0;
+            for ny in y.saturating_sub(1).max(y0)..(y + 2).min(y1) {
+                for nx in x.saturating_sub(1).max(x0)..(x + 2).min(x1) {
+                    if in_pupil(nx, ny) {
+                        selected += 1;
+                    }
+                }
+            }
+            weights.push(selected as f32 / 9.0);
+        }
+    }
+
+    let mut weights = weights.into_iter();
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let weight = weights.next().unwrap_or(0.0);
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let idx = ((y * width + x) * 4) as usize;
+            let (r, g, b) = (data[idx] as f32, data[idx + 1] as f32, data[idx + 2] as f32);
+            let target = (g + b) / 2.0 * DARKEN;
+            for (c, value) in [r, g, b].into_iter().enumerate() {
+                data[idx + c] = (value + (target - value) * weight).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKIN: [u8; 4] = [200, 160, 140, 255];
+    const PUPIL: [u8; 4] = [210, 30, 35, 255];
+
+    fn face_with_eyes(width: u32, height: u32, eyes: &[(u32, u32)], radius: u32) -> Vec<u8> {
+        let mut data = SKIN.repeat((width * height) as usize);
+        for &(cx, cy) in eyes {
+            for y in cy - radius..=cy + radius {
+                for x in cx - radius..=cx + radius {
+                    let (dx, dy) = (x as i32 - cx as i32, y as i32 - cy as i32);
+                    if dx * dx + dy * dy <= (radius * radius) as i32 {
+                        let idx = ((y * width + x) * 4) as usize;
+                        data[idx..idx + 4].copy_from_slice(&PUPIL);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn corrects_selected_pupil_only() {
+        let input = face_with_eyes(32, 32, &[(10, 16), (22, 16)], 3);
+        let mut output = vec![0u8; input.len()];
+        remove_red_eye(&input, 32, 32, 5, 11, 10, 10, &mut output).unwrap();
+
+        let center = ((16 * 32 + 10) * 4) as usize;
+        assert!(redness(output[center], output[center + 1], output[center + 2]) < 1.1);
+        let other = ((16 * 32 + 22) * 4) as usize;
+        assert_eq!(output[other..other + 4], PUPIL);
+        assert_eq!(output[0..4], SKIN);
+    }
+
+    #[test]
+    fn auto_detect_skips_large_red_areas() {
+        let mut input = face_with_eyes(64, 64, &[(20, 20), (40, 20)], 3);
+        for y in 44..60 {
+            for x in 4..60 {
+                let idx = ((y * 64 + x) * 4) as usize;
+                input[idx..idx + 4].copy_from_slice(&PUPIL);
+            }
+        }
+        let mut output = vec![0u8; input.len()];
+        let corrected = auto_remove_red_eye(&input, 64, 64, 50, &mut output).unwrap();
+
+        assert_eq!(corrected, 2);
+        let shirt = ((50 * 64 + 30) * 4) as usize;
+        assert_eq!(output[shirt..shirt + 4], PUPIL);
+    }
+
+    #[test]
+    fn overflowing_dimensions_are_rejected() {
+        let input = vec![0u8; 16];
+        let mut output = vec![0u8; 16];
+        let error = auto_remove_red_eye(&input, 65536, 65536, 50, &mut output).unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::InvalidDimensions);
+    }
+}
@@ -0,0 +1,101 @@
+// JavaScript exports: same operations as the native API in lib.rs, filling
+// caller-provided buffers so the browser can reuse its typed arrays
+
+use photo_editor_common::{Error, Progress, ProgressToken};
+use wasm_bindgen::prelude::*;
+
+use crate::{color_threshold, grabcut, magic_wand, redeye};
+
+#[wasm_bindgen]
+pub fn remove_solid_color(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    target_color: &[u8],
+    tolerance: u8,
+    feather: u8,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    if target_color.len() != 3 {
+        return Err(Error::invalid_parameter("target_color must have exactly 3 elements (R, G, B)").into());
+    }
+    let color = [target_color[0], target_color[1], target_color[2]];
+    Ok(color_threshold::remove_color(input, width, height, color, tolerance, feather, output)?)
+}
+
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn magic_wand_select(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    seed_x: u32,
+    seed_y: u32,
+    tolerance: u8,
+    connected: bool,
+    mask_output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(magic_wand::magic_wand(input, width, height, seed_x, seed_y, tolerance, connected, mask_output)?)
+}
+
+/// Pass a `ProgressToken` to follow progress and to cancel; a cancelled
+/// call throws with `ErrorCode.Cancelled`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn grabcut_segment(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    rect_x: u32,
+    rect_y: u32,
+    rect_width: u32,
+    rect_height: u32,
+    iterations: u8,
+    mask_output: &mut [u8],
+    progress: Option<ProgressToken>,
+) -> Result<usize, JsValue> {
+    let mut progress = Progress::from_token(progress.as_ref());
+    Ok(grabcut::grabcut_segment(
+        input, width, height, rect_x, rect_y, rect_width, rect_height, iterations, mask_output, &mut progress,
+    )?)
+}
+
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn remove_red_eye(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    rect_x: u32,
+    rect_y: u32,
+    rect_width: u32,
+    rect_height: u32,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(redeye::remove_red_eye(input, width, height, rect_x, rect_y, rect_width, rect_height, output)?)
+}
+
+#[wasm_bindgen]
+pub fn remove_red_eye_at(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    radius: u32,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    let (rect_x, rect_y, size) = crate::eye_rect(x, y, radius);
+    Ok(redeye::remove_red_eye(input, width, height, rect_x, rect_y, size, size, output)?)
+}
+
+#[wasm_bindgen]
+pub fn auto_remove_red_eye(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    sensitivity: u8,
+    output: &mut [u8],
+) -> Result<u32, JsValue> {
+    Ok(redeye::auto_remove_red_eye(input, width, height, sensitivity, output)?)
+}
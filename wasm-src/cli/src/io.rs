@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use photo_editor_compress::{CompressionFormat, Image, Progress};
+use serde::Deserialize;
+
+use crate::ops::Recipe;
+use crate::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+}
+
+impl Format {
+    /// Format for a file name, if its extension is one we encode
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "png" => Some(Format::Png),
+            "webp" => Some(Format::Webp),
+            "avif" => Some(Format::Avif),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+}
+
+// How results are written; the "output" object of a recipe
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Encode {
+    pub format: Option<Format>,     // Defaults to the output file's extension
+    #[serde(default = "default_quality")]
+    pub quality: u8,                // 1-100; compression level hint for PNG
+    pub target_size: Option<usize>, // Search quality for a file of about this many bytes instead
+    pub min_quality: Option<u8>,    // With target_size: downscale rather than go below this quality
+}
+
+fn default_quality() -> u8 {
+    90
+}
+
+impl Default for Encode {
+    fn default() -> Self {
+        Encode {
+            format: None,
+            quality: default_quality(),
+            target_size: None,
+            min_quality: None,
+        }
+    }
+}
+
+impl Encode {
+    pub fn encode(&self, image: &Image, format: Format) -> Result<Vec<u8>> {
+        if let Some(target_size) = self.target_size {
+            let format = match format {
+                Format::Jpeg => CompressionFormat::Jpeg,
+                Format::Png => CompressionFormat::Png,
+                Format::Webp => CompressionFormat::WebP,
+                Format::Avif => CompressionFormat::Avif,
+            };
+            let mut progress = Progress::none();
+            let result = match self.min_quality {
+                Some(min_quality) => photo_editor_compress::compress_to_size_with_downscale(
+                    image, target_size, format, min_quality, &mut progress,
+                )?,
+                None => photo_editor_compress::compress_to_size(image, target_size, format, &mut progress)?,
+            };
+            return Ok(result.data);
+        }
+
+        Ok(match format {
+            Format::Jpeg => photo_editor_compress::compress_jpeg(image, self.quality)?,
+            Format::Png => photo_editor_compress::compress_png(image, self.quality)?,
+            Format::Webp => photo_editor_compress::compress_webp(image, self.quality)?,
+            Format::Avif => photo_editor_compress::compress_avif(image, self.quality)?,
+        })
+    }
+}
+
+/**
+ * Expand `patterns` (paths or globs) and pair each file with where to write it
+ *
+ * A single input goes to `output` itself unless that is a directory (or
+ * ends with a slash). Otherwise results go into the `output` directory,
+ * named after their input with the extension of `format`, else of the
+ * input's own format, else ".png".
+ */
+pub fn plan(patterns: &[String], output: &Path, format: Option<Format>) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let before = inputs.len();
+        for path in glob::glob(pattern).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))? {
+            let path = path?;
+            if path.is_file() {
+                inputs.push(path);
+            }
+        }
+        if inputs.len() == before {
+            return Err(format!("No files match {}", pattern).into());
+        }
+    }
+
+    let to_directory = inputs.len() > 1 || output.is_dir() || output.as_os_str().to_string_lossy().ends_with(['/', '\\']);
+    if !to_directory {
+        return Ok(vec![(inputs.remove(0), output.to_path_buf())]);
+    }
+
+    let mut seen = HashSet::new();
+    inputs
+        .into_iter()
+        .map(|input| {
+            let stem = input.file_stem().ok_or_else(|| format!("No file name in {}", input.display()))?;
+            let extension = format.or_else(|| Format::from_path(&input)).unwrap_or(Format::Png).extension();
+            let target = output.join(stem).with_extension(extension);
+            if !seen.insert(target.clone()) {
+                return Err(format!("More than one input would be written to {}", target.display()).into());
+            }
+            Ok((input, target))
+        })
+        .collect()
+}
+
+/// Decode `input`, apply the recipe and write the encoded result to
+/// `output`. Returns the number of bytes written.
+pub fn process(recipe: &Recipe, input: &Path, output: &Path) -> Result<usize> {
+    let format = recipe
+        .output
+        .format
+        .or_else(|| Format::from_path(output))
+        .ok_or_else(|| format!("Unknown output format for {}; pass --format", output.display()))?;
+
+    let bytes = fs::read(input)?;
+    // Upright and in sRGB, like images opened in the editor
+    let mut image = photo_editor_core::decode_image(&bytes, true, true)?.into_image()?;
+    for step in &recipe.steps {
+        image = step.apply(&image)?;
+    }
+    let encoded = recipe.output.encode(&image, format)?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, &encoded)?;
+    Ok(encoded.len())
+}
+
+/// File size such as "250000", "500KB" or "1.5M" (1 KB = 1024 bytes)
+pub fn parse_size(text: &str) -> std::result::Result<usize, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("Invalid size: {}", text))?;
+    let unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        _ => return Err(format!("Invalid size unit: {}", unit)),
+    };
+    match (number * unit).round() as usize {
+        0 => Err("Size must be > 0".to_string()),
+        size => Ok(size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_like_the_editor() {
+        assert_eq!(parse_size("250000"), Ok(250000));
+        assert_eq!(parse_size("500KB"), Ok(500 * 1024));
+        assert_eq!(parse_size("1.5m"), Ok(1536 * 1024));
+        assert!(parse_size("0").is_err() && parse_size("12 parsecs").is_err());
+    }
+
+    #[test]
+    fn plans_output_names_and_writes_results() {
+        let dir = std::env::temp_dir().join(format!("photo-editor-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = Image::new(8, 8, vec![200; 8 * 8 * 4]).unwrap();
+        for name in ["a.png", "b.jpg"] {
+            let format = Format::from_path(Path::new(name)).unwrap();
+            fs::write(dir.join(name), Encode::default().encode(&image, format).unwrap()).unwrap();
+        }
+
+        let pattern = format!("{}/*.*", dir.display());
+        let out = dir.join("out");
+        let jobs = plan(&[pattern], &out, Some(Format::Webp)).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs.iter().all(|(_, target)| target.parent() == Some(out.as_path())));
+        assert_eq!(jobs[1].1.file_name().unwrap(), "b.webp");
+
+        let recipe = Recipe::parse(r#"{ "steps": [{ "op": "flip", "direction": "horizontal" }] }"#).unwrap();
+        assert!(process(&recipe, &jobs[0].0, &dir.join("single.png")).unwrap() > 0);
+        assert!(process(&recipe, &jobs[0].0, &dir.join("single.tga")).is_err());
+
+        assert!(plan(&[format!("{}/*.gif", dir.display())], &out, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
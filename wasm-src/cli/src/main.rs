@@ -0,0 +1,263 @@
+//! `photo-editor`: the editor's operations on files, for scripts and
+//! servers. It links the same Rust code as the browser's wasm modules, so
+//! results match the editor.
+
+mod io;
+mod ops;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use rayon::prelude::*;
+
+use io::{Encode, Format};
+use ops::{Direction, Operation, Quality, Recipe};
+
+/// Error of one file or of the command line, printed as is
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Parser)]
+#[command(name = "photo-editor", version, about = "Batch image processing with the photo editor's algorithms")]
+struct Cli {
+    /// Files processed in parallel (default: one per CPU)
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Cut out a rectangle
+    Crop {
+        #[arg(long)]
+        x: u32,
+        #[arg(long)]
+        y: u32,
+        #[arg(long)]
+        width: u32,
+        #[arg(long)]
+        height: u32,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Rotate clockwise by 90, 180 or 270 degrees
+    Rotate {
+        #[arg(long)]
+        angle: u16,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Mirror horizontally or vertically
+    Flip {
+        #[arg(long, value_enum)]
+        direction: Direction,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Resize; give one side to keep the aspect ratio
+    Resize {
+        #[arg(long, required_unless_present = "height")]
+        width: Option<u32>,
+        #[arg(long)]
+        height: Option<u32>,
+        #[arg(long, value_enum, default_value_t = Quality::High)]
+        filter: Quality,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Re-encode, at a quality or searching for a target file size
+    Compress {
+        /// Target file size, e.g. 300000, 500KB or 1.5MB
+        #[arg(long, value_parser = io::parse_size)]
+        target_size: Option<usize>,
+        /// With --target-size: downscale rather than go below this quality
+        #[arg(long, requires = "target_size")]
+        min_quality: Option<u8>,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Make the background transparent (written as PNG unless the output says otherwise)
+    RemoveBg {
+        #[command(flatten)]
+        method: RemoveBgArgs,
+        #[command(flatten)]
+        files: Files,
+        #[command(flatten)]
+        encode: EncodeArgs,
+    },
+    /// Apply the steps and output settings of a JSON recipe
+    Pipeline {
+        recipe: PathBuf,
+        #[command(flatten)]
+        files: Files,
+    },
+}
+
+#[derive(Args)]
+struct Files {
+    /// Input files or glob patterns such as "photos/*.jpg"
+    #[arg(required = true)]
+    inputs: Vec<String>,
+    /// Output file, or directory for several inputs
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct EncodeArgs {
+    /// Output format (default: from the output file name, else the input's)
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+    /// Encoder quality (1-100); compression level hint for PNG
+    #[arg(short, long, default_value_t = 90)]
+    quality: u8,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("method").required(true).args(["color", "grabcut"])))]
+struct RemoveBgArgs {
+    /// Remove a solid background color, as RRGGBB hex
+    #[arg(long, value_parser = parse_color)]
+    color: Option<[u8; 3]>,
+    /// Color distance still removed with --color
+    #[arg(long, default_value_t = ops::default_tolerance())]
+    tolerance: u8,
+    /// Edge softening with --color
+    #[arg(long, default_value_t = ops::default_feather())]
+    feather: u8,
+    /// Segment the subject inside a rectangle: X,Y,WIDTH,HEIGHT
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = parse_rect)]
+    grabcut: Option<[u32; 4]>,
+    /// Refinement iterations with --grabcut
+    #[arg(long, default_value_t = ops::default_iterations())]
+    iterations: u8,
+}
+
+fn parse_color(text: &str) -> std::result::Result<[u8; 3], String> {
+    let hex = text.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+    let value = value.ok_or_else(|| format!("Expected a color as RRGGBB, got {}", text))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+fn parse_rect(text: &str) -> std::result::Result<[u32; 4], String> {
+    let invalid = || format!("Expected X,Y,WIDTH,HEIGHT, got {}", text);
+    let values: Vec<u32> = text.split(',').map(|v| v.trim().parse().map_err(|_| invalid())).collect::<std::result::Result<_, _>>()?;
+    values.try_into().map_err(|_| invalid())
+}
+
+impl EncodeArgs {
+    fn into_encode(self) -> Encode {
+        Encode {
+            format: self.format,
+            quality: self.quality,
+            ..Encode::default()
+        }
+    }
+}
+
+impl Command {
+    /// Every subcommand is a recipe over a set of files
+    fn into_recipe(self) -> Result<(Recipe, Files)> {
+        let single = |step, files, encode: EncodeArgs| {
+            let recipe = Recipe {
+                steps: vec![step],
+                output: encode.into_encode(),
+            };
+            Ok((recipe, files))
+        };
+        match self {
+            Command::Crop { x, y, width, height, files, encode } => {
+                single(Operation::Crop { x, y, width, height }, files, encode)
+            }
+            Command::Rotate { angle, files, encode } => single(Operation::Rotate { angle }, files, encode),
+            Command::Flip { direction, files, encode } => single(Operation::Flip { direction }, files, encode),
+            Command::Resize { width, height, filter, files, encode } => {
+                single(Operation::Resize { width, height, quality: filter }, files, encode)
+            }
+            Command::Compress { target_size, min_quality, files, encode } => {
+                let output = Encode {
+                    target_size,
+                    min_quality,
+                    ..encode.into_encode()
+                };
+                Ok((Recipe { steps: Vec::new(), output }, files))
+            }
+            Command::RemoveBg { method, files, mut encode } => {
+                let step = match (method.color, method.grabcut) {
+                    (Some(color), _) => Operation::RemoveColor {
+                        color,
+                        tolerance: method.tolerance,
+                        feather: method.feather,
+                    },
+                    (None, Some(rect)) => Operation::Grabcut {
+                        rect,
+                        iterations: method.iterations,
+                    },
+                    (None, None) => unreachable!("clap requires --color or --grabcut"),
+                };
+                // Keep the transparency unless an output file name asks for another format
+                if encode.format.is_none() && Format::from_path(&files.output).is_none() {
+                    encode.format = Some(Format::Png);
+                }
+                single(step, files, encode)
+            }
+            Command::Pipeline { recipe, files } => {
+                let json = fs::read_to_string(&recipe).map_err(|e| format!("{}: {}", recipe.display(), e))?;
+                Ok((Recipe::parse(&json)?, files))
+            }
+        }
+    }
+}
+
+/// Process every file; false if any of them failed
+fn run(cli: Cli) -> Result<bool> {
+    if let Some(jobs) = cli.jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+    }
+    let (recipe, files) = cli.command.into_recipe()?;
+    let jobs = io::plan(&files.inputs, &files.output, recipe.output.format)?;
+
+    let failed = jobs
+        .par_iter()
+        .filter(|(input, output)| match io::process(&recipe, input, output) {
+            Ok(size) => {
+                println!("{} -> {} ({} bytes)", input.display(), output.display(), size);
+                false
+            }
+            Err(error) => {
+                eprintln!("{}: {}", input.display(), error);
+                true
+            }
+        })
+        .count();
+    if failed > 0 {
+        eprintln!("{} of {} files failed", failed, jobs.len());
+    }
+    Ok(failed == 0)
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("photo-editor: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use clap::ValueEnum;
+use photo_editor_core::{CropRect, FlipDirection, Image, Progress, ResizeQuality, RotateAngle};
+use serde::Deserialize;
+
+use crate::io::Encode;
+use crate::Result;
+
+/**
+ * What to do with every input file: edits applied in order, then encoding
+ *
+ * Subcommands build a one-step recipe; `pipeline` reads one from JSON:
+ *
+ * ```json
+ * {
+ *   "steps": [
+ *     { "op": "resize", "width": 1600 },
+ *     { "op": "rotate", "angle": 90 }
+ *   ],
+ *   "output": { "format": "webp", "target_size": 300000 }
+ * }
+ * ```
+ */
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    #[serde(default)]
+    pub steps: Vec<Operation>,
+    #[serde(default)]
+    pub output: Encode,
+}
+
+impl Recipe {
+    pub fn parse(json: &str) -> Result<Recipe> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid recipe: {}", e).into())
+    }
+}
+
+// One edit, named by "op" in recipes
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Operation {
+    Crop { x: u32, y: u32, width: u32, height: u32 },
+    Rotate { angle: u16 },                          // 90, 180 or 270 clockwise
+    Flip { direction: Direction },
+    Resize {
+        width: Option<u32>,                         // Missing side keeps the aspect ratio
+        height: Option<u32>,
+        #[serde(default)]
+        quality: Quality,
+    },
+    Tone { spec: serde_json::Value },               // Levels/curve spec, as in the editor
+    RemoveColor {
+        color: [u8; 3],
+        #[serde(default = "default_tolerance")]
+        tolerance: u8,
+        #[serde(default = "default_feather")]
+        feather: u8,
+    },
+    Grabcut {
+        rect: [u32; 4],                             // x, y, width, height around the subject
+        #[serde(default = "default_iterations")]
+        iterations: u8,
+    },
+}
+
+// Defaults of the editor's background removal panel
+pub fn default_tolerance() -> u8 {
+    20
+}
+
+pub fn default_feather() -> u8 {
+    2
+}
+
+pub fn default_iterations() -> u8 {
+    5
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    Fast,
+    #[default]
+    High,
+}
+
+impl Operation {
+    pub fn apply(&self, image: &Image) -> Result<Image> {
+        Ok(match *self {
+            Operation::Crop { x, y, width, height } => {
+                photo_editor_core::crop_image(image, &CropRect::new(x, y, width, height))?
+            }
+            Operation::Rotate { angle } => {
+                let angle = match angle {
+                    90 => RotateAngle::Degree90,
+                    180 => RotateAngle::Degree180,
+                    270 => RotateAngle::Degree270,
+                    _ => return Err(format!("Rotation must be 90, 180 or 270 degrees, not {}", angle).into()),
+                };
+                photo_editor_core::rotate_image(image, angle)?
+            }
+            Operation::Flip { direction } => {
+                let direction = match direction {
+                    Direction::Horizontal => FlipDirection::Horizontal,
+                    Direction::Vertical => FlipDirection::Vertical,
+                };
+                photo_editor_core::flip_image(image, direction)?
+            }
+            Operation::Resize { width, height, quality } => {
+                let (width, height) = fit(image, width, height)?;
+                let quality = match quality {
+                    Quality::Fast => ResizeQuality::Fast,
+                    Quality::High => ResizeQuality::High,
+                };
+                photo_editor_core::resize_image(image, width, height, quality, &mut Progress::none())?
+            }
+            Operation::Tone { ref spec } => photo_editor_core::adjust_tone(image, &spec.to_string())?,
+            Operation::RemoveColor { color, tolerance, feather } => {
+                photo_editor_bgremove::remove_solid_color(image, color, tolerance, feather)?
+            }
+            Operation::Grabcut { rect: [x, y, width, height], iterations } => {
+                let mask = photo_editor_bgremove::grabcut_segment(
+                    image, x, y, width, height, iterations, &mut Progress::none(),
+                )?;
+                // Like the editor: mask values below 128 are background
+                let mut data = image.data().to_vec();
+                for (pixel, &m) in data.chunks_exact_mut(4).zip(&mask) {
+                    if m < 128 {
+                        pixel[3] = 0;
+                    }
+                }
+                Image::new(image.width(), image.height(), data)?
+            }
+        })
+    }
+}
+
+/// Target size for a resize; a missing side follows the aspect ratio
+fn fit(image: &Image, width: Option<u32>, height: Option<u32>) -> Result<(u32, u32)> {
+    let scaled = |side: u32, from: u32, to: u32| ((side as f64 * to as f64 / from as f64).round() as u32).max(1);
+    match (width, height) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        (Some(width), None) => Ok((width, scaled(image.height(), image.width(), width))),
+        (None, Some(height)) => Ok((scaled(image.width(), image.height(), height), height)),
+        (None, None) => Err("Resize needs a width, a height or both".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recipe_steps_apply_in_order() {
+        let recipe = Recipe::parse(
+            r#"{
+                "steps": [
+                    { "op": "resize", "width": 20 },
+                    { "op": "rotate", "angle": 90 },
+                    { "op": "crop", "x": 0, "y": 0, "width": 5, "height": 4 }
+                ],
+                "output": { "format": "png" }
+            }"#,
+        )
+        .unwrap();
+
+        let mut image = Image::new(10, 6, vec![128; 10 * 6 * 4]).unwrap();
+        image = recipe.steps[0].apply(&image).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 12));
+        image = recipe.steps[1].apply(&image).unwrap();
+        assert_eq!((image.width(), image.height()), (12, 20));
+        image = recipe.steps[2].apply(&image).unwrap();
+        assert_eq!((image.width(), image.height()), (5, 4));
+
+        assert!(Recipe::parse(r#"{ "steps": [{ "op": "sharpen" }] }"#).is_err());
+        assert!(Operation::Rotate { angle: 45 }.apply(&image).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+/**
+ * Narrow 16-bit RGBA samples to 8 bits per channel, writing as many
+ * samples as both slices hold
+ *
+ * With `dither`, triangular noise of one 8-bit step is added to the color
+ * channels before rounding, trading banding in smooth gradients (skies)
+ * for fine grain. The noise is hashed from the sample index, so repeated
+ * exports are byte-identical. Alpha is always rounded exactly.
+ */
+pub fn rgba16_to_rgba8(input: &[u16], dither: bool, output: &mut [u8]) {
+    for (i, (src, dst)) in input.iter().zip(output.iter_mut()).enumerate() {
+        let mut value = *src as f32 / 257.0;
+        if dither && i % 4 != 3 {
+            value += triangular_noise(i as u32);
+        }
+        *dst = value.round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+// Noise in (-1, 1) with a triangular distribution
+fn triangular_noise(index: u32) -> f32 {
+    let mut x = index.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xC2B2_AE35);
+    x ^= x >> 16;
+    let a = (x & 0xFFFF) as f32 / 65536.0;
+    let b = (x >> 16) as f32 / 65536.0;
+    a + b - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dither_breaks_up_bands() {
+        // A gradient spanning four 8-bit steps over 256 pixels
+        let input: Vec<u16> = (0..256u32)
+            .flat_map(|x| {
+                let v = (100 * 257 + x * 4) as u16;
+                [v, v, v, 65535]
+            })
+            .collect();
+        let narrow = |dither| {
+            let mut output = vec![0u8; input.len()];
+            rgba16_to_rgba8(&input, dither, &mut output);
+            output
+        };
+        let plain = narrow(false);
+        let dithered = narrow(true);
+
+        // Without dithering the gradient collapses into a few long flat runs
+        let transitions = |data: &[u8]| data.chunks(4).collect::<Vec<_>>().windows(2).filter(|w| w[0][0] != w[1][0]).count();
+        assert!(transitions(&plain) <= 4);
+        assert!(transitions(&dithered) > 50);
+        assert_eq!(narrow(true), dithered);
+    }
+}
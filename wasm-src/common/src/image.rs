@@ -0,0 +1,66 @@
+use crate::{Error, Result};
+
+/**
+ * RGBA pixels, 4 samples per pixel in row-major order
+ *
+ * `Image` holds 8-bit pixels and `Image<u16>` the 16-bit working pixels.
+ * The buffer is checked against the dimensions on construction, so the
+ * native APIs taking an image only validate their own parameters.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image<T = u8> {
+    width: u32,
+    height: u32,
+    data: Vec<T>,
+}
+
+impl<T> Image<T> {
+    pub fn new(width: u32, height: u32, data: Vec<T>) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::invalid_dimensions("Empty input or zero dimensions"));
+        }
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| Error::invalid_dimensions("Image dimensions overflow"))?;
+        if data.len() != expected {
+            return Err(Error::input_size_mismatch(format!(
+                "Input size mismatch: expected {}, got {}",
+                expected,
+                data.len()
+            )));
+        }
+        Ok(Image { width, height, data })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    #[test]
+    fn test_new_checks_buffer_against_dimensions() {
+        let image = Image::new(2, 1, vec![0u8; 8]).unwrap();
+        assert_eq!((image.width(), image.height(), image.data().len()), (2, 1, 8));
+
+        assert_eq!(Image::new(2, 2, vec![0u16; 8]).unwrap_err().code(), ErrorCode::InputSizeMismatch);
+        assert_eq!(Image::<u8>::new(0, 2, Vec::new()).unwrap_err().code(), ErrorCode::InvalidDimensions);
+    }
+}
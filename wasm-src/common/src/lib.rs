@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+pub mod depth;
+mod image;
+mod progress;
+
+pub use image::Image;
+pub use progress::Progress;
+#[cfg(feature = "wasm")]
+pub use progress::ProgressToken;
+
+/**
+ * Error category, exposed to JavaScript as the `code` of thrown errors
+ *
+ * The numeric values are part of the API: don't reorder or reuse them.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidDimensions = 1, // Zero, overflowing or too large width/height
+    InputSizeMismatch = 2, // Input length doesn't match the dimensions
+    OutputTooSmall = 3,    // Output buffer can't hold the result
+    InvalidParameter = 4,  // Quality, options or other argument out of range
+    OutOfBounds = 5,       // Coordinates or region outside the image
+    EncodeFailed = 6,      // Encoder error
+    DecodeFailed = 7,      // Input file can't be decoded
+    Unsupported = 8,       // Valid input this build can't handle
+    Cancelled = 9,         // Stopped through a ProgressToken
+}
+
+/**
+ * Error returned by every fallible operation in the WASM modules
+ *
+ * The native Rust API returns it directly. With the `wasm` feature, at the
+ * `#[wasm_bindgen]` boundary it converts into a JavaScript `Error` whose
+ * `name` is "PhotoEditorError" and with a numeric `code` property.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    code: ErrorCode,
+    message: Cow<'static, str>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<Cow<'static, str>>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_dimensions(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::InvalidDimensions, message)
+    }
+
+    pub fn input_size_mismatch(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::InputSizeMismatch, message)
+    }
+
+    pub fn output_too_small(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::OutputTooSmall, message)
+    }
+
+    pub fn invalid_parameter(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::InvalidParameter, message)
+    }
+
+    pub fn out_of_bounds(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::OutOfBounds, message)
+    }
+
+    pub fn encode_failed(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::EncodeFailed, message)
+    }
+
+    pub fn decode_failed(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::DecodeFailed, message)
+    }
+
+    pub fn unsupported(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::Unsupported, message)
+    }
+
+    pub fn cancelled(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::new(ErrorCode::Cancelled, message)
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(feature = "wasm")]
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        let js_error = js_sys::Error::new(&error.message);
+        js_error.set_name("PhotoEditorError");
+        // Setting a property on a fresh Error object can't fail
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("code"), &JsValue::from(error.code as u32));
+        js_error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_carries_code_and_message() {
+        let error = Error::output_too_small(format!("needed {} bytes", 42));
+        assert_eq!(error.code(), ErrorCode::OutputTooSmall);
+        assert_eq!(error.to_string(), "needed 42 bytes");
+        assert_eq!(Error::invalid_parameter("Quality out of range").code() as u32, 4);
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(feature = "wasm")]
+use js_sys::{Atomics, Function, Int32Array};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, Result};
+
+// Smallest change in progress passed on to the callback
+const REPORT_STEP: f64 = 0.01;
+
+/**
+ * Progress reporting and cancellation for a long-running operation
+ *
+ * Operations call `report` between iterations or rows with the fraction
+ * done so far. Once the callback asks to stop, `report` returns an error
+ * with `ErrorCode::Cancelled`, which the operation propagates with `?`.
+ * The callback only runs when progress moved by at least 1%, so reporting
+ * every row is cheap; cancellation is noticed at the same granularity.
+ */
+pub struct Progress<'a> {
+    callback: Option<Box<dyn FnMut(f64) -> bool + 'a>>,
+    last: f64,
+}
+
+impl<'a> Progress<'a> {
+    /// No reporting, never cancelled
+    pub fn none() -> Self {
+        Progress {
+            callback: None,
+            last: f64::NEG_INFINITY,
+        }
+    }
+
+    /// `callback` gets the fraction done (0-1) and returns false to cancel
+    pub fn new(callback: impl FnMut(f64) -> bool + 'a) -> Self {
+        Progress {
+            callback: Some(Box::new(callback)),
+            last: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Report from JavaScript through `token`, if one was passed
+    #[cfg(feature = "wasm")]
+    pub fn from_token(token: Option<&'a ProgressToken>) -> Self {
+        match token {
+            Some(token) => Progress::new(|fraction| token.poll(fraction)),
+            None => Progress::none(),
+        }
+    }
+
+    pub fn report(&mut self, fraction: f64) -> Result<()> {
+        let fraction = fraction.clamp(0.0, 1.0);
+        if self.callback.is_none() || (fraction < 1.0 && fraction - self.last < REPORT_STEP) {
+            return Ok(());
+        }
+        self.last = fraction;
+        self.call(fraction)
+    }
+
+    /// Check for cancellation without new progress, for loops of unknown length
+    pub fn check(&mut self) -> Result<()> {
+        self.call(self.last.max(0.0))
+    }
+
+    fn call(&mut self, fraction: f64) -> Result<()> {
+        match self.callback.as_mut().map(|callback| callback(fraction)) {
+            Some(false) => Err(Error::cancelled("Operation cancelled")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Progress for a sub-task covering `start..end` of this operation
+    pub fn range(&mut self, start: f64, end: f64) -> Progress<'_> {
+        if self.callback.is_none() {
+            return Progress::none();
+        }
+        Progress::new(move |fraction| self.report(start + (end - start) * fraction).is_ok())
+    }
+}
+
+/**
+ * Progress callback and cancellation flag passed from JavaScript
+ *
+ * The callback receives the fraction done (0-1); returning `false` (or
+ * throwing) cancels the operation. A worker is busy for the whole call, so
+ * to cancel from another thread, share an `Int32Array` over a
+ * SharedArrayBuffer with `set_cancel_flag` and store a non-zero value in
+ * its first element. Operations taking a token consume it: create a new
+ * one for every call.
+ */
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub struct ProgressToken {
+    callback: Option<Function>,
+    cancel_flag: Option<Int32Array>,
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl ProgressToken {
+    #[wasm_bindgen(constructor)]
+    pub fn new(callback: Option<Function>) -> ProgressToken {
+        ProgressToken {
+            callback,
+            cancel_flag: None,
+        }
+    }
+
+    /// Cancel once `flag[0]` is non-zero
+    pub fn set_cancel_flag(&mut self, flag: Int32Array) {
+        self.cancel_flag = Some(flag);
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl ProgressToken {
+    /// Pass on progress; false once the operation should stop
+    fn poll(&self, fraction: f64) -> bool {
+        if let Some(flag) = &self.cancel_flag {
+            if Atomics::load(flag, 0) != Ok(0) {
+                return false;
+            }
+        }
+        match &self.callback {
+            Some(callback) => callback
+                .call1(&JsValue::NULL, &JsValue::from_f64(fraction))
+                .is_ok_and(|result| result != JsValue::FALSE),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    #[test]
+    fn test_report_throttles_and_cancels() {
+        let mut seen = Vec::new();
+        let mut cancelled_at = None;
+        {
+            let mut progress = Progress::new(|fraction| {
+                seen.push(fraction);
+                fraction < 0.5
+            });
+            for row in 0..=1000 {
+                if let Err(error) = progress.report(row as f64 / 1000.0) {
+                    assert_eq!(error.code(), ErrorCode::Cancelled);
+                    cancelled_at = Some(row);
+                    break;
+                }
+            }
+        }
+        // About one callback per percent, stopping at the first refusal
+        assert!((40..=51).contains(&seen.len()));
+        assert!(matches!(cancelled_at, Some(500..=520)));
+
+        // Sub-ranges map onto the parent's range
+        let mut seen = Vec::new();
+        let mut progress = Progress::new(|fraction| {
+            seen.push(fraction);
+            true
+        });
+        progress.range(0.5, 1.0).report(0.5).unwrap();
+        drop(progress);
+        assert_eq!(seen, [0.75]);
+    }
+}
@@ -0,0 +1,153 @@
+use ravif::{Encoder, Img, RGBA8};
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// AV1 frames are limited to 65536 pixels per side
+const MAX_DIMENSION: u32 = 65536;
+
+/**
+ * AVIF advanced compression parameters
+ *
+ * Color is always stored as full-resolution 4:4:4 YCbCr at 10 bits, which
+ * decodes without banding even from 8-bit input. Opaque images get no
+ * alpha plane, so `alpha_quality` only matters for transparent ones.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct AvifAdvancedParams {
+    pub speed: u8,         // 1-10, encoder speed (1=slowest best, 10=fastest)
+    pub alpha_quality: u8, // 1-100, alpha plane quality
+}
+
+impl Default for AvifAdvancedParams {
+    fn default() -> Self {
+        Self {
+            speed: 6,
+            alpha_quality: 80,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AvifAdvancedParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> AvifAdvancedParams {
+        AvifAdvancedParams::default()
+    }
+}
+
+/**
+ * Compress RGBA image data to AVIF format (basic)
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - AVIF quality (1-100)
+ *
+ * # Returns
+ * Ok(avif_bytes) on success
+ * Err(error) on failure
+ */
+pub fn compress_to_avif(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, Error> {
+    compress_to_avif_advanced(input, width, height, quality, &AvifAdvancedParams::default())
+}
+
+/**
+ * Compress RGBA image data to AVIF format (advanced)
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - AVIF quality (1-100)
+ * * `params` - Encoder speed and alpha quality
+ *
+ * # Returns
+ * Ok(avif_bytes) on success
+ * Err(error) on failure
+ *
+ * The AV1 encoding runs single-threaded so it works in any worker.
+ */
+pub fn compress_to_avif_advanced(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &AvifAdvancedParams,
+) -> Result<Vec<u8>, Error> {
+    log_debug!("compress_to_avif_advanced: {}x{}, quality={}", width, height, quality);
+    log_debug!("AVIF params - speed: {}, alpha_quality: {}", params.speed, params.alpha_quality);
+
+    if input.len() != (width as usize) * (height as usize) * 4 {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(Error::invalid_dimensions("Image dimensions exceed the AVIF limit of 65536 pixels"));
+    }
+
+    let pixels: Vec<RGBA8> = input.chunks_exact(4).map(|p| RGBA8::new(p[0], p[1], p[2], p[3])).collect();
+    let encoded = Encoder::new()
+        .with_quality(quality.clamp(1, 100) as f32)
+        .with_alpha_quality(params.alpha_quality.clamp(1, 100) as f32)
+        .with_speed(params.speed.clamp(1, 10))
+        .with_num_threads(Some(1))
+        .encode_rgba(Img::new(&pixels[..], width as usize, height as usize))
+        .map_err(|e| {
+            log_error!("AVIF encoding error: {:?}", e);
+            Error::encode_failed("AVIF encoding failed")
+        })?;
+    let avif = encoded.avif_file;
+    log_debug!(
+        "AVIF encoded successfully, size: {} (color {}, alpha {})",
+        avif.len(), encoded.color_byte_size, encoded.alpha_byte_size
+    );
+    Ok(avif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_image(width: u32, height: u32, alpha: bool) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let a = if alpha { (x * 255 / width) as u8 } else { 255 };
+                [(x * 255 / width) as u8, (y * 255 / height) as u8, 96, a]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_avif_is_an_isobmff_file_and_honours_quality() {
+        let rgba = sample_image(48, 40, false);
+        let params = AvifAdvancedParams {
+            speed: 10,
+            ..AvifAdvancedParams::default()
+        };
+
+        let high = compress_to_avif_advanced(&rgba, 48, 40, 95, &params).unwrap();
+        assert_eq!(&high[4..12], b"ftypavif");
+        let low = compress_to_avif_advanced(&rgba, 48, 40, 20, &params).unwrap();
+        assert!(low.len() < high.len());
+
+        // Transparency adds an alpha plane, whose size follows alpha_quality
+        let rgba = sample_image(48, 40, true);
+        let with_alpha = compress_to_avif_advanced(&rgba, 48, 40, 20, &params).unwrap();
+        assert!(with_alpha.len() > low.len());
+        assert!(with_alpha.windows(5).any(|w| w == b"auxC\0"));
+    }
+
+    #[test]
+    fn test_avif_advanced_params_default() {
+        let params = AvifAdvancedParams::default();
+        assert_eq!(params.speed, 6);
+        assert_eq!(params.alpha_quality, 80);
+    }
+}
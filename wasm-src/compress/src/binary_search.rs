@@ -1,6 +1,15 @@
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgba};
+use photo_editor_common::{Error, Progress};
+
+use crate::avif;
 use crate::jpeg;
+use crate::metrics;
+use crate::png::{self, PngAdvancedParams};
+use crate::webp;
 use crate::CompressionFormat;
 use crate::CompressionResult;
+use crate::SizeStrategy;
 
 // Maximum number of binary search iterations
 const MAX_ITERATIONS: u8 = 10;
@@ -8,11 +17,23 @@ const MAX_ITERATIONS: u8 = 10;
 // Tolerance for target size (±5%)
 const TOLERANCE_PERCENT: f64 = 0.05;
 
+// Downscale fallback: estimated steps are clamped to shrink each side by
+// 10-75%, then the last gap is bisected REFINE_STEPS times
+const MAX_DOWNSCALE_STEPS: u8 = 8;
+const MIN_SCALE_STEP: f64 = 0.25;
+const MAX_SCALE_STEP: f64 = 0.9;
+const REFINE_STEPS: u8 = 3;
+
+// Smallest longer side the downscale fallback goes to
+const MIN_DIMENSION: u32 = 16;
+
 /**
  * Find optimal quality to achieve target file size using binary search
  *
  * Uses binary search algorithm to find the quality parameter (1-100)
  * that produces a compressed image closest to the target file size.
+ * PNG has no quality knob, so it is tried lossless first and then
+ * quantized, searching the palette size instead (see `fit`).
  *
  * # Algorithm
  * 1. Start with quality range [1, 100]
@@ -28,10 +49,11 @@ const TOLERANCE_PERCENT: f64 = 0.05;
  * * `height` - Image height
  * * `target_size` - Target file size in bytes
  * * `format` - Compression format
- * * `output` - Output buffer
+ * * `progress` - Reported per attempt; cancelling stops the search
  *
  * # Returns
- * CompressionResult with actual size and quality used
+ * CompressionResult with the encoded image, its size, quality and the
+ * strategy used, or a cancelled error
  */
 pub fn find_optimal_quality(
     input: &[u8],
@@ -39,102 +61,362 @@ pub fn find_optimal_quality(
     height: u32,
     target_size: usize,
     format: CompressionFormat,
-    output: &mut [u8],
-) -> CompressionResult {
-    // Binary search bounds
-    let mut min_quality: u8 = 1;
-    let mut max_quality: u8 = 100;
-
-    // Track best result
-    let mut best_result: Option<CompressionResult> = None;
+    progress: &mut Progress,
+) -> Result<CompressionResult, Error> {
+    // If no good result found, the minimum quality is used as fallback
+    match fit(input, width, height, with_tolerance(target_size), format, 1, progress)? {
+        Ok(result) | Err(result) => Ok(result),
+    }
+}
 
-    // Binary search loop
-    for _iteration in 0..MAX_ITERATIONS {
-        let mid_quality = (min_quality + max_quality) / 2;
+/**
+ * Reach a target file size by lowering quality, then resolution
+ *
+ * Searches quality down to `min_quality` at full resolution first. If even
+ * that is too large, the image is downscaled (Catmull-Rom, the filter of
+ * core's `ResizeQuality::High`) in steps estimated from how far off the
+ * last attempt was, then bisected back up to the largest size that fits.
+ * Unlike `find_optimal_quality` there is no tolerance: a result fits only
+ * at or under `target_size` bytes.
+ * For PNG the floor applies to the palette: `min_quality` percent of 256
+ * colors.
+ *
+ * # Arguments
+ * * `input` - RGBA image data
+ * * `width` - Image width
+ * * `height` - Image height
+ * * `target_size` - Target file size in bytes
+ * * `format` - Compression format
+ * * `min_quality` - Lowest quality (1-100) accepted before downscaling
+ * * `progress` - Reported per attempt; cancelling stops the search
+ *
+ * # Returns
+ * CompressionResult with size, quality, strategy and final dimensions; if
+ * the smallest size tried still misses the target, that attempt is
+ * returned. Err only when cancelled.
+ */
+pub fn find_optimal_size_with_downscale(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    target_size: usize,
+    format: CompressionFormat,
+    min_quality: u8,
+    progress: &mut Progress,
+) -> Result<CompressionResult, Error> {
+    // Every fit at one resolution gets an equal share of the progress
+    let attempts = (1 + MAX_DOWNSCALE_STEPS + REFINE_STEPS) as f64;
+    let mut attempt = 0.0;
+    let mut next_share = || {
+        attempt += 1.0;
+        ((attempt - 1.0) / attempts, attempt / attempts)
+    };
 
-        // Compress with current quality
-        let compressed_size = compress_with_format(
-            input,
-            width,
-            height,
-            mid_quality,
-            format,
-            output,
-        );
+    let (start, end) = next_share();
+    let mut floor = match fit(input, width, height, target_size, format, min_quality, &mut progress.range(start, end))? {
+        Ok(result) => return Ok(result),
+        Err(result) => result,
+    };
 
-        // Check if we're within tolerance of target
-        let size_diff = compressed_size as f64 - target_size as f64;
-        let percent_diff = (size_diff.abs() / target_size as f64) * 100.0;
+    let min_scale = (MIN_DIMENSION as f64 / width.max(height) as f64).min(1.0);
+    let mut scale = 1.0;
+    let mut too_large = 1.0;
+    let mut found = None;
+    for _step in 0..MAX_DOWNSCALE_STEPS {
+        if scale <= min_scale {
+            break;
+        }
+        // Encoded size grows roughly with the pixel count
+        let estimate = (target_size as f64 / floor.size.max(1) as f64).sqrt();
+        scale = (scale * estimate.clamp(MIN_SCALE_STEP, MAX_SCALE_STEP)).max(min_scale);
 
-        let within_tolerance = percent_diff <= (TOLERANCE_PERCENT * 100.0);
+        let (pixels, new_width, new_height) = downscale(input, width, height, scale);
+        log_debug!("Downscaled to {}x{}", new_width, new_height);
+        let (start, end) = next_share();
+        match fit(&pixels, new_width, new_height, target_size, format, min_quality, &mut progress.range(start, end))? {
+            Ok(result) => {
+                found = Some((scale, result));
+                break;
+            }
+            Err(result) => {
+                too_large = scale;
+                floor = result;
+            }
+        }
+    }
 
-        if within_tolerance {
-            // Good result, keep it and try for better quality
-            best_result = Some(CompressionResult {
-                size: compressed_size,
-                quality: mid_quality,
-            });
+    // Otherwise return the smallest attempt
+    let Some((mut fit_scale, mut best)) = found else {
+        return Ok(floor);
+    };
 
-            // Try higher quality (better image, larger file)
-            if min_quality < max_quality {
-                min_quality = mid_quality + 1;
-            } else {
-                break;
+    // Bisect towards the largest resolution that still fits
+    for _step in 0..REFINE_STEPS {
+        let scale = (fit_scale + too_large) / 2.0;
+        let (pixels, new_width, new_height) = downscale(input, width, height, scale);
+        if (new_width, new_height) == (best.width, best.height) {
+            break;
+        }
+        let (start, end) = next_share();
+        match fit(&pixels, new_width, new_height, target_size, format, min_quality, &mut progress.range(start, end))? {
+            Ok(result) => {
+                fit_scale = scale;
+                best = result;
             }
-        } else if compressed_size > target_size {
-            // File too large, compress more (lower quality)
-            if compressed_size == 0 {
-                // Encoding failed, try lower quality
-                max_quality = mid_quality - 1;
-            } else {
-                max_quality = mid_quality - 1;
+            Err(_) => too_large = scale,
+        }
+    }
+    Ok(best)
+}
+
+/**
+ * Best encoding at these dimensions of at most `max_size` bytes with
+ * quality (or palette size) at or above the floor
+ *
+ * PNG has no quality knob, so it is tried lossless first and then
+ * quantized, searching the largest palette (dithered) that fits.
+ *
+ * # Returns
+ * Ok(result) with the best encoding, or Err(result) with the encoding at
+ * the floor; the outer Err if cancelled through `progress`
+ */
+fn fit(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    max_size: usize,
+    format: CompressionFormat,
+    min_quality: u8,
+    progress: &mut Progress,
+) -> Result<Result<CompressionResult, CompressionResult>, Error> {
+    let result = |data: Vec<u8>, quality, strategy, colors| CompressionResult {
+        size: data.len(),
+        data,
+        quality,
+        strategy,
+        colors,
+        width,
+        height,
+        ssim: 0.0,
+    };
+
+    if let CompressionFormat::Png = format {
+        progress.report(0.0)?;
+        let lossless = png::compress_to_png(input, width, height, 100).unwrap_or_default();
+        if fits(lossless.len(), max_size) {
+            return Ok(Ok(result(lossless, 100, SizeStrategy::Lossless, 0)));
+        }
+
+        let encode = |colors: u16| {
+            let params = PngAdvancedParams {
+                quantize: true,
+                max_colors: colors,
+                ..PngAdvancedParams::default()
+            };
+            png::compress_to_png_advanced(input, width, height, 100, &params).unwrap_or_default()
+        };
+        let min_colors = (min_quality as u16 * 256 / 100).clamp(2, 256);
+        // The lossless attempt counts as one search iteration
+        let share = 1.0 / (MAX_ITERATIONS as f64 + 1.0);
+        return Ok(match search(min_colors, 256, max_size, encode, &mut progress.range(share, 1.0))? {
+            Ok((colors, data)) => Ok(result(data, 100, SizeStrategy::Palette, colors)),
+            Err(data) => Err(result(data, 100, SizeStrategy::Palette, min_colors)),
+        });
+    }
+
+    let encode = |quality: u16| compress_with_format(input, width, height, quality as u8, format);
+    Ok(match search(min_quality.clamp(1, 100) as u16, 100, max_size, encode, progress)? {
+        Ok((quality, data)) => Ok(result(data, quality as u8, SizeStrategy::Quality, 0)),
+        Err(data) => Err(result(data, min_quality.clamp(1, 100), SizeStrategy::Quality, 0)),
+    })
+}
+
+/// Resample RGBA data by `scale`, with the filter core's `ResizeQuality::High` uses
+fn downscale(input: &[u8], width: u32, height: u32, scale: f64) -> (Vec<u8>, u32, u32) {
+    let new_width = ((width as f64 * scale).round() as u32).clamp(1, width);
+    let new_height = ((height as f64 * scale).round() as u32).clamp(1, height);
+    let source = ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(width, height, input)
+        .expect("input length matches dimensions");
+    let resized = imageops::resize(&source, new_width, new_height, FilterType::CatmullRom);
+    (resized.into_raw(), new_width, new_height)
+}
+
+/**
+ * Smallest encoding whose SSIM against the input reaches `min_ssim`
+ *
+ * Binary-searches quality for the lowest setting that passes: compressed
+ * output is decoded and compared after every attempt. PNG searches the
+ * smallest palette instead, and stays lossless (SSIM 1.0) if 256 colors
+ * aren't enough. AVIF can't be decoded in this module, so it isn't
+ * supported.
+ *
+ * # Arguments
+ * * `input` - RGBA image data
+ * * `width` - Image width
+ * * `height` - Image height
+ * * `format` - Compression format (Jpeg, WebP or Png)
+ * * `min_ssim` - Required structural similarity (0-1)
+ *
+ * # Returns
+ * Ok(result) with the encoding. If even the highest quality misses the
+ * bar, that encoding is returned.
+ * Err(error) for AVIF
+ */
+pub fn find_quality_for_ssim(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    format: CompressionFormat,
+    min_ssim: f64,
+) -> Result<CompressionResult, Error> {
+    let measure = |encoded: &[u8]| -> f64 {
+        match image::load_from_memory(encoded) {
+            Ok(decoded) => metrics::ssim(input, &decoded.into_rgba8(), width as usize, height as usize),
+            Err(_) => 0.0,
+        }
+    };
+    let result = |data: Vec<u8>, quality, strategy, colors, ssim| CompressionResult {
+        size: data.len(),
+        data,
+        quality,
+        strategy,
+        colors,
+        width,
+        height,
+        ssim,
+    };
+
+    match format {
+        CompressionFormat::Avif => Err(Error::unsupported("AVIF output can't be decoded to measure SSIM")),
+        CompressionFormat::Png => {
+            let encode = |colors: u16| {
+                let params = PngAdvancedParams {
+                    quantize: true,
+                    max_colors: colors,
+                    ..PngAdvancedParams::default()
+                };
+                png::compress_to_png_advanced(input, width, height, 100, &params).unwrap_or_default()
+            };
+            match search_lowest(2, 256, min_ssim, encode, measure) {
+                Some((colors, data, ssim)) => Ok(result(data, 100, SizeStrategy::Palette, colors, ssim)),
+                None => {
+                    let data = png::compress_to_png(input, width, height, 100)?;
+                    Ok(result(data, 100, SizeStrategy::Lossless, 0, 1.0))
+                }
             }
-        } else {
-            // File smaller than target, good result
-            // Try higher quality for better image
-            best_result = Some(CompressionResult {
-                size: compressed_size,
-                quality: mid_quality,
+        }
+        _ => {
+            let encode = |quality: u16| compress_with_format(input, width, height, quality as u8, format);
+            let (quality, data, ssim) = search_lowest(1, 100, min_ssim, encode, measure).unwrap_or_else(|| {
+                let data = encode(100);
+                let ssim = measure(&data);
+                (100, data, ssim)
             });
+            Ok(result(data, quality as u8, SizeStrategy::Quality, 0, ssim))
+        }
+    }
+}
 
-            if min_quality < max_quality {
-                min_quality = mid_quality + 1;
-            } else {
+/// Binary search for the smallest parameter in `min..=max` whose encoding
+/// scores at least `min_score`. Returns the parameter, encoding and score,
+/// or None if nothing passed.
+fn search_lowest<F, M>(
+    min: u16,
+    max: u16,
+    min_score: f64,
+    mut encode: F,
+    score: M,
+) -> Option<(u16, Vec<u8>, f64)>
+where
+    F: FnMut(u16) -> Vec<u8>,
+    M: Fn(&[u8]) -> f64,
+{
+    let (mut low, mut high) = (min, max);
+    let mut best = None;
+
+    for _iteration in 0..MAX_ITERATIONS {
+        if low > high {
+            break;
+        }
+        let mid = low + (high - low) / 2;
+        let encoded = encode(mid);
+        let value = if encoded.is_empty() { 0.0 } else { score(&encoded) };
+        log_debug!("Search {}: {} bytes, score {:.4}", mid, encoded.len(), value);
+
+        if value >= min_score {
+            // Good enough, keep it and try a smaller file
+            best = Some((mid, encoded, value));
+            if mid == min {
                 break;
             }
+            high = mid - 1;
+        } else {
+            low = mid + 1;
         }
+    }
+    best
+}
 
-        // Prevent infinite loop
-        if min_quality >= max_quality {
+/// Largest size accepted for a target when searching quality alone:
+/// within tolerance above it
+fn with_tolerance(target_size: usize) -> usize {
+    (target_size as f64 * (1.0 + TOLERANCE_PERCENT)) as usize
+}
+
+/// Whether an encoding succeeded and is at most `max_size` bytes
+fn fits(size: usize, max_size: usize) -> bool {
+    size > 0 && size <= max_size
+}
+
+/// Parameter and encoding that fit, or the encoding at the minimum
+type Found = Result<(u16, Vec<u8>), Vec<u8>>;
+
+/// Binary search for the largest parameter in `min..=max` whose encoding
+/// is at most `max_size` bytes. Returns the parameter and encoding, or Err with the
+/// encoding at `min` if even that was too large; the outer Err if cancelled.
+fn search<F>(
+    min: u16,
+    max: u16,
+    max_size: usize,
+    mut encode: F,
+    progress: &mut Progress,
+) -> Result<Found, Error>
+where
+    F: FnMut(u16) -> Vec<u8>,
+{
+    let (mut low, mut high) = (min, max);
+    let mut best = None;
+
+    for iteration in 0..MAX_ITERATIONS {
+        if low > high {
             break;
         }
-    }
+        progress.report(iteration as f64 / MAX_ITERATIONS as f64)?;
+        let mid = low + (high - low) / 2;
+        let encoded = encode(mid);
+        log_debug!("Search {}: {} bytes (max {})", mid, encoded.len(), max_size);
 
-    // If no good result found, try minimum quality as fallback
-    if best_result.is_none() {
-        let compressed_size = compress_with_format(
-            input,
-            width,
-            height,
-            1,
-            format,
-            output,
-        );
-
-        best_result = Some(CompressionResult {
-            size: compressed_size,
-            quality: 1,
-        });
+        if fits(encoded.len(), max_size) {
+            // Good result, keep it and try for better quality
+            best = Some((mid, encoded));
+            low = mid + 1;
+        } else if mid == min {
+            return Ok(Err(encoded));
+        } else {
+            // File too large (or encoding failed), compress more
+            high = mid - 1;
+        }
     }
 
-    best_result.unwrap_or(CompressionResult {
-        size: 0,
-        quality: 0,
-    })
+    // Iterations ran out before reaching `min`
+    Ok(best.ok_or_else(|| encode(min)))
 }
 
 /**
  * Compress image with specified format and quality
+ *
+ * # Returns
+ * The encoded image, or an empty buffer if encoding failed
  */
 fn compress_with_format(
     input: &[u8],
@@ -142,16 +424,14 @@ fn compress_with_format(
     height: u32,
     quality: u8,
     format: CompressionFormat,
-    output: &mut [u8],
-) -> usize {
-    match format {
-        CompressionFormat::Jpeg => jpeg::compress_to_jpeg(input, width, height, quality, output)
-            .unwrap_or(0),
-        CompressionFormat::WebP => jpeg::compress_to_jpeg(input, width, height, quality, output)
-            .unwrap_or(0), // Fallback to JPEG
-        CompressionFormat::Png => jpeg::compress_to_jpeg(input, width, height, quality, output)
-            .unwrap_or(0), // Fallback to JPEG
-    }
+) -> Vec<u8> {
+    let encoded = match format {
+        CompressionFormat::Jpeg => jpeg::compress_to_jpeg(input, width, height, quality),
+        CompressionFormat::WebP => webp::compress_to_webp(input, width, height, quality),
+        CompressionFormat::Png => png::compress_to_png(input, width, height, quality),
+        CompressionFormat::Avif => avif::compress_to_avif(input, width, height, quality),
+    };
+    encoded.unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -163,7 +443,6 @@ mod tests {
         // Create a simple test image
         let rgba_data = vec![255u8; 400]; // 10x10 white image
         let target_size = 1000; // 1KB target
-        let mut output = vec![0u8; 4096];
 
         let result = find_optimal_quality(
             &rgba_data,
@@ -171,8 +450,9 @@ mod tests {
             10,
             target_size,
             CompressionFormat::Jpeg,
-            &mut output,
-        );
+            &mut Progress::none(),
+        )
+        .unwrap();
 
         // Should find some quality level
         assert!(result.quality > 0);
@@ -182,7 +462,6 @@ mod tests {
     #[test]
     fn test_max_iterations_limit() {
         let rgba_data = vec![255u8; 400];
-        let mut output = vec![0u8; 4096];
 
         // Use very small target to force many iterations
         let result = find_optimal_quality(
@@ -191,10 +470,112 @@ mod tests {
             10,
             100, // Very small target
             CompressionFormat::Jpeg,
-            &mut output,
-        );
+            &mut Progress::none(),
+        )
+        .unwrap();
 
         // Should terminate without hanging
         assert!(result.quality > 0);
     }
+
+    #[test]
+    fn test_avif_target_size() {
+        let rgba_data: Vec<u8> = (0..32 * 32u32)
+            .flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, (i * 37 % 256) as u8, 255])
+            .collect();
+
+        let result = find_optimal_quality(&rgba_data, 32, 32, 1500, CompressionFormat::Avif, &mut Progress::none()).unwrap();
+
+        assert!(result.size > 0 && result.size <= 1500);
+        assert_eq!(&result.data[4..12], b"ftypavif");
+    }
+
+    fn photo(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let noise = (i.wrapping_mul(2654435761) >> 27) as u8;
+                [(x * 4) as u8 ^ noise, (y * 4) as u8, (x + y + noise as u32) as u8, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_webp_target_size_encodes_webp() {
+        let rgba_data = photo(48, 48);
+
+        let result = find_optimal_quality(&rgba_data, 48, 48, 1500, CompressionFormat::WebP, &mut Progress::none()).unwrap();
+
+        assert!(result.size > 0 && result.size as f64 <= 1500.0 * (1.0 + TOLERANCE_PERCENT));
+        assert_eq!(result.strategy, SizeStrategy::Quality);
+        assert_eq!(&result.data[..4], b"RIFF");
+        assert_eq!(&result.data[8..12], b"WEBP");
+    }
+
+    #[test]
+    fn test_png_target_size_quantizes_when_lossless_is_too_large() {
+        let rgba_data = photo(48, 48);
+
+        let lossless = find_optimal_quality(&rgba_data, 48, 48, 100_000, CompressionFormat::Png, &mut Progress::none()).unwrap();
+        assert_eq!(lossless.strategy, SizeStrategy::Lossless);
+
+        let result = find_optimal_quality(&rgba_data, 48, 48, lossless.size / 2, CompressionFormat::Png, &mut Progress::none()).unwrap();
+        assert_eq!(result.strategy, SizeStrategy::Palette);
+        assert!((2..=256).contains(&result.colors));
+        assert!(result.size as f64 <= (lossless.size / 2) as f64 * (1.0 + TOLERANCE_PERCENT));
+
+        // The reported encoding, not the last attempt
+        assert_eq!(result.data.len(), result.size);
+        let decoded = image::load_from_memory(&result.data).unwrap();
+        assert_eq!(decoded.width(), 48);
+        assert_eq!(&result.data[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_downscale_fallback_keeps_quality_floor() {
+        let rgba_data = photo(256, 192);
+
+        let floor = compress_with_format(&rgba_data, 256, 192, 60, CompressionFormat::Jpeg);
+        let target_size = floor.len() / 3;
+        let result = find_optimal_size_with_downscale(
+            &rgba_data,
+            256,
+            192,
+            target_size,
+            CompressionFormat::Jpeg,
+            60,
+            &mut Progress::none(),
+        )
+        .unwrap();
+
+        assert!(result.quality >= 60);
+        assert!(result.width < 256 && result.height < 192);
+        assert!(result.width > MIN_DIMENSION);
+        assert!(result.size <= target_size);
+
+        let decoded = image::load_from_memory(&result.data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (result.width, result.height));
+
+        // Cancelling partway through stops with a distinct error
+        let mut progress = Progress::new(|fraction| fraction < 0.2);
+        let error = find_optimal_size_with_downscale(&rgba_data, 256, 192, target_size, CompressionFormat::Jpeg, 60, &mut progress)
+            .unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::Cancelled);
+    }
+
+    #[test]
+    fn test_ssim_target_keeps_the_bar_with_the_lowest_quality() {
+        let rgba_data = photo(64, 48);
+
+        let loose = find_quality_for_ssim(&rgba_data, 64, 48, CompressionFormat::Jpeg, 0.8).unwrap();
+        let strict = find_quality_for_ssim(&rgba_data, 64, 48, CompressionFormat::Jpeg, 0.95).unwrap();
+        assert!(loose.ssim >= 0.8 && strict.ssim >= 0.95);
+        assert!(loose.quality < strict.quality && loose.size < strict.size);
+
+        // The reported encoding, not the last attempt
+        let decoded = image::load_from_memory(&strict.data).unwrap().into_rgba8();
+        assert_eq!(metrics::ssim(&rgba_data, &decoded, 64, 48), strict.ssim);
+
+        assert!(find_quality_for_ssim(&rgba_data, 64, 48, CompressionFormat::Avif, 0.9).is_err());
+    }
 }
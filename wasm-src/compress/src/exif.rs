@@ -0,0 +1,335 @@
+//! Minimal EXIF (TIFF structure) reader/writer
+//!
+//! Only IFD0 and its Exif, GPS and Interoperability sub-IFDs are kept.
+//! Entry values are stored as raw bytes in the byte order of the source
+//! file, so tags this module does not understand survive a round trip
+//! untouched. The IFD1 thumbnail is dropped because it no longer matches
+//! the exported pixels.
+
+pub const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+pub const TAG_MAKE: u16 = 0x010F;
+pub const TAG_MODEL: u16 = 0x0110;
+pub const TAG_ORIENTATION: u16 = 0x0112;
+pub const TAG_ARTIST: u16 = 0x013B;
+pub const TAG_COPYRIGHT: u16 = 0x8298;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_INTEROP_IFD: u16 = 0xA005;
+
+// Offsets into the original file that are meaningless once re-encoded
+const DROPPED_TAGS: [u16; 6] = [
+    0x0111, // StripOffsets
+    0x0117, // StripByteCounts
+    0x0201, // JPEGInterchangeFormat
+    0x0202, // JPEGInterchangeFormatLength
+    0xA002, // PixelXDimension
+    0xA003, // PixelYDimension
+];
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+
+// Guards against corrupt counts; real IFDs hold a few dozen entries
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub tag: u16,
+    pub kind: u16,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exif {
+    pub(crate) big_endian: bool,
+    pub(crate) ifd0: Vec<Entry>,
+    pub(crate) exif: Vec<Entry>,
+    pub(crate) gps: Vec<Entry>,
+    pub(crate) interop: Vec<Entry>,
+}
+
+fn type_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn ifd(&self, offset: usize) -> Option<Vec<Entry>> {
+        let count = self.u16(offset)? as usize;
+        if count > MAX_ENTRIES {
+            return None;
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let at = offset + 2 + i * 12;
+            let tag = self.u16(at)?;
+            let kind = self.u16(at + 2)?;
+            let count = self.u32(at + 4)?;
+            // Unknown types cannot be sized, so they cannot be copied either
+            let Some(size) = type_size(kind).and_then(|s| s.checked_mul(count as usize)) else {
+                continue;
+            };
+            let data = if size <= 4 {
+                self.data.get(at + 8..at + 8 + size)?
+            } else {
+                let start = self.u32(at + 8)? as usize;
+                match self.data.get(start..start.saturating_add(size)) {
+                    Some(data) => data,
+                    None => continue,
+                }
+            };
+            entries.push(Entry { tag, kind, count, data: data.to_vec() });
+        }
+        Some(entries)
+    }
+
+    /// Follow a sub-IFD pointer entry, if present and readable
+    fn sub_ifd(&self, entries: &[Entry], tag: u16) -> Vec<Entry> {
+        entries
+            .iter()
+            .find(|e| e.tag == tag && e.data.len() == 4)
+            .and_then(|e| {
+                let offset = Reader { data: &e.data, big_endian: self.big_endian }.u32(0)?;
+                self.ifd(offset as usize)
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Exif {
+    /**
+     * Parse a TIFF-structured EXIF blob (without the "Exif\0\0" prefix)
+     */
+    pub fn parse(tiff: &[u8]) -> Option<Exif> {
+        let big_endian = match tiff.get(0..4)? {
+            b"MM\0\x2a" => true,
+            b"II\x2a\0" => false,
+            _ => return None,
+        };
+        let reader = Reader { data: tiff, big_endian };
+        let ifd0 = reader.ifd(reader.u32(4)? as usize)?;
+        let exif = reader.sub_ifd(&ifd0, TAG_EXIF_IFD);
+        let gps = reader.sub_ifd(&ifd0, TAG_GPS_IFD);
+        let interop = reader.sub_ifd(&exif, TAG_INTEROP_IFD);
+
+        let strip = |entries: Vec<Entry>| -> Vec<Entry> {
+            entries
+                .into_iter()
+                .filter(|e| {
+                    !matches!(e.tag, TAG_EXIF_IFD | TAG_GPS_IFD | TAG_INTEROP_IFD)
+                        && !DROPPED_TAGS.contains(&e.tag)
+                })
+                .collect()
+        };
+
+        Some(Exif {
+            big_endian,
+            ifd0: strip(ifd0),
+            exif: strip(exif),
+            gps: strip(gps),
+            interop: strip(interop),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ifd0.is_empty() && self.exif.is_empty() && self.gps.is_empty()
+    }
+
+    pub fn has_gps(&self) -> bool {
+        !self.gps.is_empty()
+    }
+
+    pub fn remove_gps(&mut self) {
+        self.gps.clear();
+    }
+
+    fn find(&self, tag: u16) -> Option<&Entry> {
+        self.ifd0.iter().chain(self.exif.iter()).find(|e| e.tag == tag)
+    }
+
+    /**
+     * Read an ASCII tag from IFD0 or the Exif IFD
+     */
+    pub fn ascii(&self, tag: u16) -> Option<String> {
+        let entry = self.find(tag).filter(|e| e.kind == TYPE_ASCII)?;
+        let end = entry.data.iter().position(|&b| b == 0).unwrap_or(entry.data.len());
+        let value = String::from_utf8_lossy(&entry.data[..end]).trim().to_string();
+        (!value.is_empty()).then_some(value)
+    }
+
+    pub fn short(&self, tag: u16) -> Option<u16> {
+        let entry = self.find(tag).filter(|e| e.kind == TYPE_SHORT)?;
+        Reader { data: &entry.data, big_endian: self.big_endian }.u16(0)
+    }
+
+    /**
+     * Set an IFD0 ASCII tag (Artist, Copyright, ImageDescription, ...)
+     */
+    pub fn set_ascii(&mut self, tag: u16, value: &str) {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        let entry = Entry { tag, kind: TYPE_ASCII, count: data.len() as u32, data };
+        upsert(&mut self.ifd0, entry);
+    }
+
+    /**
+     * Overwrite an existing IFD0 SHORT tag; absent tags are left absent
+     */
+    pub fn replace_short(&mut self, tag: u16, value: u16) {
+        let data = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        if let Some(entry) = self.ifd0.iter_mut().find(|e| e.tag == tag) {
+            *entry = Entry { tag, kind: TYPE_SHORT, count: 1, data: data.to_vec() };
+        }
+    }
+
+    /**
+     * Serialize back to a TIFF blob (without the "Exif\0\0" prefix)
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let pointer = |tag: u16| Entry { tag, kind: TYPE_LONG, count: 1, data: vec![0; 4] };
+
+        let mut ifd0 = self.ifd0.clone();
+        let mut exif = self.exif.clone();
+        if !self.interop.is_empty() {
+            exif.push(pointer(TAG_INTEROP_IFD));
+        }
+        if !exif.is_empty() {
+            ifd0.push(pointer(TAG_EXIF_IFD));
+        }
+        if !self.gps.is_empty() {
+            ifd0.push(pointer(TAG_GPS_IFD));
+        }
+
+        // Pointers are inline LONGs, so IFD sizes do not depend on their values
+        let exif_offset = 8 + ifd_size(&ifd0);
+        let interop_offset = exif_offset + ifd_size(&exif);
+        let gps_offset = interop_offset + ifd_size(&self.interop);
+        self.set_pointer(&mut ifd0, TAG_EXIF_IFD, exif_offset);
+        self.set_pointer(&mut ifd0, TAG_GPS_IFD, gps_offset);
+        self.set_pointer(&mut exif, TAG_INTEROP_IFD, interop_offset);
+
+        let mut out = Vec::with_capacity(gps_offset + ifd_size(&self.gps));
+        if self.big_endian {
+            out.extend_from_slice(b"MM\0\x2a\0\0\0\x08");
+        } else {
+            out.extend_from_slice(b"II\x2a\0\x08\0\0\0");
+        }
+        for entries in [&ifd0, &exif, &self.interop, &self.gps] {
+            if !entries.is_empty() {
+                self.write_ifd(&mut out, entries);
+            }
+        }
+        out
+    }
+
+    fn set_pointer(&self, entries: &mut [Entry], tag: u16, offset: usize) {
+        let offset = offset as u32;
+        if let Some(entry) = entries.iter_mut().find(|e| e.tag == tag) {
+            entry.data = if self.big_endian { offset.to_be_bytes() } else { offset.to_le_bytes() }.to_vec();
+        }
+    }
+
+    fn write_ifd(&self, out: &mut Vec<u8>, entries: &[Entry]) {
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|e| e.tag);
+
+        let u16_bytes = |v: u16| if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut data_offset = out.len() + 2 + entries.len() * 12 + 4;
+        let mut data_area = Vec::new();
+        out.extend_from_slice(&u16_bytes(entries.len() as u16));
+        for entry in &entries {
+            out.extend_from_slice(&u16_bytes(entry.tag));
+            out.extend_from_slice(&u16_bytes(entry.kind));
+            out.extend_from_slice(&u32_bytes(entry.count));
+            if entry.data.len() <= 4 {
+                let mut inline = [0u8; 4];
+                inline[..entry.data.len()].copy_from_slice(&entry.data);
+                out.extend_from_slice(&inline);
+            } else {
+                out.extend_from_slice(&u32_bytes(data_offset as u32));
+                data_area.extend_from_slice(&entry.data);
+                if entry.data.len() % 2 == 1 {
+                    data_area.push(0);
+                }
+                data_offset += padded_len(&entry.data);
+            }
+        }
+        // No IFD1: the thumbnail is never written
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&data_area);
+    }
+}
+
+fn padded_len(data: &[u8]) -> usize {
+    data.len() + data.len() % 2
+}
+
+fn ifd_size(entries: &[Entry]) -> usize {
+    if entries.is_empty() {
+        return 0;
+    }
+    let data: usize = entries.iter().filter(|e| e.data.len() > 4).map(|e| padded_len(&e.data)).sum();
+    2 + entries.len() * 12 + 4 + data
+}
+
+fn upsert(entries: &mut Vec<Entry>, entry: Entry) {
+    match entries.iter_mut().find(|e| e.tag == entry.tag) {
+        Some(existing) => *existing = entry,
+        None => entries.push(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_sub_ifds_and_edits() {
+        let mut exif = Exif { big_endian: false, ..Default::default() };
+        exif.set_ascii(TAG_MAKE, "Camera Co");
+        exif.ifd0.push(Entry { tag: TAG_ORIENTATION, kind: TYPE_SHORT, count: 1, data: vec![6, 0] });
+        exif.exif.push(Entry { tag: TAG_DATE_TIME_ORIGINAL, kind: TYPE_ASCII, count: 20, data: b"2024:05:01 10:00:00\0".to_vec() });
+        exif.interop.push(Entry { tag: 0x0001, kind: TYPE_ASCII, count: 4, data: b"R98\0".to_vec() });
+        exif.gps.push(Entry { tag: 0x0002, kind: 5, count: 3, data: vec![1; 24] });
+
+        let mut parsed = Exif::parse(&exif.to_bytes()).unwrap();
+        assert_eq!(parsed, exif);
+        assert_eq!(parsed.short(TAG_ORIENTATION), Some(6));
+        assert_eq!(parsed.ascii(TAG_DATE_TIME_ORIGINAL).as_deref(), Some("2024:05:01 10:00:00"));
+
+        parsed.replace_short(TAG_ORIENTATION, 1);
+        parsed.set_ascii(TAG_ARTIST, "Jane Doe");
+        parsed.remove_gps();
+        let edited = Exif::parse(&parsed.to_bytes()).unwrap();
+        assert_eq!(edited.short(TAG_ORIENTATION), Some(1));
+        assert_eq!(edited.ascii(TAG_ARTIST).as_deref(), Some("Jane Doe"));
+        assert!(!edited.has_gps());
+    }
+}
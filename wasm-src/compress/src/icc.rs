@@ -0,0 +1,123 @@
+use photo_editor_common::Error;
+
+use crate::metadata::{self, Container};
+
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+// Segment payload minus the header and the sequence number/count bytes
+const MAX_JPEG_CHUNK: usize = 0xFFFF - 2 - ICC_HEADER.len() - 2;
+
+// Size of the fixed ICC profile header
+const MIN_PROFILE_LEN: usize = 128;
+
+const PNG_PROFILE_NAME: &[u8] = b"ICC Profile";
+
+/**
+ * Embed an ICC profile into an encoded JPEG or PNG
+ *
+ * # Arguments
+ * * `encoded` - JPEG or PNG produced by the encoders in this crate
+ * * `profile` - ICC profile the pixels were encoded in (e.g. from the decoder)
+ *
+ * # Returns
+ * Ok(file with the profile in APP2 segments or an iCCP chunk) on success
+ * Err(error_message) on failure
+ *
+ * Any profile already present in `encoded` is replaced. In PNG the sRGB
+ * chunk is dropped as well, since it must not appear alongside iCCP.
+ */
+pub fn embed_icc_profile(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, Error> {
+    if profile.len() < MIN_PROFILE_LEN || &profile[36..40] != b"acsp" {
+        return Err(Error::invalid_parameter("Invalid ICC profile"));
+    }
+
+    match metadata::container(encoded) {
+        Some(Container::Jpeg) => embed_jpeg(encoded, profile),
+        Some(Container::Png) => embed_png(encoded, profile),
+        _ => Err(Error::unsupported("Encoded data is not a JPEG or PNG file")),
+    }
+}
+
+fn embed_jpeg(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, Error> {
+    let chunks: Vec<&[u8]> = profile.chunks(MAX_JPEG_CHUNK).collect();
+    if chunks.len() > 255 {
+        return Err(Error::invalid_parameter("ICC profile too large for JPEG"));
+    }
+    let blocks: Vec<(u8, Vec<u8>)> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| (0xE2, [ICC_HEADER, &[i as u8 + 1, chunks.len() as u8], chunk].concat()))
+        .collect();
+
+    let (segments, scan_start) = metadata::jpeg_segments(encoded)?;
+    let mut out = Vec::with_capacity(encoded.len() + profile.len() + chunks.len() * 18);
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut pending = Some(blocks);
+    for (marker, start, end) in segments {
+        // Conventionally placed after the JFIF and EXIF/XMP segments
+        if marker != 0xE0 && marker != 0xE1 {
+            metadata::write_segments(&mut out, pending.take());
+        }
+        if !(marker == 0xE2 && encoded[start + 4..end].starts_with(ICC_HEADER)) {
+            out.extend_from_slice(&encoded[start..end]);
+        }
+    }
+    metadata::write_segments(&mut out, pending.take());
+    out.extend_from_slice(&encoded[scan_start..]);
+    Ok(out)
+}
+
+fn embed_png(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, Error> {
+    // profile name, compression method 0 (zlib), compressed profile
+    let data = [PNG_PROFILE_NAME, b"\0\0", &metadata::deflate(profile)].concat();
+
+    let chunks = metadata::png_chunks(encoded)?;
+    let mut out = Vec::with_capacity(encoded.len() + data.len() + 12);
+    out.extend_from_slice(metadata::PNG_SIGNATURE);
+    let mut pending = Some(data);
+    for (kind, start, end) in chunks {
+        // iCCP must precede PLTE and IDAT
+        if &kind == b"PLTE" || &kind == b"IDAT" {
+            if let Some(data) = pending.take() {
+                metadata::write_chunk(&mut out, b"iCCP", &data);
+            }
+        }
+        if &kind != b"iCCP" && &kind != b"sRGB" {
+            out.extend_from_slice(&encoded[start..end]);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageDecoder;
+    use std::io::Cursor;
+
+    /// Header-only profile: enough for the containers, not for a CMS
+    fn fake_profile(len: usize) -> Vec<u8> {
+        let mut profile: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        profile[0..4].copy_from_slice(&(len as u32).to_be_bytes());
+        profile[36..40].copy_from_slice(b"acsp");
+        profile
+    }
+
+    #[test]
+    fn round_trips_through_jpeg_and_png_decoders() {
+        let rgba = vec![200u8; 16 * 16 * 4];
+
+        // Large enough to need two APP2 segments
+        let profile = fake_profile(100_000);
+        let encoded = crate::jpeg::compress_to_jpeg(&rgba, 16, 16, 80).unwrap();
+        let jpeg = embed_icc_profile(&encoded, &profile).unwrap();
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(&jpeg)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap(), Some(profile));
+
+        let profile = fake_profile(3000);
+        let encoded = crate::png::compress_to_png(&rgba, 16, 16, 50).unwrap();
+        let png = embed_icc_profile(&encoded, &profile).unwrap();
+        let mut decoder = image::codecs::png::PngDecoder::new(Cursor::new(&png)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap(), Some(profile));
+    }
+}
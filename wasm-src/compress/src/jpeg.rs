@@ -1,19 +1,42 @@
 use image::{ImageBuffer, RgbaImage, DynamicImage, ImageEncoder, EncodableLayout};
 use image::codecs::jpeg::JpegEncoder;
 use std::io::Cursor;
+use jpeg_encoder::{ChromaSubsamplingMethod, ColorType, Encoder, QuantizationTableType, SamplingFactor};
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+/**
+ * Chroma subsampling for JPEG encoding
+ *
+ * 4:4:4 keeps full color resolution, 4:2:2 halves it horizontally and
+ * 4:2:0 halves it in both directions.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    Yuv444 = 0,
+    Yuv422 = 1,
+    Yuv420 = 2,
+}
+
 /**
  * JPEG advanced compression parameters
  *
- * Note: The image crate's JpegEncoder has limited parameter support.
- * This structure provides a foundation for future enhancements with
- * more advanced JPEG encoders like jpeg-encoder.
+ * Custom quantization tables hold 64 values (1-255) in natural row-major
+ * order. When set they replace the quality-scaled standard tables, so the
+ * `quality` argument no longer affects the output. Restart markers use the
+ * standard Huffman tables, so `optimize` has no effect with them.
  */
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
 pub struct JpegAdvancedParams {
-    pub optimize: bool,        // Optimize Huffman tables (smaller file, slower encoding)
-    pub progressive: bool,     // Progressive JPEG (faster perceived loading)
+    pub optimize: bool,                        // Optimize Huffman tables (smaller file, slower encoding)
+    pub progressive: bool,                     // Progressive JPEG (faster perceived loading)
+    pub chroma_subsampling: ChromaSubsampling, // Chroma resolution
+    pub restart_interval: u16,                 // MCUs between restart markers, 0 disables them
+    pub luma_quant_table: Option<Vec<u16>>,    // Custom luma quantization table
+    pub chroma_quant_table: Option<Vec<u16>>,  // Custom chroma quantization table
 }
 
 impl Default for JpegAdvancedParams {
@@ -21,10 +44,22 @@ impl Default for JpegAdvancedParams {
         Self {
             optimize: true,
             progressive: false, // Default to non-progressive for compatibility
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+            restart_interval: 0,
+            luma_quant_table: None,
+            chroma_quant_table: None,
         }
     }
 }
 
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl JpegAdvancedParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> JpegAdvancedParams {
+        JpegAdvancedParams::default()
+    }
+}
+
 /**
  * Compress RGBA image data to JPEG format (basic)
  *
@@ -33,48 +68,46 @@ impl Default for JpegAdvancedParams {
  * * `width` - Image width in pixels
  * * `height` - Image height in pixels
  * * `quality` - JPEG quality (1-100)
- * * `output` - Output buffer for compressed JPEG data
  *
  * # Returns
- * Ok(number_of_bytes_written) on success
- * Err(error_message) on failure
+ * Ok(jpeg_bytes) on success
+ * Err(error) on failure
  */
 pub fn compress_to_jpeg(
     input: &[u8],
     width: u32,
     height: u32,
     quality: u8,
-    output: &mut [u8],
-) -> Result<usize, &'static str> {
-    web_sys::console::log_1(&format!("compress_to_jpeg: {}x{}, quality={}", width, height, quality).into());
+) -> Result<Vec<u8>, Error> {
+    log_debug!("compress_to_jpeg: {}x{}, quality={}", width, height, quality);
 
     // Create RGBA image from input buffer
     let rgba_image: RgbaImage =
         match ImageBuffer::from_raw(width, height, input.to_vec()) {
             Some(img) => {
-                web_sys::console::log_1(&"ImageBuffer created successfully".into());
+                log_debug!("ImageBuffer created successfully");
                 img
             },
             None => {
-                web_sys::console::log_1(&"Failed to create ImageBuffer".into());
-                return Err("Failed to create image buffer from input data");
+                log_error!("Failed to create ImageBuffer");
+                return Err(Error::input_size_mismatch("Failed to create image buffer from input data"));
             }
         };
 
     let dynamic_image = DynamicImage::ImageRgba8(rgba_image);
 
-    web_sys::console::log_1(&"Converting RGBA to RGB...".into());
+    log_debug!("Converting RGBA to RGB...");
 
     // Convert RGBA to RGB (JPEG doesn't support alpha channel)
     let rgb_image = dynamic_image.to_rgb8();
 
-    web_sys::console::log_1(&"Creating JPEG encoder...".into());
+    log_debug!("Creating JPEG encoder...");
 
     // Encode to JPEG with specified quality
     let mut buffer = Cursor::new(Vec::new());
-    let encoder = JpegEncoder::new_with_quality(&mut buffer, quality.into());
+    let encoder = JpegEncoder::new_with_quality(&mut buffer, quality);
 
-    web_sys::console::log_1(&"Encoding image...".into());
+    log_debug!("Encoding image...");
 
     match encoder.write_image(
         rgb_image.as_bytes(),
@@ -84,22 +117,12 @@ pub fn compress_to_jpeg(
     ) {
         Ok(_) => {
             let jpeg_data = buffer.into_inner();
-            web_sys::console::log_2(&"JPEG encoded successfully, size:".into(), &jpeg_data.len().into());
-
-            // Copy to output buffer
-            if jpeg_data.len() > output.len() {
-                web_sys::console::log_2(&"Output buffer too small".into(),
-                    &format!("needed {}, got {}", jpeg_data.len(), output.len()).into());
-                return Err("Output buffer too small for compressed data");
-            }
-
-            output[..jpeg_data.len()].copy_from_slice(&jpeg_data);
-            web_sys::console::log_2(&"Compression complete, copied".into(), &jpeg_data.len().into());
-            Ok(jpeg_data.len())
+            log_debug!("JPEG encoded successfully, size: {}", jpeg_data.len());
+            Ok(jpeg_data)
         }
         Err(e) => {
-            web_sys::console::log_1(&format!("JPEG encoding error: {:?}", e).into());
-            Err("JPEG encoding failed")
+            log_error!("JPEG encoding error: {:?}", e);
+            Err(Error::encode_failed("JPEG encoding failed"))
         }
     }
 }
@@ -113,14 +136,12 @@ pub fn compress_to_jpeg(
  * * `height` - Image height in pixels
  * * `quality` - JPEG quality (1-100)
  * * `params` - Advanced encoding parameters
- * * `output` - Output buffer for compressed JPEG data
  *
  * # Returns
- * Ok(number_of_bytes_written) on success
- * Err(error_message) on failure
+ * Ok(jpeg_bytes) on success
+ * Err(error) on failure
  *
- * Note: Current implementation uses the image crate which has limited support
- * for advanced parameters. The params are logged for future enhancement.
+ * Chroma is box-averaged when subsampled, like libjpeg. Alpha is dropped.
  */
 pub fn compress_to_jpeg_advanced(
     input: &[u8],
@@ -128,22 +149,61 @@ pub fn compress_to_jpeg_advanced(
     height: u32,
     quality: u8,
     params: &JpegAdvancedParams,
-    output: &mut [u8],
-) -> Result<usize, &'static str> {
-    web_sys::console::log_1(&"compress_to_jpeg_advanced: called with params".into());
-    web_sys::console::log_1(&format!("JPEG params - optimize: {}, progressive: {}",
-        params.optimize, params.progressive).into());
-
-    // For now, use basic compression (image crate doesn't expose advanced params)
-    // Future enhancement: Switch to jpeg-encoder crate for more control
-    let result = compress_to_jpeg(input, width, height, quality, output);
-
-    // Log note about advanced parameter support
-    if params.optimize || params.progressive {
-        web_sys::console::log_1(&"Note: optimize and progressive parameters logged but not applied (limited by image crate)".into());
+) -> Result<Vec<u8>, Error> {
+    log_debug!("compress_to_jpeg_advanced: {}x{}, quality={}", width, height, quality);
+    log_debug!(
+        "JPEG params - optimize: {}, progressive: {}, subsampling: {:?}, restart_interval: {}",
+        params.optimize, params.progressive, params.chroma_subsampling, params.restart_interval
+    );
+
+    if input.len() != (width as usize) * (height as usize) * 4 {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
     }
+    let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(Error::invalid_dimensions("Image dimensions exceed the JPEG limit of 65535 pixels"));
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = Encoder::new(&mut buffer, quality.clamp(1, 100));
+    // jpeg-encoder gathers optimized Huffman statistics without the DC
+    // predictor resets at restart markers, so the two can't be combined
+    encoder.set_optimized_huffman_tables(params.optimize && params.restart_interval == 0);
+    encoder.set_progressive(params.progressive);
+    encoder.set_restart_interval(params.restart_interval);
+    encoder.set_sampling_factor(match params.chroma_subsampling {
+        ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+    });
+    encoder.set_chroma_subsampling_method(ChromaSubsamplingMethod::Average);
 
-    result
+    if params.luma_quant_table.is_some() || params.chroma_quant_table.is_some() {
+        let luma = quant_table(params.luma_quant_table.as_deref())?;
+        let chroma = quant_table(params.chroma_quant_table.as_deref())?;
+        encoder.set_quantization_tables(luma, chroma);
+    }
+
+    let rgb: Vec<u8> = input.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+    encoder.encode(&rgb, w, h, ColorType::Rgb).map_err(|e| {
+        log_error!("JPEG encoding error: {:?}", e);
+        Error::encode_failed("JPEG encoding failed")
+    })?;
+    log_debug!("JPEG encoded successfully, size: {}", buffer.len());
+    Ok(buffer)
+}
+
+/// A user table, or the standard Annex K table when only the other one is set
+fn quant_table(table: Option<&[u16]>) -> Result<QuantizationTableType, Error> {
+    let Some(table) = table else {
+        return Ok(QuantizationTableType::Default);
+    };
+    let table: [u16; 64] = table
+        .try_into()
+        .map_err(|_| Error::invalid_parameter("Quantization tables must have 64 entries"))?;
+    if table.iter().any(|&q| !(1..=255).contains(&q)) {
+        return Err(Error::invalid_parameter("Quantization table values must be between 1 and 255"));
+    }
+    Ok(QuantizationTableType::Custom(Box::new(table)))
 }
 
 #[cfg(test)]
@@ -160,30 +220,134 @@ mod tests {
             255, 255, 0, 255, // Yellow pixel
         ];
 
-        let mut output = vec![0u8; 4096];
-        let result = compress_to_jpeg(&rgba_data, 2, 2, 80, &mut output);
+        let result = compress_to_jpeg(&rgba_data, 2, 2, 80);
 
         assert!(result.is_ok());
-        assert!(result.unwrap() > 0);
+        assert!(!result.unwrap().is_empty());
     }
 
     #[test]
     fn test_compress_to_jpeg_quality_range() {
         let rgba_data = vec![255u8; 16]; // 2x2 white image
-        let mut output = vec![0u8; 4096];
 
         // Test valid quality values
         for quality in [1, 50, 100] {
-            let mut output = vec![0u8; 4096];
-            let result = compress_to_jpeg(&rgba_data, 2, 2, quality, &mut output);
+            let result = compress_to_jpeg(&rgba_data, 2, 2, quality);
             assert!(result.is_ok());
         }
     }
 
     #[test]
+    fn test_compress_to_jpeg_reports_size_mismatch() {
+        let rgba_data = vec![255u8; 12];
+
+        let error = compress_to_jpeg(&rgba_data, 2, 2, 80).unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::InputSizeMismatch);
+    }
+
+    #[test]
+    fn test_compress_to_jpeg_quality_loss_is_measured() {
+        let rgba_data: Vec<u8> = (0..64 * 48u32)
+            .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 5) as u8, (i * 13 % 97) as u8, 255])
+            .collect();
+
+        let high = compress_to_jpeg(&rgba_data, 64, 48, 90).unwrap();
+        let high = crate::metrics::compare_encoded(&rgba_data, &high, 64, 48, false).unwrap();
+        let low = compress_to_jpeg(&rgba_data, 64, 48, 20).unwrap();
+        let low = crate::metrics::compare_encoded(&rgba_data, &low, 64, 48, false).unwrap();
+
+        assert!(high.psnr > 30.0 && high.ssim > 0.9);
+        assert!(low.psnr < high.psnr && low.ssim < high.ssim && low.ms_ssim < high.ms_ssim);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_jpeg_advanced_params_default() {
         let params = JpegAdvancedParams::default();
         assert_eq!(params.optimize, true);
         assert_eq!(params.progressive, false);
+        assert_eq!(params.chroma_subsampling, ChromaSubsampling::Yuv420);
+        assert_eq!(params.restart_interval, 0);
+        assert!(params.luma_quant_table.is_none() && params.chroma_quant_table.is_none());
+    }
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 255 / width) as u8, (y * 255 / height) as u8, 128, 255]
+            })
+            .collect()
+    }
+
+    /// Segment payload following the first occurrence of `marker`
+    fn segment(jpeg: &[u8], marker: u8) -> Option<&[u8]> {
+        let pos = jpeg.windows(2).position(|w| w == [0xFF, marker])?;
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        Some(&jpeg[pos + 4..pos + 2 + len])
+    }
+
+    #[test]
+    fn test_jpeg_advanced_params_are_written_to_the_stream() {
+        let rgba = gradient(64, 48);
+
+        let params = JpegAdvancedParams {
+            progressive: true,
+            chroma_subsampling: ChromaSubsampling::Yuv422,
+            restart_interval: 4,
+            ..JpegAdvancedParams::default()
+        };
+        let jpeg = &compress_to_jpeg_advanced(&rgba, 64, 48, 85, &params).unwrap();
+
+        // SOF2: precision, height, width, components, then id + sampling of Y
+        let sof = segment(jpeg, 0xC2).expect("progressive frame header");
+        assert_eq!(sof[5], 3);
+        assert_eq!(sof[7], 0x21);
+        assert_eq!(segment(jpeg, 0xDD).unwrap(), [0, 4]);
+
+        let decoded = image::load_from_memory(jpeg).unwrap().into_rgb8();
+        assert_eq!(decoded.dimensions(), (64, 48));
+
+        let optimized_progressive = JpegAdvancedParams {
+            progressive: true,
+            ..JpegAdvancedParams::default()
+        };
+        let jpeg = compress_to_jpeg_advanced(&rgba, 64, 48, 85, &optimized_progressive).unwrap();
+        assert!(image::load_from_memory(&jpeg).is_ok());
+
+        // Optimized Huffman tables only ever shrink the baseline output
+        let baseline = JpegAdvancedParams {
+            optimize: false,
+            ..JpegAdvancedParams::default()
+        };
+        let optimized = compress_to_jpeg_advanced(&rgba, 64, 48, 85, &JpegAdvancedParams::default()).unwrap();
+        let plain = compress_to_jpeg_advanced(&rgba, 64, 48, 85, &baseline).unwrap();
+        assert!(optimized.len() < plain.len());
+    }
+
+    #[test]
+    fn test_jpeg_custom_quantization_tables() {
+        let rgba = gradient(32, 32);
+        let params = JpegAdvancedParams {
+            luma_quant_table: Some(vec![3; 64]),
+            chroma_quant_table: Some(vec![9; 64]),
+            ..JpegAdvancedParams::default()
+        };
+        let jpeg = &compress_to_jpeg_advanced(&rgba, 32, 32, 10, &params).unwrap();
+
+        let tables: Vec<&[u8]> = jpeg
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| *w == [0xFF, 0xDB])
+            .map(|(pos, _)| &jpeg[pos + 4..])
+            .collect();
+        let has_table = |value: u8| tables.iter().any(|t| t[1..65].iter().all(|&q| q == value));
+        assert!(has_table(3) && has_table(9));
+
+        let short = JpegAdvancedParams {
+            luma_quant_table: Some(vec![3; 63]),
+            ..JpegAdvancedParams::default()
+        };
+        assert!(compress_to_jpeg_advanced(&rgba, 32, 32, 80, &short).is_err());
     }
 }
@@ -1,262 +1,353 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+/// Log at a level set with set_log_level; arguments aren't formatted when
+/// the level is filtered out
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {{
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, format_args!($($arg)*));
+        }
+    }};
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Error, $($arg)*) };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Info, $($arg)*) };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Debug, $($arg)*) };
+}
+
+mod logging;
 mod jpeg;
 mod webp;
 mod png;
+mod avif;
+mod quantize;
+mod optimize;
 mod binary_search;
+mod metrics;
+mod exif;
+mod icc;
+mod metadata;
+mod vp8;
+mod vp8_tables;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use photo_editor_common::{Error, ErrorCode, Image, Progress, Result};
 
 // Export advanced parameters
-pub use jpeg::JpegAdvancedParams;
+pub use jpeg::{ChromaSubsampling, JpegAdvancedParams};
 pub use webp::WebPAdvancedParams;
+pub use avif::AvifAdvancedParams;
+pub use png::PngAdvancedParams;
+
+// Export metadata types
+pub use metadata::{MetadataInfo, MetadataOptions, MetadataPolicy};
+
+// Export quality metrics
+pub use metrics::QualityMetrics;
+
+// Export log levels
+pub use logging::LogLevel;
+
+// How compress_to_size reached its target
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeStrategy {
+    Quality = 0,  // Encoder quality search (JPEG, WebP, AVIF)
+    Lossless = 1, // Lossless PNG already fit
+    Palette = 2,  // Quantized PNG, palette size search
+}
 
 // Export compression result struct
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
 pub struct CompressionResult {
-    pub size: usize,
-    pub quality: u8,
+    pub data: Vec<u8>,          // Encoded file (each read from JS copies it)
+    pub size: usize,            // data.len()
+    pub quality: u8,            // Encoder quality, or PNG compression level
+    pub strategy: SizeStrategy,
+    pub colors: u16,            // Palette size for SizeStrategy::Palette, otherwise 0
+    pub width: u32,             // Dimensions of the encoded image (smaller if downscaled)
+    pub height: u32,
+    pub ssim: f64,              // SSIM against the input for compress_to_quality_target, otherwise 0
 }
 
 // Compression format enum
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone, Copy)]
 pub enum CompressionFormat {
     Jpeg,
     WebP,
     Png,
+    Avif,
 }
 
-/**
- * Compress RGBA image data to JPEG format
- *
- * # Arguments
- * * `input` - RGBA image data (4 bytes per pixel, row-major order)
- * * `width` - Image width in pixels
- * * `height` - Image height in pixels
- * * `quality` - JPEG quality (1-100)
- * * `output` - Output buffer (pre-allocated, same size as input)
- *
- * # Returns
- * Number of bytes written to output buffer
- */
-#[wasm_bindgen]
-pub fn compress_jpeg(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    quality: u8,
-    output: &mut [u8],
-) -> usize {
-    // Validate inputs
-    if input.is_empty() || width == 0 || height == 0 {
-        web_sys::console::log_1(&"Validation failed: empty input or zero dimensions".into());
-        return 0;
-    }
 
-    let expected_size = (width * height * 4) as usize;
-    if input.len() != expected_size {
-        web_sys::console::log_2(&"Input size mismatch".into(),
-            &format!("expected {}, got {}", expected_size, input.len()).into());
-        return 0;
-    }
+// Native API: the algorithms and checks behind the JavaScript exports in
+// wasm.rs. Inputs are validated `Image`s, so only parameters are checked here.
 
-    if quality < 1 || quality > 100 {
-        web_sys::console::log_2(&"Quality out of range".into(), &quality.into());
-        return 0;
+fn validate_quality(quality: u8) -> Result<()> {
+    if !(1..=100).contains(&quality) {
+        return Err(Error::invalid_parameter(format!("Quality out of range {}", quality)));
     }
+    Ok(())
+}
 
-    if output.len() < input.len() {
-        web_sys::console::log_2(&"Output buffer too small".into(),
-            &format!("output: {}, input: {}", output.len(), input.len()).into());
-        return 0;
-    }
+/// Encode `image` as JPEG at `quality` (1-100).
+pub fn compress_jpeg(image: &Image, quality: u8) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_jpeg");
+    validate_quality(quality)?;
 
-    web_sys::console::log_1(&"Calling JPEG compression...".into());
-
-    // Call JPEG compression
-    match jpeg::compress_to_jpeg(input, width, height, quality, output) {
-        Ok(size) => {
-            web_sys::console::log_2(&"JPEG compression success".into(), &size.into());
-            size
-        },
-        Err(e) => {
-            web_sys::console::log_2(&"JPEG compression error".into(), &e.into());
-            0
-        }
-    }
+    log_debug!("Calling JPEG compression...");
+    let encoded = jpeg::compress_to_jpeg(image.data(), image.width(), image.height(), quality)?;
+    log_info!("JPEG compression success {}", encoded.len());
+    Ok(encoded)
 }
 
-/**
- * Compress RGBA image data to WebP format
- *
- * # Arguments
- * * `input` - RGBA image data (4 bytes per pixel, row-major order)
- * * `width` - Image width in pixels
- * * `height` - Image height in pixels
- * * `quality` - WebP quality (1-100)
- * * `output` - Output buffer (pre-allocated, same size as input)
- *
- * # Returns
- * Number of bytes written to output buffer
- */
-#[wasm_bindgen]
-pub fn compress_webp(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    quality: u8,
-    output: &mut [u8],
-) -> usize {
-    // Validate inputs
-    if input.is_empty() || width == 0 || height == 0 {
-        web_sys::console::log_1(&"WebP compression: Validation failed - empty input or zero dimensions".into());
-        return 0;
-    }
+/// Encode `image` as JPEG with progressive scans, chroma subsampling and
+/// the other encoder options of `params`.
+pub fn compress_jpeg_advanced(image: &Image, quality: u8, params: &JpegAdvancedParams) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_jpeg_advanced");
+    validate_quality(quality)?;
 
-    let expected_size = (width * height * 4) as usize;
-    if input.len() != expected_size {
-        web_sys::console::log_2(&"WebP compression: Input size mismatch".into(),
-            &format!("expected {}, got {}", expected_size, input.len()).into());
-        return 0;
-    }
+    log_debug!("Calling advanced JPEG compression...");
+    let encoded = jpeg::compress_to_jpeg_advanced(image.data(), image.width(), image.height(), quality, params)?;
+    log_info!("JPEG compression success {}", encoded.len());
+    Ok(encoded)
+}
 
-    if quality < 1 || quality > 100 {
-        web_sys::console::log_2(&"WebP compression: Quality out of range".into(), &quality.into());
-        return 0;
-    }
+/// Encode `image` as lossy WebP at `quality` (1-100).
+pub fn compress_webp(image: &Image, quality: u8) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_webp");
+    validate_quality(quality)?;
 
-    if output.len() < input.len() {
-        web_sys::console::log_2(&"WebP compression: Output buffer too small".into(),
-            &format!("output: {}, input: {}", output.len(), input.len()).into());
-        return 0;
-    }
+    log_debug!("Calling WebP compression...");
+    let encoded = webp::compress_to_webp(image.data(), image.width(), image.height(), quality)?;
+    log_info!("WebP compression success {}", encoded.len());
+    Ok(encoded)
+}
 
-    web_sys::console::log_1(&"Calling WebP compression...".into());
-
-    // Call WebP compression
-    match webp::compress_to_webp(input, width, height, quality, output) {
-        Ok(size) => {
-            web_sys::console::log_2(&"WebP compression success".into(), &size.into());
-            size
-        },
-        Err(e) => {
-            web_sys::console::log_2(&"WebP compression error".into(), &e.into());
-            0
-        }
+/// Encode `image` as WebP with the method, filtering and lossless mode of `params`.
+pub fn compress_webp_advanced(image: &Image, quality: u8, params: &WebPAdvancedParams) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_webp_advanced");
+    validate_quality(quality)?;
+    if params.method > 6 {
+        return Err(Error::invalid_parameter(format!("WebP method out of range {}", params.method)));
     }
+
+    log_debug!("Calling advanced WebP compression...");
+    let encoded = webp::compress_to_webp_advanced(image.data(), image.width(), image.height(), quality, params)?;
+    log_info!("WebP compression success {}", encoded.len());
+    Ok(encoded)
 }
 
-/**
- * Compress RGBA image data to PNG format
- *
- * Note: PNG is lossless compression. The quality parameter controls
- * compression level (Fast/Default/High/Best) for trade-off between size and speed.
- *
- * # Arguments
- * * `input` - RGBA image data (4 bytes per pixel, row-major order)
- * * `width` - Image width in pixels
- * * `height` - Image height in pixels
- * * `quality` - PNG compression level hint (1-100, maps to compression type)
- * * `output` - Output buffer (pre-allocated, same size as input)
- *
- * # Returns
- * Number of bytes written to output buffer
- */
-#[wasm_bindgen]
-pub fn compress_png(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    quality: u8,
-    output: &mut [u8],
-) -> usize {
-    // Validate inputs
-    if input.is_empty() || width == 0 || height == 0 {
-        web_sys::console::log_1(&"PNG compression: Validation failed - empty input or zero dimensions".into());
-        return 0;
-    }
+/// Encode `image` as AVIF at `quality` (1-100).
+pub fn compress_avif(image: &Image, quality: u8) -> Result<Vec<u8>> {
+    compress_avif_advanced(image, quality, &AvifAdvancedParams::default())
+}
 
-    let expected_size = (width * height * 4) as usize;
-    if input.len() != expected_size {
-        web_sys::console::log_2(&"PNG compression: Input size mismatch".into(),
-            &format!("expected {}, got {}", expected_size, input.len()).into());
-        return 0;
+/// Encode `image` as AVIF with the encoder speed and alpha quality of `params`.
+pub fn compress_avif_advanced(image: &Image, quality: u8, params: &AvifAdvancedParams) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_avif_advanced");
+    validate_quality(quality)?;
+    if !(1..=10).contains(&params.speed) || !(1..=100).contains(&params.alpha_quality) {
+        return Err(Error::invalid_parameter(format!(
+            "AVIF params out of range - speed: {}, alpha_quality: {}",
+            params.speed, params.alpha_quality
+        )));
     }
 
-    if quality < 1 || quality > 100 {
-        web_sys::console::log_2(&"PNG compression: Quality out of range".into(), &quality.into());
-        return 0;
-    }
+    log_debug!("Calling AVIF compression...");
+    let encoded = avif::compress_to_avif_advanced(image.data(), image.width(), image.height(), quality, params)?;
+    log_info!("AVIF compression success {}", encoded.len());
+    Ok(encoded)
+}
+
+/// Encode `image` as lossless PNG; `quality` (1-100) picks the compression level.
+pub fn compress_png(image: &Image, quality: u8) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_png");
+    validate_quality(quality)?;
 
-    if output.len() < input.len() {
-        web_sys::console::log_2(&"PNG compression: Output buffer too small".into(),
-            &format!("output: {}, input: {}", output.len(), input.len()).into());
-        return 0;
+    log_debug!("Calling PNG compression...");
+    let encoded = png::compress_to_png(image.data(), image.width(), image.height(), quality)?;
+    log_info!("PNG compression success {}", encoded.len());
+    Ok(encoded)
+}
+
+/// Encode `image` as PNG, palette-quantized if `params` asks for it.
+pub fn compress_png_advanced(image: &Image, quality: u8, params: &PngAdvancedParams) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_png_advanced");
+    validate_quality(quality)?;
+    if !(2..=256).contains(&params.max_colors) {
+        return Err(Error::invalid_parameter(format!("Palette size out of range {}", params.max_colors)));
     }
 
-    web_sys::console::log_1(&"Calling PNG compression...".into());
-
-    // Call PNG compression
-    match png::compress_to_png(input, width, height, quality, output) {
-        Ok(size) => {
-            web_sys::console::log_2(&"PNG compression success".into(), &size.into());
-            size
-        },
-        Err(e) => {
-            web_sys::console::log_2(&"PNG compression error".into(), &e.into());
-            0
+    log_debug!("Calling advanced PNG compression...");
+    let encoded = png::compress_to_png_advanced(image.data(), image.width(), image.height(), quality, params)?;
+    log_info!("PNG compression success {}", encoded.len());
+    Ok(encoded)
+}
+
+/// Encode 16-bit pixels: PNG keeps all 16 bits, the other formats are
+/// narrowed to 8 bits first, optionally dithered.
+pub fn compress_16(image: &Image<u16>, format: CompressionFormat, quality: u8, dither: bool) -> Result<Vec<u8>> {
+    let _span = logging::span("compress_16");
+    validate_quality(quality)?;
+
+    let (input, width, height) = (image.data(), image.width(), image.height());
+    let encoded = match format {
+        CompressionFormat::Png => png::compress_to_png_16(input, width, height, quality)?,
+        CompressionFormat::Jpeg | CompressionFormat::WebP | CompressionFormat::Avif => {
+            let mut narrowed = vec![0u8; input.len()];
+            photo_editor_common::depth::rgba16_to_rgba8(input, dither, &mut narrowed);
+            match format {
+                CompressionFormat::Jpeg => jpeg::compress_to_jpeg(&narrowed, width, height, quality)?,
+                CompressionFormat::Avif => avif::compress_to_avif(&narrowed, width, height, quality)?,
+                _ => webp::compress_to_webp(&narrowed, width, height, quality)?,
+            }
         }
-    }
+    };
+    Ok(encoded)
 }
 
-/**
- * Compress image to target file size using binary search
- *
- * Uses binary search to find the optimal quality parameter that
- * produces a compressed image close to the target file size.
- *
- * # Arguments
- * * `input` - RGBA image data (4 bytes per pixel, row-major order)
- * * `width` - Image width in pixels
- * * `height` - Image height in pixels
- * * `target_size` - Target file size in bytes
- * * `format` - Compression format (Jpeg, WebP, or Png)
- * * `output` - Output buffer (pre-allocated, same size as input)
- *
- * # Returns
- * CompressionResult containing actual size and quality used
- */
-#[wasm_bindgen]
+/// Search quality (PNG: lossless, then palette size) for a file close to
+/// `target_size` bytes. Fails with `ErrorCode::Cancelled` once `progress`
+/// asks to stop.
 pub fn compress_to_size(
-    input: &[u8],
-    width: u32,
-    height: u32,
+    image: &Image,
     target_size: usize,
     format: CompressionFormat,
-    output: &mut [u8],
-) -> CompressionResult {
-    // Validate inputs
-    if input.is_empty() || width == 0 || height == 0 || target_size == 0 {
-        return CompressionResult {
-            size: 0,
-            quality: 0,
-        };
+    progress: &mut Progress,
+) -> Result<CompressionResult> {
+    let _span = logging::span("compress_to_size");
+    if target_size == 0 {
+        return Err(Error::invalid_parameter("Target size must be > 0"));
     }
 
-    let expected_size = (width * height * 4) as usize;
-    if input.len() != expected_size {
-        return CompressionResult {
-            size: 0,
-            quality: 0,
-        };
+    // Use binary search to find optimal quality
+    let result = binary_search::find_optimal_quality(
+        image.data(), image.width(), image.height(), target_size, format, progress,
+    )?;
+    if result.size == 0 {
+        return Err(Error::encode_failed("Target size compression failed"));
     }
+    log_info!("Target size {}: {} bytes at quality {}", target_size, result.size, result.quality);
+    Ok(result)
+}
 
-    if output.len() < input.len() {
-        return CompressionResult {
-            size: 0,
-            quality: 0,
-        };
+/// Like `compress_to_size`, but never below `min_quality` (1-100); the
+/// image is downscaled instead when that still misses the target.
+pub fn compress_to_size_with_downscale(
+    image: &Image,
+    target_size: usize,
+    format: CompressionFormat,
+    min_quality: u8,
+    progress: &mut Progress,
+) -> Result<CompressionResult> {
+    let _span = logging::span("compress_to_size_with_downscale");
+    if target_size == 0 {
+        return Err(Error::invalid_parameter("Target size must be > 0"));
+    }
+    if !(1..=100).contains(&min_quality) {
+        return Err(Error::invalid_parameter(format!("Minimum quality out of range {}", min_quality)));
     }
 
-    // Use binary search to find optimal quality
-    binary_search::find_optimal_quality(input, width, height, target_size, format, output)
+    let result = binary_search::find_optimal_size_with_downscale(
+        image.data(), image.width(), image.height(), target_size, format, min_quality, progress,
+    )?;
+    if result.size == 0 {
+        return Err(Error::encode_failed("Target size compression failed"));
+    }
+    log_info!(
+        "Target size {}: {} bytes at quality {}, {}x{}",
+        target_size, result.size, result.quality, result.width, result.height
+    );
+    Ok(result)
+}
+
+/// Smallest file whose decoded pixels keep at least `min_ssim` (0-1]
+/// structural similarity with `image`.
+pub fn compress_to_quality_target(image: &Image, format: CompressionFormat, min_ssim: f64) -> Result<CompressionResult> {
+    let _span = logging::span("compress_to_quality_target");
+    if !(min_ssim > 0.0 && min_ssim <= 1.0) {
+        return Err(Error::invalid_parameter(format!("SSIM out of range {}", min_ssim)));
+    }
+
+    let result = binary_search::find_quality_for_ssim(image.data(), image.width(), image.height(), format, min_ssim)?;
+    log_info!("SSIM target {}: {} bytes at quality {} (SSIM {:.4})", min_ssim, result.size, result.quality, result.ssim);
+    Ok(result)
+}
+
+/// PSNR, SSIM and MS-SSIM between two images of the same size, plus a
+/// difference heatmap if asked for.
+pub fn compare_images(reference: &Image, distorted: &Image, heatmap: bool) -> Result<QualityMetrics> {
+    if (reference.width(), reference.height()) != (distorted.width(), distorted.height()) {
+        return Err(Error::input_size_mismatch("Images have different dimensions"));
+    }
+    metrics::compare(reference.data(), distorted.data(), reference.width(), reference.height(), heatmap)
+}
+
+/// Quality metrics of a compressed file against the original pixels.
+pub fn compare_encoded(reference: &Image, encoded: &[u8], heatmap: bool) -> Result<QualityMetrics> {
+    metrics::compare_encoded(reference.data(), encoded, reference.width(), reference.height(), heatmap)
+}
+
+/// EXIF/XMP/IPTC metadata found in a JPEG, PNG or WebP file.
+pub fn read_metadata(bytes: &[u8]) -> MetadataInfo {
+    metadata::Metadata::read(bytes).info()
+}
+
+/// Copy the metadata of `source` into an encoded JPEG or PNG, as `options` allows.
+pub fn apply_metadata(encoded: &[u8], source: &[u8], options: &MetadataOptions) -> Result<Vec<u8>> {
+    metadata::apply_metadata(encoded, source, options)
+}
+
+/// Losslessly shrink a PNG file; returns the input when it can't be made smaller.
+pub fn optimize_png(bytes: &[u8]) -> Result<Vec<u8>> {
+    let _span = logging::span("optimize_png");
+    optimize::optimize_png(bytes)
+}
+
+/// Embed an ICC profile into an encoded JPEG or PNG.
+pub fn embed_icc_profile(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    icc::embed_icc_profile(encoded, profile)
+}
+
+/// Messages go to the `log` crate, or to the browser console from the wasm exports.
+pub fn set_log_level(level: LogLevel) {
+    logging::set_level(level);
+}
+
+pub fn set_log_timings(enabled: bool) {
+    logging::set_timings(enabled);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_api_encodes_and_scores_images() {
+        let data = (0..32u32 * 24).flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 10) as u8, 128, 255]).collect();
+        let image = Image::new(32, 24, data).unwrap();
+
+        let encoded = compress_jpeg(&image, 90).unwrap();
+        let metrics = compare_encoded(&image, &encoded, false).unwrap();
+        assert!(metrics.ssim > 0.9);
+
+        let result = compress_to_size(&image, encoded.len(), CompressionFormat::WebP, &mut Progress::none()).unwrap();
+        assert!(result.size <= encoded.len());
+
+        assert_eq!(compress_png(&image, 0).unwrap_err().code(), ErrorCode::InvalidParameter);
+        let small = Image::new(1, 1, vec![0; 4]).unwrap();
+        assert_eq!(compare_images(&image, &small, false).unwrap_err().code(), ErrorCode::InputSizeMismatch);
+    }
 }
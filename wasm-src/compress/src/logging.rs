@@ -0,0 +1,117 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// How much the compress module logs
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off = 0,   // Nothing
+    Error = 1, // Encoder failures
+    Info = 2,  // One line per exported call
+    Debug = 3, // Encoder steps and every search attempt
+}
+
+// Quiet by default: only failures are reported
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Error as u8);
+static TIMINGS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Off,
+        1 => LogLevel::Error,
+        2 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+/// Whether messages at `level` are written; checked before formatting
+pub fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level <= self::level()
+}
+
+pub fn set_timings(enabled: bool) {
+    TIMINGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Write to the browser console from the wasm exports, otherwise to the
+/// `log` crate
+pub fn write(level: LogLevel, message: fmt::Arguments) {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    {
+        let text = JsValue::from(message.to_string());
+        match level {
+            LogLevel::Off => {}
+            LogLevel::Error => web_sys::console::error_1(&text),
+            LogLevel::Info => web_sys::console::info_1(&text),
+            LogLevel::Debug => web_sys::console::debug_1(&text),
+        }
+    }
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    match level {
+        LogLevel::Off => {}
+        LogLevel::Error => log::error!("{}", message),
+        LogLevel::Info => log::info!("{}", message),
+        LogLevel::Debug => log::debug!("{}", message),
+    }
+}
+
+/// Milliseconds from an arbitrary origin
+fn now() -> f64 {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    {
+        use std::sync::OnceLock;
+        use std::time::Instant;
+
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+/// Logs how long it lived when dropped, if timings are enabled
+pub struct Span {
+    name: &'static str,
+    start: Option<f64>,
+}
+
+pub fn span(name: &'static str) -> Span {
+    let start = TIMINGS.load(Ordering::Relaxed).then(now);
+    Span { name, start }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(start) = self.start else { return };
+        if level() != LogLevel::Off {
+            write(LogLevel::Info, format_args!("{} took {:.1} ms", self.name, now() - start));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_filter_less_important_messages() {
+        set_level(LogLevel::Info);
+        assert!(enabled(LogLevel::Error) && enabled(LogLevel::Info));
+        assert!(!enabled(LogLevel::Debug) && !enabled(LogLevel::Off));
+
+        set_level(LogLevel::Off);
+        assert!(!enabled(LogLevel::Error));
+
+        set_level(LogLevel::Error);
+        assert_eq!(level(), LogLevel::Error);
+    }
+}
@@ -0,0 +1,891 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::exif::{self, Exif};
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+const RAW_PROFILE_EXIF: &str = "Raw profile type exif";
+const RAW_PROFILE_IPTC: &str = "Raw profile type iptc";
+
+// Photoshop image resource IDs
+const RESOURCE_IPTC: u16 = 0x0404;
+const RESOURCE_IPTC_DIGEST: u16 = 0x0425;
+
+// IPTC IIM datasets (record 2)
+const IPTC_BYLINE: u8 = 80;
+const IPTC_COPYRIGHT: u8 = 116;
+const IPTC_CAPTION: u8 = 120;
+const IPTC_LOCATION: [u8; 7] = [
+    26,  // Content Location Code
+    27,  // Content Location Name
+    90,  // City
+    92,  // Sub-location
+    95,  // Province/State
+    100, // Country Code
+    101, // Country Name
+];
+
+/// Marker segment as (marker, start, end)
+pub(crate) type Segment = (u8, usize, usize);
+
+/// PNG chunk as (type, start, end)
+pub(crate) type Chunk = ([u8; 4], usize, usize);
+
+// Largest payload that fits a single JPEG marker segment
+const MAX_SEGMENT_PAYLOAD: usize = 0xFFFF - 2;
+
+/**
+ * What to do with the source file's metadata on export
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataPolicy {
+    PreserveAll = 0,
+    StripAll = 1,
+    StripLocation = 2,
+    PreserveWithEdits = 3,
+}
+
+/**
+ * Export metadata options
+ *
+ * `artist`, `copyright` and `description` are only applied with
+ * `MetadataPolicy::PreserveWithEdits`; they are written to EXIF and to any
+ * XMP/IPTC block carried over from the source.
+ *
+ * `upright` says whether the exported pixels were already rotated to the
+ * source's EXIF Orientation (`decode_image` with `auto_orient`). Only then
+ * is the Orientation tag reset to 1; otherwise it is kept so viewers still
+ * rotate the raw pixels. Defaults to true.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct MetadataOptions {
+    pub policy: MetadataPolicy,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    pub description: Option<String>,
+    pub upright: bool,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MetadataOptions {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(policy: MetadataPolicy) -> MetadataOptions {
+        MetadataOptions {
+            policy,
+            artist: None,
+            copyright: None,
+            description: None,
+            upright: true,
+        }
+    }
+}
+
+/**
+ * Summary of the metadata found in an image file
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug, Default)]
+pub struct MetadataInfo {
+    pub has_exif: bool,
+    pub has_xmp: bool,
+    pub has_iptc: bool,
+    pub has_location: bool,
+    pub orientation: u16,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub date_taken: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    pub description: Option<String>,
+    pub xmp: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Container {
+    Jpeg,
+    Png,
+    WebP,
+}
+
+pub(crate) fn container(bytes: &[u8]) -> Option<Container> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        Some(Container::Jpeg)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        Some(Container::Png)
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(Container::WebP)
+    } else {
+        None
+    }
+}
+
+/**
+ * Metadata blocks carried from a source file to an export
+ *
+ * IPTC is kept as raw IIM datasets. Other Photoshop image resources found
+ * next to it in a JPEG are kept verbatim so they survive a JPEG round trip.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    pub exif: Option<Exif>,
+    pub xmp: Option<String>,
+    pub iptc: Option<Vec<u8>>,
+    photoshop: Vec<u8>,
+}
+
+impl Metadata {
+    /**
+     * Read metadata from a JPEG, PNG or WebP file
+     *
+     * Reading is best-effort: unknown containers and malformed blocks
+     * yield no metadata rather than an error.
+     */
+    pub fn read(bytes: &[u8]) -> Metadata {
+        let mut metadata = Metadata::default();
+        match container(bytes) {
+            Some(Container::Jpeg) => metadata.read_jpeg(bytes),
+            Some(Container::Png) => metadata.read_png(bytes),
+            Some(Container::WebP) => metadata.read_webp(bytes),
+            None => {}
+        }
+        metadata
+    }
+
+    fn read_jpeg(&mut self, bytes: &[u8]) {
+        let Ok((segments, _)) = jpeg_segments(bytes) else {
+            return;
+        };
+
+        let mut resources = Vec::new();
+        for (marker, start, end) in segments {
+            let payload = &bytes[start + 4..end];
+            match marker {
+                0xE1 if payload.starts_with(EXIF_HEADER) && self.exif.is_none() => {
+                    self.exif = Exif::parse(&payload[EXIF_HEADER.len()..]);
+                }
+                0xE1 if payload.starts_with(XMP_HEADER) && self.xmp.is_none() => {
+                    self.xmp = Some(String::from_utf8_lossy(&payload[XMP_HEADER.len()..]).into_owned());
+                }
+                // Resource blocks may be split across consecutive APP13 segments
+                0xED if payload.starts_with(PHOTOSHOP_HEADER) => {
+                    resources.extend_from_slice(&payload[PHOTOSHOP_HEADER.len()..]);
+                }
+                _ => {}
+            }
+        }
+
+        let (iptc, others) = split_resources(&resources);
+        self.iptc = iptc;
+        self.photoshop = others;
+    }
+
+    fn read_png(&mut self, bytes: &[u8]) {
+        let Ok(chunks) = png_chunks(bytes) else {
+            return;
+        };
+
+        for (kind, start, end) in chunks {
+            let data = &bytes[start + 8..end - 4];
+            match &kind {
+                b"eXIf" => {
+                    let tiff = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+                    self.exif = Exif::parse(tiff).or(self.exif.take());
+                }
+                b"iTXt" | b"tEXt" | b"zTXt" => {
+                    let Some((keyword, text)) = text_chunk(&kind, data) else {
+                        continue;
+                    };
+                    match keyword.as_str() {
+                        XMP_KEYWORD => self.xmp = Some(String::from_utf8_lossy(&text).into_owned()),
+                        RAW_PROFILE_EXIF if self.exif.is_none() => {
+                            let profile = parse_raw_profile(&text).unwrap_or_default();
+                            let tiff = profile.strip_prefix(EXIF_HEADER).unwrap_or(&profile);
+                            self.exif = Exif::parse(tiff);
+                        }
+                        RAW_PROFILE_IPTC => {
+                            let profile = parse_raw_profile(&text).unwrap_or_default();
+                            // Some writers store the whole Photoshop resource block here
+                            let resources = profile.strip_prefix(PHOTOSHOP_HEADER).unwrap_or(&profile);
+                            self.iptc = if resources.starts_with(b"8BIM") {
+                                split_resources(resources).0
+                            } else {
+                                Some(profile).filter(|p| !p.is_empty())
+                            };
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn read_webp(&mut self, bytes: &[u8]) {
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let kind = &bytes[pos..pos + 4];
+            let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            let Some(data) = bytes.get(pos + 8..pos + 8 + size) else {
+                break;
+            };
+            match kind {
+                b"EXIF" => self.exif = Exif::parse(data.strip_prefix(EXIF_HEADER).unwrap_or(data)),
+                b"XMP " => self.xmp = Some(String::from_utf8_lossy(data).into_owned()),
+                _ => {}
+            }
+            pos += 8 + size + size % 2;
+        }
+    }
+
+    pub fn info(&self) -> MetadataInfo {
+        let iptc = self.iptc.as_deref().map(iim_datasets).unwrap_or_default();
+        let iptc_text = |dataset: u8| {
+            iptc.iter()
+                .find(|(record, id, _)| *record == 2 && *id == dataset)
+                .map(|(_, _, value)| String::from_utf8_lossy(value).into_owned())
+        };
+        let exif_text = |tag: u16| self.exif.as_ref().and_then(|e| e.ascii(tag));
+
+        MetadataInfo {
+            has_exif: self.exif.as_ref().is_some_and(|e| !e.is_empty()),
+            has_xmp: self.xmp.is_some(),
+            has_iptc: !iptc.is_empty(),
+            has_location: self.exif.as_ref().is_some_and(Exif::has_gps)
+                || self.xmp.as_deref().is_some_and(|x| strip_xmp_properties(x, is_location_property) != x)
+                || iptc.iter().any(|(record, id, _)| *record == 2 && IPTC_LOCATION.contains(id)),
+            orientation: self.exif.as_ref().and_then(|e| e.short(exif::TAG_ORIENTATION)).unwrap_or(1),
+            make: exif_text(exif::TAG_MAKE),
+            model: exif_text(exif::TAG_MODEL),
+            date_taken: exif_text(exif::TAG_DATE_TIME_ORIGINAL),
+            artist: exif_text(exif::TAG_ARTIST).or_else(|| iptc_text(IPTC_BYLINE)),
+            copyright: exif_text(exif::TAG_COPYRIGHT).or_else(|| iptc_text(IPTC_COPYRIGHT)),
+            description: exif_text(exif::TAG_IMAGE_DESCRIPTION).or_else(|| iptc_text(IPTC_CAPTION)),
+            xmp: self.xmp.clone(),
+        }
+    }
+
+    /**
+     * Apply an export policy
+     *
+     * When the pixels were already made upright, any EXIF Orientation is
+     * reset to 1 to keep viewers from rotating the image a second time.
+     */
+    pub fn with_policy(mut self, options: &MetadataOptions) -> Metadata {
+        match options.policy {
+            MetadataPolicy::StripAll => return Metadata::default(),
+            MetadataPolicy::PreserveAll => {}
+            MetadataPolicy::StripLocation => self.strip_location(),
+            MetadataPolicy::PreserveWithEdits => self.apply_edits(options),
+        }
+        if options.upright {
+            if let Some(exif) = &mut self.exif {
+                exif.replace_short(exif::TAG_ORIENTATION, 1);
+            }
+        }
+        self
+    }
+
+    fn strip_location(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            exif.remove_gps();
+        }
+        if let Some(xmp) = &mut self.xmp {
+            *xmp = strip_xmp_properties(xmp, is_location_property);
+        }
+        if let Some(iptc) = &mut self.iptc {
+            let mut datasets = iim_datasets(iptc);
+            datasets.retain(|(record, id, _)| !(*record == 2 && IPTC_LOCATION.contains(id)));
+            *iptc = iim_bytes(&datasets);
+        }
+    }
+
+    fn apply_edits(&mut self, options: &MetadataOptions) {
+        let edits = [
+            (&options.artist, exif::TAG_ARTIST, IPTC_BYLINE, "dc:creator", "Seq"),
+            (&options.copyright, exif::TAG_COPYRIGHT, IPTC_COPYRIGHT, "dc:rights", "Alt"),
+            (&options.description, exif::TAG_IMAGE_DESCRIPTION, IPTC_CAPTION, "dc:description", "Alt"),
+        ];
+
+        for (value, tag, dataset, property, container) in edits {
+            let Some(value) = value.as_deref().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+
+            self.exif.get_or_insert_with(Exif::default).set_ascii(tag, value);
+
+            if let Some(iptc) = &mut self.iptc {
+                let mut datasets = iim_datasets(iptc);
+                datasets.retain(|(record, id, _)| !(*record == 2 && *id == dataset));
+                datasets.push((2, dataset, value.as_bytes().to_vec()));
+                // Declare UTF-8 (ESC % G) so readers do not assume Latin-1
+                if !datasets.iter().any(|(record, id, _)| *record == 1 && *id == 90) {
+                    datasets.push((1, 90, b"\x1b%G".to_vec()));
+                }
+                datasets.sort_by_key(|(record, _, _)| *record);
+                *iptc = iim_bytes(&datasets);
+            }
+
+            if let Some(xmp) = &mut self.xmp {
+                let lang = if container == "Alt" { " xml:lang=\"x-default\"" } else { "" };
+                let element = format!(
+                    "<{p}><rdf:{c}><rdf:li{l}>{v}</rdf:li></rdf:{c}></{p}>",
+                    p = property,
+                    c = container,
+                    l = lang,
+                    v = xml_escape(value)
+                );
+                *xmp = insert_xmp_property(&strip_xmp_properties(xmp, |name| name == property), &element);
+            }
+        }
+    }
+
+    fn write_jpeg(&self, encoded: &[u8]) -> Result<Vec<u8>, Error> {
+        let (segments, scan_start) = jpeg_segments(encoded)?;
+
+        let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+        if let Some(exif) = self.exif.as_ref().filter(|e| !e.is_empty()) {
+            blocks.push((0xE1, [EXIF_HEADER, &exif.to_bytes()].concat()));
+        }
+        if let Some(xmp) = &self.xmp {
+            blocks.push((0xE1, [XMP_HEADER, xmp.as_bytes()].concat()));
+        }
+        let resources = self.photoshop_resources();
+        if !resources.is_empty() {
+            blocks.push((0xED, [PHOTOSHOP_HEADER, &resources].concat()));
+        }
+        if blocks.iter().any(|(_, payload)| payload.len() > MAX_SEGMENT_PAYLOAD) {
+            return Err(Error::invalid_parameter("Metadata block too large for a JPEG segment"));
+        }
+
+        let mut out = Vec::with_capacity(encoded.len() + blocks.iter().map(|(_, p)| p.len() + 4).sum::<usize>());
+        out.extend_from_slice(&[0xFF, 0xD8]);
+        let mut pending = Some(blocks);
+        for (marker, start, end) in segments {
+            // JFIF requires APP0 first; metadata goes right after it
+            if marker != 0xE0 {
+                write_segments(&mut out, pending.take());
+            }
+            if marker != 0xE1 && marker != 0xED {
+                out.extend_from_slice(&encoded[start..end]);
+            }
+        }
+        write_segments(&mut out, pending.take());
+        out.extend_from_slice(&encoded[scan_start..]);
+        Ok(out)
+    }
+
+    fn write_png(&self, encoded: &[u8]) -> Result<Vec<u8>, Error> {
+        let chunks = png_chunks(encoded)?;
+
+        let mut blocks: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+        if let Some(exif) = self.exif.as_ref().filter(|e| !e.is_empty()) {
+            blocks.push((*b"eXIf", exif.to_bytes()));
+        }
+        if let Some(xmp) = &self.xmp {
+            // keyword, no compression, empty language tag and translated keyword
+            let data = [XMP_KEYWORD.as_bytes(), b"\0\0\0\0\0", xmp.as_bytes()].concat();
+            blocks.push((*b"iTXt", data));
+        }
+        if let Some(iptc) = self.iptc.as_ref().filter(|i| !i.is_empty()) {
+            let profile = format_raw_profile("iptc", iptc);
+            let data = [RAW_PROFILE_IPTC.as_bytes(), b"\0\0", &deflate(profile.as_bytes())].concat();
+            blocks.push((*b"zTXt", data));
+        }
+
+        let mut out = Vec::with_capacity(encoded.len() + blocks.iter().map(|(_, d)| d.len() + 12).sum::<usize>());
+        out.extend_from_slice(PNG_SIGNATURE);
+        let mut pending = Some(blocks);
+        for (kind, start, end) in chunks {
+            if &kind == b"IDAT" {
+                for (kind, data) in pending.take().unwrap_or_default() {
+                    write_chunk(&mut out, &kind, &data);
+                }
+            }
+            if !is_metadata_chunk(&kind, &encoded[start + 8..end - 4]) {
+                out.extend_from_slice(&encoded[start..end]);
+            }
+        }
+        Ok(out)
+    }
+
+    fn photoshop_resources(&self) -> Vec<u8> {
+        let mut resources = self.photoshop.clone();
+        if let Some(iptc) = self.iptc.as_ref().filter(|i| !i.is_empty()) {
+            resources.extend_from_slice(b"8BIM");
+            resources.extend_from_slice(&RESOURCE_IPTC.to_be_bytes());
+            resources.extend_from_slice(&[0, 0]); // empty pascal-string name, padded
+            resources.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+            resources.extend_from_slice(iptc);
+            if iptc.len() % 2 == 1 {
+                resources.push(0);
+            }
+        }
+        resources
+    }
+}
+
+/**
+ * Copy metadata from `source` into an encoded JPEG or PNG
+ *
+ * # Arguments
+ * * `encoded` - JPEG or PNG produced by the encoders in this crate
+ * * `source` - Original JPEG, PNG or WebP file the pixels came from
+ * * `options` - Export policy and edits
+ *
+ * # Returns
+ * The encoded file with the selected metadata embedded (EXIF in APP1/eXIf,
+ * XMP in APP1/iTXt, IPTC in APP13/zTXt). Metadata already present in
+ * `encoded` is replaced.
+ */
+pub fn apply_metadata(encoded: &[u8], source: &[u8], options: &MetadataOptions) -> Result<Vec<u8>, Error> {
+    let metadata = Metadata::read(source).with_policy(options);
+    match container(encoded) {
+        Some(Container::Jpeg) => metadata.write_jpeg(encoded),
+        Some(Container::Png) => metadata.write_png(encoded),
+        Some(Container::WebP) => Err(Error::unsupported("Writing metadata to WebP is not supported")),
+        None => Err(Error::unsupported("Encoded data is not a JPEG or PNG file")),
+    }
+}
+
+/**
+ * Marker segments before the first scan as (marker, start, end), plus the
+ * offset of the SOS marker where entropy-coded data begins
+ */
+pub(crate) fn jpeg_segments(bytes: &[u8]) -> Result<(Vec<Segment>, usize), Error> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if bytes.get(pos) != Some(&0xFF) {
+            return Err(Error::decode_failed("Malformed JPEG marker"));
+        }
+        // Any number of 0xFF fill bytes may precede a marker
+        let mut marker_pos = pos + 1;
+        while bytes.get(marker_pos) == Some(&0xFF) {
+            marker_pos += 1;
+        }
+        let marker = *bytes.get(marker_pos).ok_or_else(|| Error::decode_failed("Truncated JPEG"))?;
+        if marker == 0xDA {
+            return Ok((segments, pos));
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos = marker_pos + 1;
+            continue;
+        }
+        let len = bytes
+            .get(marker_pos + 1..marker_pos + 3)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .filter(|&len| len >= 2)
+            .ok_or_else(|| Error::decode_failed("Truncated JPEG segment"))?;
+        let end = marker_pos + 1 + len;
+        if end > bytes.len() {
+            return Err(Error::decode_failed("Truncated JPEG segment"));
+        }
+        // Normalize fill bytes away so `start + 4` is always the payload
+        if marker_pos != pos + 1 {
+            pos = marker_pos - 1;
+        }
+        segments.push((marker, pos, end));
+        pos = end;
+    }
+}
+
+pub(crate) fn write_segments(out: &mut Vec<u8>, blocks: Option<Vec<(u8, Vec<u8>)>>) {
+    for (marker, payload) in blocks.unwrap_or_default() {
+        out.extend_from_slice(&[0xFF, marker]);
+        out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(&payload);
+    }
+}
+
+/**
+ * Chunks of a PNG as (type, start, end), where `start..end` spans the
+ * length, type, data and CRC fields
+ */
+pub(crate) fn png_chunks(bytes: &[u8]) -> Result<Vec<Chunk>, Error> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < bytes.len() {
+        let header = bytes.get(pos..pos + 8).ok_or_else(|| Error::decode_failed("Truncated PNG chunk"))?;
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        let end = pos + 12 + len;
+        if end > bytes.len() {
+            return Err(Error::decode_failed("Truncated PNG chunk"));
+        }
+        chunks.push((kind, pos, end));
+        pos = end;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+pub(crate) fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+/**
+ * Keyword and (decompressed) text of a tEXt, zTXt or iTXt chunk
+ */
+fn text_chunk(kind: &[u8; 4], data: &[u8]) -> Option<(String, Vec<u8>)> {
+    let nul = data.iter().position(|&b| b == 0)?;
+    let keyword = String::from_utf8_lossy(&data[..nul]).into_owned();
+    let rest = &data[nul + 1..];
+    let text = match kind {
+        b"tEXt" => rest.to_vec(),
+        b"zTXt" => inflate(rest.get(1..)?)?,
+        _ => {
+            let (compressed, rest) = (*rest.first()? == 1, rest.get(2..)?);
+            // Skip the language tag and translated keyword
+            let lang_end = rest.iter().position(|&b| b == 0)?;
+            let rest = &rest[lang_end + 1..];
+            let translated_end = rest.iter().position(|&b| b == 0)?;
+            let text = &rest[translated_end + 1..];
+            if compressed {
+                inflate(text)?
+            } else {
+                text.to_vec()
+            }
+        }
+    };
+    Some((keyword, text))
+}
+
+pub(crate) fn is_metadata_chunk(kind: &[u8; 4], data: &[u8]) -> bool {
+    match kind {
+        b"eXIf" => true,
+        b"tEXt" | b"zTXt" | b"iTXt" => {
+            let keyword = data.split(|&b| b == 0).next().unwrap_or_default();
+            [XMP_KEYWORD, RAW_PROFILE_EXIF, RAW_PROFILE_IPTC].iter().any(|k| k.as_bytes() == keyword)
+        }
+        _ => false,
+    }
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec cannot fail
+    encoder.write_all(data).expect("in-memory write");
+    encoder.finish().expect("in-memory write")
+}
+
+/**
+ * Decode an ImageMagick-style "Raw profile type" text:
+ * "\n<name>\n<length>\n<hex digits split over lines>"
+ */
+fn parse_raw_profile(text: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(text).ok()?;
+    let mut parts = text.split_whitespace();
+    let _name = parts.next()?;
+    let len: usize = parts.next()?.parse().ok()?;
+    let hex: Vec<u8> = parts.flat_map(str::bytes).collect();
+    let bytes: Vec<u8> = hex
+        .chunks_exact(2)
+        .map_while(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect();
+    (bytes.len() >= len).then(|| bytes[..len].to_vec())
+}
+
+fn format_raw_profile(name: &str, data: &[u8]) -> String {
+    let mut text = format!("\n{}\n{:8}\n", name, data.len());
+    for line in data.chunks(36) {
+        for byte in line {
+            text.push_str(&format!("{:02x}", byte));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/**
+ * Split Photoshop image resource blocks into the IPTC payload and the
+ * remaining resources. The IPTC digest is dropped since it would no longer
+ * match once the IPTC block is edited or stripped.
+ */
+fn split_resources(data: &[u8]) -> (Option<Vec<u8>>, Vec<u8>) {
+    let mut iptc = None;
+    let mut others = Vec::new();
+    let mut pos = 0;
+    while pos + 12 <= data.len() && &data[pos..pos + 4] == b"8BIM" {
+        let id = u16::from_be_bytes([data[pos + 4], data[pos + 5]]);
+        let name_len = 1 + data[pos + 6] as usize;
+        let size_at = pos + 6 + name_len + name_len % 2;
+        let Some(size) = data.get(size_at..size_at + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize) else {
+            break;
+        };
+        let start = size_at + 4;
+        let Some(payload) = data.get(start..start + size) else {
+            break;
+        };
+        let next = (start + size + size % 2).min(data.len());
+        match id {
+            RESOURCE_IPTC => iptc = Some(payload.to_vec()),
+            RESOURCE_IPTC_DIGEST => {}
+            _ => others.extend_from_slice(&data[pos..next]),
+        }
+        pos = next;
+    }
+    (iptc, others)
+}
+
+/**
+ * IPTC IIM datasets as (record, dataset, value); parsing stops at the first
+ * malformed or extended-length dataset
+ */
+fn iim_datasets(data: &[u8]) -> Vec<(u8, u8, Vec<u8>)> {
+    let mut datasets = Vec::new();
+    let mut pos = 0;
+    while pos + 5 <= data.len() && data[pos] == 0x1C {
+        let len = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        if len & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = data.get(pos + 5..pos + 5 + len) else {
+            break;
+        };
+        datasets.push((data[pos + 1], data[pos + 2], value.to_vec()));
+        pos += 5 + len;
+    }
+    datasets
+}
+
+fn iim_bytes(datasets: &[(u8, u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (record, dataset, value) in datasets {
+        // Standard datasets are limited to 32767 bytes
+        let value = &value[..value.len().min(0x7FFF)];
+        out.extend_from_slice(&[0x1C, *record, *dataset]);
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        out.extend_from_slice(value);
+    }
+    out
+}
+
+fn is_location_property(name: &str) -> bool {
+    name.starts_with("exif:GPS")
+        || matches!(
+            name,
+            "photoshop:City"
+                | "photoshop:State"
+                | "photoshop:Country"
+                | "Iptc4xmpCore:Location"
+                | "Iptc4xmpCore:CountryCode"
+                | "Iptc4xmpExt:LocationCreated"
+                | "Iptc4xmpExt:LocationShown"
+        )
+}
+
+/**
+ * Remove XMP properties whose qualified name matches, in both element form
+ * (`<exif:GPSLatitude>..</exif:GPSLatitude>`) and attribute form
+ * (`exif:GPSLatitude="..."`)
+ */
+fn strip_xmp_properties<F: Fn(&str) -> bool>(xmp: &str, matches: F) -> String {
+    let mut out = String::with_capacity(xmp.len());
+    let mut rest = xmp;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..=close];
+        let name = tag_name(tag);
+
+        if !tag.starts_with("</") && !tag.starts_with("<?") && !tag.starts_with("<!") && matches(name) {
+            if tag.ends_with("/>") {
+                rest = &rest[close + 1..];
+                continue;
+            }
+            let end_tag = format!("</{}>", name);
+            if let Some(end) = rest.find(&end_tag) {
+                rest = &rest[end + end_tag.len()..];
+                continue;
+            }
+        }
+
+        out.push_str(&strip_attributes(tag, &matches));
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn tag_name(tag: &str) -> &str {
+    let name = tag.trim_start_matches('<');
+    let end = name.find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap_or(name.len());
+    &name[..end]
+}
+
+fn strip_attributes<F: Fn(&str) -> bool>(tag: &str, matches: &F) -> String {
+    let name_end = 1 + tag_name(tag).len();
+    let mut out = tag[..name_end].to_string();
+    let mut rest = &tag[name_end..];
+    loop {
+        let trimmed = rest.trim_start();
+        let whitespace = &rest[..rest.len() - trimmed.len()];
+        let Some(eq) = trimmed.find('=') else {
+            break;
+        };
+        let name = trimmed[..eq].trim_end();
+        let value = &trimmed[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(value_len) = value[1..].find(quote) else {
+            break;
+        };
+        let attr_len = trimmed.len() - value.len() + value_len + 2;
+        if name.contains(['<', '>', '/']) {
+            break;
+        }
+        if !matches(name) {
+            out.push_str(whitespace);
+            out.push_str(&trimmed[..attr_len]);
+        }
+        rest = &trimmed[attr_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/**
+ * Insert an element into the first rdf:Description, declaring the Dublin
+ * Core namespace there if needed. XMP without an rdf:Description is left
+ * unchanged.
+ */
+fn insert_xmp_property(xmp: &str, element: &str) -> String {
+    let Some(start) = xmp.find("<rdf:Description") else {
+        return xmp.to_string();
+    };
+    let Some(len) = xmp[start..].find('>') else {
+        return xmp.to_string();
+    };
+    let end = start + len;
+
+    let mut tag = xmp[start..end].to_string();
+    let self_closing = tag.ends_with('/');
+    if self_closing {
+        tag.pop();
+    }
+    if !tag.contains("xmlns:dc=") {
+        tag.push_str(" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"");
+    }
+    let closing = if self_closing { "</rdf:Description>" } else { "" };
+    format!("{}{}>{}{}{}", &xmp[..start], tag, element, closing, &xmp[end + 1..])
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exif::Entry;
+
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about="" xmlns:exif="http://ns.adobe.com/exif/1.0/" exif:GPSLatitude="51,30.0N" exif:ExposureTime="1/125"><exif:GPSLongitude>0,7.0W</exif:GPSLongitude></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    fn gray(size: u32) -> Vec<u8> {
+        (0..size * size).flat_map(|i| [(i % 256) as u8, 128, 64, 255]).collect()
+    }
+
+    fn encode_jpeg(size: u32) -> Vec<u8> {
+        crate::jpeg::compress_to_jpeg(&gray(size), size, size, 80).unwrap()
+    }
+
+    fn source_with_everything() -> Vec<u8> {
+        let mut exif = Exif::default();
+        exif.set_ascii(exif::TAG_ARTIST, "Jane Doe");
+        exif.ifd0.push(Entry { tag: exif::TAG_ORIENTATION, kind: 3, count: 1, data: vec![6, 0] });
+        exif.gps.push(Entry { tag: 0x0002, kind: 5, count: 3, data: vec![1; 24] });
+        let iptc = iim_bytes(&[(2, IPTC_BYLINE, b"Jane Doe".to_vec()), (2, 90, b"London".to_vec())]);
+
+        let metadata = Metadata {
+            exif: Some(exif),
+            xmp: Some(XMP.to_string()),
+            iptc: Some(iptc),
+            photoshop: Vec::new(),
+        };
+        metadata.write_jpeg(&encode_jpeg(8)).unwrap()
+    }
+
+    #[test]
+    fn strip_location_keeps_other_metadata() {
+        let source = source_with_everything();
+        assert!(Metadata::read(&source).info().has_location);
+
+        let options = MetadataOptions::new(MetadataPolicy::StripLocation);
+        let exported = apply_metadata(&encode_jpeg(8), &source, &options).unwrap();
+        let metadata = Metadata::read(&exported);
+        let info = metadata.info();
+
+        assert!(!info.has_location);
+        assert_eq!(info.artist.as_deref(), Some("Jane Doe"));
+        assert_eq!(info.orientation, 1);
+        let xmp = info.xmp.unwrap();
+        assert!(xmp.contains("exif:ExposureTime") && !xmp.contains("GPS"));
+        assert_eq!(iim_datasets(&metadata.iptc.unwrap()).len(), 1);
+
+        let stripped = apply_metadata(&exported, &source, &MetadataOptions::new(MetadataPolicy::StripAll)).unwrap();
+        let info = Metadata::read(&stripped).info();
+        assert!(!info.has_exif && !info.has_xmp && !info.has_iptc);
+    }
+
+    #[test]
+    fn orientation_is_kept_for_pixels_that_were_not_auto_oriented() {
+        let source = source_with_everything();
+        let mut options = MetadataOptions::new(MetadataPolicy::PreserveAll);
+        assert_eq!(Metadata::read(&apply_metadata(&encode_jpeg(8), &source, &options).unwrap()).info().orientation, 1);
+
+        options.upright = false;
+        let exported = apply_metadata(&encode_jpeg(8), &source, &options).unwrap();
+        assert_eq!(Metadata::read(&exported).info().orientation, 6);
+    }
+
+    #[test]
+    fn edits_are_written_into_png() {
+        let png = crate::png::compress_to_png(&gray(8), 8, 8, 50).unwrap();
+
+        let mut options = MetadataOptions::new(MetadataPolicy::PreserveWithEdits);
+        options.copyright = Some("© 2024 Jane Doe".to_string());
+        let exported = apply_metadata(&png, &source_with_everything(), &options).unwrap();
+        assert!(image::load_from_memory(&exported).is_ok());
+
+        let info = Metadata::read(&exported).info();
+        assert_eq!(info.copyright.as_deref(), Some("© 2024 Jane Doe"));
+        assert_eq!(info.artist.as_deref(), Some("Jane Doe"));
+        assert!(info.xmp.unwrap().contains("<dc:rights><rdf:Alt><rdf:li xml:lang=\"x-default\">© 2024 Jane Doe"));
+        assert!(info.has_location);
+    }
+}
@@ -0,0 +1,308 @@
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// SSIM stabilizing constants for 8-bit samples: (0.01 * 255)² and (0.03 * 255)²
+const C1: f32 = 6.5025;
+const C2: f32 = 58.5225;
+
+// Gaussian window of the reference SSIM implementation: 11 taps, sigma 1.5
+const WINDOW_RADIUS: usize = 5;
+const WINDOW_SIGMA: f32 = 1.5;
+
+// MS-SSIM scale weights from Wang, Simoncelli & Bovik 2003, finest first
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+// Heatmap gain: a channel difference of 64 or more is shown at full heat
+const HEATMAP_GAIN: f32 = 4.0;
+
+/**
+ * Quality metrics of a distorted image against its reference
+ *
+ * All metrics work on pixels composited over white, so the hidden color of
+ * fully transparent pixels doesn't count. SSIM and MS-SSIM use luma.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct QualityMetrics {
+    pub psnr: f64,        // dB over RGB, infinite for identical images
+    pub ssim: f64,        // 0-1, 1 = identical
+    pub ms_ssim: f64,     // 0-1, multi-scale SSIM (5 scales where the size allows)
+    pub heatmap: Vec<u8>, // RGBA difference map (black = equal, red-yellow-white = worse), empty unless requested
+}
+
+/**
+ * Compare two RGBA images of the same size
+ *
+ * # Arguments
+ * * `reference` - Original RGBA image data
+ * * `distorted` - RGBA image data to score, same dimensions
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `heatmap` - Also render a per-pixel difference heatmap
+ *
+ * # Returns
+ * Ok(metrics) on success
+ * Err(error_message) if the buffers don't match the dimensions
+ */
+pub fn compare(
+    reference: &[u8],
+    distorted: &[u8],
+    width: u32,
+    height: u32,
+    heatmap: bool,
+) -> Result<QualityMetrics, Error> {
+    let expected = (width as usize) * (height as usize) * 4;
+    if width == 0 || height == 0 || reference.len() != expected || distorted.len() != expected {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
+    }
+    let (width, height) = (width as usize, height as usize);
+    let a = composite(reference);
+    let b = composite(distorted);
+    let (x, y) = (luma(&a), luma(&b));
+
+    Ok(QualityMetrics {
+        psnr: psnr(&a, &b),
+        ssim: ssim_stats(&x, &y, width, height).0,
+        ms_ssim: ms_ssim(x, y, width, height),
+        heatmap: if heatmap { difference_heatmap(&a, &b) } else { Vec::new() },
+    })
+}
+
+/**
+ * Compare an RGBA original against the decoded result of compressed bytes
+ *
+ * # Arguments
+ * * `reference` - Original RGBA image data
+ * * `encoded` - JPEG, PNG or WebP file produced from it
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `heatmap` - Also render a per-pixel difference heatmap
+ *
+ * # Returns
+ * Ok(metrics) on success
+ * Err(error_message) if the file can't be decoded or has other dimensions
+ */
+pub fn compare_encoded(
+    reference: &[u8],
+    encoded: &[u8],
+    width: u32,
+    height: u32,
+    heatmap: bool,
+) -> Result<QualityMetrics, Error> {
+    let decoded = image::load_from_memory(encoded)
+        .map_err(|_| Error::decode_failed("Failed to decode compressed image"))?
+        .into_rgba8();
+    if decoded.dimensions() != (width, height) {
+        return Err(Error::input_size_mismatch("Decoded image dimensions do not match the original"));
+    }
+    compare(reference, &decoded, width, height, heatmap)
+}
+
+/**
+ * Structural similarity (SSIM) of two RGBA images
+ *
+ * Computed on luma with an 11x11 Gaussian window, as in Wang et al. 2004.
+ *
+ * # Arguments
+ * * `a` - Reference RGBA image data
+ * * `b` - Distorted RGBA image data, same dimensions
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ *
+ * # Returns
+ * Mean SSIM, 1.0 for identical images
+ */
+pub(crate) fn ssim(a: &[u8], b: &[u8], width: usize, height: usize) -> f64 {
+    ssim_stats(&luma(&composite(a)), &luma(&composite(b)), width, height).0
+}
+
+/// Peak signal-to-noise ratio over the RGB channels
+fn psnr(a: &[[f32; 3]], b: &[[f32; 3]]) -> f64 {
+    let squared: f64 = a
+        .iter()
+        .zip(b)
+        .flat_map(|(p, q)| (0..3).map(move |c| ((p[c] - q[c]) as f64).powi(2)))
+        .sum();
+    let mse = squared / (a.len() * 3) as f64;
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Mean SSIM and mean contrast-structure term (SSIM without luminance)
+fn ssim_stats(x: &[f32], y: &[f32], width: usize, height: usize) -> (f64, f64) {
+    let product = |p: &[f32], q: &[f32]| -> Vec<f32> { p.iter().zip(q).map(|(a, b)| a * b).collect() };
+    let mu_x = blur(x, width, height);
+    let mu_y = blur(y, width, height);
+    let xx = blur(&product(x, x), width, height);
+    let yy = blur(&product(y, y), width, height);
+    let xy = blur(&product(x, y), width, height);
+
+    let (mut total, mut total_cs) = (0.0f64, 0.0f64);
+    for i in 0..x.len() {
+        let (mx, my) = (mu_x[i], mu_y[i]);
+        let var_x = xx[i] - mx * mx;
+        let var_y = yy[i] - my * my;
+        let cov = xy[i] - mx * my;
+        let luminance = (2.0 * mx * my + C1) / (mx * mx + my * my + C1);
+        let cs = (2.0 * cov + C2) / (var_x + var_y + C2);
+        total += (luminance * cs) as f64;
+        total_cs += cs as f64;
+    }
+    let n = x.len().max(1) as f64;
+    (total / n, total_cs / n)
+}
+
+/// Multi-scale SSIM: contrast-structure at each scale, luminance at the
+/// coarsest. Scales stop once the image is smaller than the window, and the
+/// weights of the scales used are renormalized.
+fn ms_ssim(mut x: Vec<f32>, mut y: Vec<f32>, mut width: usize, mut height: usize) -> f64 {
+    let window = 2 * WINDOW_RADIUS + 1;
+    let scales = (1..=MS_SSIM_WEIGHTS.len())
+        .take_while(|&s| (width.min(height) >> (s - 1)) >= window)
+        .count()
+        .max(1);
+    let weight_sum: f64 = MS_SSIM_WEIGHTS[..scales].iter().sum();
+
+    let mut product = 1.0;
+    for (scale, weight) in MS_SSIM_WEIGHTS[..scales].iter().enumerate() {
+        let (ssim, cs) = ssim_stats(&x, &y, width, height);
+        let term = if scale + 1 == scales { ssim } else { cs };
+        product *= term.max(0.0).powf(weight / weight_sum);
+        if scale + 1 < scales {
+            y = halve(&y, width, height).0;
+            (x, width, height) = halve(&x, width, height);
+        }
+    }
+    product
+}
+
+/// 2x2 box downsample; an odd last row or column is dropped
+fn halve(plane: &[f32], width: usize, height: usize) -> (Vec<f32>, usize, usize) {
+    let (w, h) = (width / 2, height / 2);
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let i = 2 * y * width + 2 * x;
+            out.push((plane[i] + plane[i + 1] + plane[i + width] + plane[i + width + 1]) / 4.0);
+        }
+    }
+    (out, w, h)
+}
+
+/// "Hot" colormap of the largest channel difference per pixel
+fn difference_heatmap(a: &[[f32; 3]], b: &[[f32; 3]]) -> Vec<u8> {
+    a.iter()
+        .zip(b)
+        .flat_map(|(p, q)| {
+            let diff = (0..3).map(|c| (p[c] - q[c]).abs()).fold(0.0f32, f32::max);
+            let t = (diff / 255.0 * HEATMAP_GAIN).min(1.0) * 3.0;
+            let ramp = |offset: f32| ((t - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+            [ramp(0.0), ramp(1.0), ramp(2.0), 255]
+        })
+        .collect()
+}
+
+/// RGB of RGBA pixels composited over white
+fn composite(rgba: &[u8]) -> Vec<[f32; 3]> {
+    rgba.chunks_exact(4)
+        .map(|p| {
+            let alpha = p[3] as f32 / 255.0;
+            [0, 1, 2].map(|c| p[c] as f32 * alpha + 255.0 * (1.0 - alpha))
+        })
+        .collect()
+}
+
+/// BT.601 luma
+fn luma(rgb: &[[f32; 3]]) -> Vec<f32> {
+    rgb.iter().map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2]).collect()
+}
+
+/// Gaussian-weighted local mean, separable, with clamped edges
+fn blur(plane: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let weights: Vec<f32> = (0..=2 * WINDOW_RADIUS)
+        .map(|i| {
+            let d = i as f32 - WINDOW_RADIUS as f32;
+            (-d * d / (2.0 * WINDOW_SIGMA * WINDOW_SIGMA)).exp()
+        })
+        .collect();
+    let sum: f32 = weights.iter().sum();
+    let weights: Vec<f32> = weights.iter().map(|w| w / sum).collect();
+
+    let tap = |i: usize, offset: usize, len: usize| (i + offset).saturating_sub(WINDOW_RADIUS).min(len - 1);
+    let mut rows = vec![0.0f32; plane.len()];
+    for y in 0..height {
+        let row = &plane[y * width..(y + 1) * width];
+        for x in 0..width {
+            rows[y * width + x] = weights.iter().enumerate().map(|(k, w)| w * row[tap(x, k, width)]).sum();
+        }
+    }
+    let mut out = vec![0.0f32; plane.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = weights
+                .iter()
+                .enumerate()
+                .map(|(k, w)| w * rows[tap(y, k, height) * width + x])
+                .sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i % width * 8) as u8, (i / width * 10) as u8, (i * 7 % 256) as u8, 255])
+            .collect()
+    }
+
+    #[test]
+    fn test_ssim_orders_distortions() {
+        let (width, height) = (32, 24);
+        let original = gradient(width, height);
+        let shift = |amount: i16| -> Vec<u8> {
+            original
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| match i % 8 {
+                    3 | 7 => v,
+                    0..=2 => (v as i16 + amount).clamp(0, 255) as u8,
+                    _ => (v as i16 - amount).clamp(0, 255) as u8,
+                })
+                .collect()
+        };
+
+        assert!((ssim(&original, &original, width, height) - 1.0).abs() < 1e-6);
+        let slight = ssim(&original, &shift(4), width, height);
+        let heavy = ssim(&original, &shift(40), width, height);
+        assert!(slight < 1.0 && heavy < slight);
+    }
+
+    #[test]
+    fn test_psnr_ms_ssim_and_heatmap() {
+        let (width, height) = (96, 64);
+        let original = gradient(width, height);
+        let same = compare(&original, &original, 96, 64, false).unwrap();
+        assert!(same.psnr.is_infinite());
+        assert!((same.ms_ssim - 1.0).abs() < 1e-6);
+        assert!(same.heatmap.is_empty());
+
+        // A uniform error of 10 on one pixel in four: MSE 25, PSNR 34.15 dB
+        let mut distorted = original.clone();
+        for pixel in distorted.chunks_exact_mut(16) {
+            pixel[..3].iter_mut().for_each(|v| *v = if *v >= 10 { *v - 10 } else { *v + 10 });
+        }
+        let metrics = compare(&original, &distorted, 96, 64, true).unwrap();
+        assert!((metrics.psnr - 34.15).abs() < 0.01);
+        assert!(metrics.ssim < 1.0 && metrics.ms_ssim < 1.0 && metrics.ms_ssim > 0.5);
+        assert_eq!(metrics.heatmap.len(), original.len());
+        assert_eq!(&metrics.heatmap[..4], &[120, 0, 0, 255]);
+        assert_eq!(&metrics.heatmap[4..8], &[0, 0, 0, 255]);
+    }
+}
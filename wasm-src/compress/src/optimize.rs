@@ -0,0 +1,524 @@
+use flate2::{Compress, Compression, FlushCompress};
+use photo_editor_common::Error;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::num::NonZeroU64;
+
+use crate::metadata::{self, PNG_SIGNATURE};
+
+// Deflate level used to rank color type and filter combinations
+const TRIAL_LEVEL: u32 = 6;
+
+// Filtered data above this size gets flate2's best level instead of zopfli,
+// whose running time would run to tens of seconds
+const ZOPFLI_LIMIT: usize = 1 << 20;
+
+// Zopfli iterations are spread over this many bytes, so small images get
+// up to MAX_ZOPFLI_ITERATIONS and large ones as few as one
+const ZOPFLI_BUDGET: usize = 256 << 10;
+const MAX_ZOPFLI_ITERATIONS: usize = 15;
+
+// Ancillary chunks that affect how the image is displayed
+const RENDERING_CHUNKS: [&[u8; 4]; 8] = [b"iCCP", b"sRGB", b"gAMA", b"cHRM", b"cICP", b"mDCV", b"cLLI", b"pHYs"];
+
+// PNG color types
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const INDEXED: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+/// Row filter choice: one filter type for every row, or a per-row heuristic
+#[derive(Clone, Copy, Debug)]
+enum Strategy {
+    Fixed(u8),
+    MinSum,
+    Entropy,
+    Brute,
+}
+
+const STRATEGIES: [Strategy; 8] = [
+    Strategy::Fixed(0),
+    Strategy::Fixed(1),
+    Strategy::Fixed(2),
+    Strategy::Fixed(3),
+    Strategy::Fixed(4),
+    Strategy::MinSum,
+    Strategy::Entropy,
+    Strategy::Brute,
+];
+
+/// Decoded image as RGBA samples at 8 (`wide` false) or 16 bits
+struct Pixels {
+    width: usize,
+    height: usize,
+    wide: bool,
+    data: Vec<[u16; 4]>,
+}
+
+impl Pixels {
+    fn max(&self) -> u16 {
+        if self.wide { 65535 } else { 255 }
+    }
+}
+
+/// One way of storing the image: IHDR color type and depth, unfiltered
+/// scanlines, and the PLTE/tRNS payloads it needs
+struct Candidate {
+    color_type: u8,
+    depth: u8,
+    raw: Vec<u8>,
+    plte: Vec<u8>,
+    trns: Vec<u8>,
+}
+
+impl Candidate {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            RGB => 3,
+            GRAY_ALPHA => 2,
+            RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn stride(&self, width: usize) -> usize {
+        (width * self.channels() * self.depth as usize).div_ceil(8)
+    }
+
+    /// Distance in bytes to the pixel a filter predicts from
+    fn filter_distance(&self) -> usize {
+        (self.channels() * self.depth as usize / 8).max(1)
+    }
+}
+
+/**
+ * Losslessly recompress a PNG file
+ *
+ * Tries every color type the pixels allow (RGBA to RGB when opaque, to
+ * grayscale, to a palette, with the smallest bit depth and a tRNS color key
+ * where possible) with every row filter strategy, keeps the smallest, and
+ * recompresses it with zopfli. Metadata and color management chunks are
+ * kept; other ancillary chunks are dropped. Animated PNGs, and files that
+ * don't get smaller, are returned unchanged.
+ *
+ * # Arguments
+ * * `bytes` - Complete PNG file
+ *
+ * # Returns
+ * Ok(optimized_png) on success
+ * Err(error_message) if the file can't be decoded
+ */
+pub fn optimize_png(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(Error::decode_failed("Not a PNG file"));
+    }
+    let chunks = metadata::png_chunks(bytes)?;
+    if chunks.iter().any(|(kind, _, _)| kind == b"acTL") {
+        log_debug!("optimize_png: animated PNG left unchanged");
+        return Ok(bytes.to_vec());
+    }
+
+    let (pixels, source_gray) = decode(bytes)?;
+    // An ICC profile only describes one color model, so keep the source's
+    let has_profile = chunks.iter().any(|(kind, _, _)| kind == b"iCCP");
+    let candidates = candidates(&pixels, !has_profile || source_gray, !has_profile || !source_gray);
+
+    let mut best: Option<(usize, Vec<u8>, &Candidate)> = None;
+    for candidate in &candidates {
+        let stride = candidate.stride(pixels.width);
+        for strategy in STRATEGIES {
+            let filtered = filter(&candidate.raw, stride, candidate.filter_distance(), strategy);
+            let size = deflate(&filtered, TRIAL_LEVEL).len();
+            log_debug!(
+                "optimize_png: color type {} depth {} {:?} -> {}",
+                candidate.color_type, candidate.depth, strategy, size
+            );
+            if best.as_ref().is_none_or(|(s, _, _)| size < *s) {
+                best = Some((size, filtered, candidate));
+            }
+        }
+    }
+    let (_, filtered, candidate) = best.ok_or_else(|| Error::encode_failed("PNG optimization failed"))?;
+
+    let idat = if filtered.len() <= ZOPFLI_LIMIT {
+        zopfli_deflate(&filtered)
+    } else {
+        deflate(&filtered, 9)
+    };
+
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(pixels.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(pixels.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[candidate.depth, candidate.color_type, 0, 0, 0]);
+    metadata::write_chunk(&mut out, b"IHDR", &ihdr);
+    for &(kind, start, end) in &chunks {
+        let data = &bytes[start + 8..end - 4];
+        if RENDERING_CHUNKS.contains(&&kind) || metadata::is_metadata_chunk(&kind, data) {
+            out.extend_from_slice(&bytes[start..end]);
+        }
+    }
+    if !candidate.plte.is_empty() {
+        metadata::write_chunk(&mut out, b"PLTE", &candidate.plte);
+    }
+    if !candidate.trns.is_empty() {
+        metadata::write_chunk(&mut out, b"tRNS", &candidate.trns);
+    }
+    metadata::write_chunk(&mut out, b"IDAT", &idat);
+    metadata::write_chunk(&mut out, b"IEND", &[]);
+
+    log_debug!("optimize_png: {} -> {} bytes", bytes.len(), out.len());
+    if out.len() >= bytes.len() {
+        return Ok(bytes.to_vec());
+    }
+    Ok(out)
+}
+
+/// Pixels as RGBA, narrowed to 8 bits when the 16-bit samples are just
+/// repeated bytes; also reports whether the source was grayscale
+fn decode(bytes: &[u8]) -> Result<(Pixels, bool), Error> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|_| Error::decode_failed("Invalid PNG file"))?;
+    let source_gray = matches!(
+        reader.info().color_type,
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha
+    );
+    let mut buffer = vec![0u8; reader.output_buffer_size().ok_or_else(|| Error::invalid_dimensions("PNG too large"))?];
+    let info = reader.next_frame(&mut buffer).map_err(|_| Error::decode_failed("Invalid PNG file"))?;
+
+    let wide = info.bit_depth == png::BitDepth::Sixteen;
+    let samples: Vec<u16> = if wide {
+        buffer[..info.line_size * info.height as usize]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect()
+    } else {
+        buffer[..info.line_size * info.height as usize].iter().map(|&b| b as u16).collect()
+    };
+    let max = if wide { 65535 } else { 255 };
+    let data: Vec<[u16; 4]> = match info.color_type {
+        png::ColorType::Grayscale => samples.iter().map(|&v| [v, v, v, max]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => samples.chunks_exact(3).map(|p| [p[0], p[1], p[2], max]).collect(),
+        png::ColorType::Rgba => samples.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+        png::ColorType::Indexed => return Err(Error::decode_failed("Invalid PNG file")),
+    };
+
+    let mut pixels = Pixels {
+        width: info.width as usize,
+        height: info.height as usize,
+        wide,
+        data,
+    };
+    if wide && pixels.data.iter().flatten().all(|&v| v >> 8 == v & 0xFF) {
+        pixels.wide = false;
+        pixels.data.iter_mut().for_each(|p| *p = p.map(|v| v >> 8));
+    }
+    Ok((pixels, source_gray))
+}
+
+fn candidates(pixels: &Pixels, allow_gray: bool, allow_color: bool) -> Vec<Candidate> {
+    let max = pixels.max();
+    let depth = if pixels.wide { 16 } else { 8 };
+    let opaque = pixels.data.iter().all(|p| p[3] == max);
+    let gray = pixels.data.iter().all(|p| p[0] == p[1] && p[1] == p[2]);
+    let key = color_key(pixels);
+    let alpha = !opaque && key.is_none();
+    let key_bytes = |values: &[u16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+
+    let mut candidates = Vec::new();
+    if allow_color {
+        let channels = if alpha { 4 } else { 3 };
+        candidates.push(Candidate {
+            color_type: if alpha { RGBA } else { RGB },
+            depth,
+            raw: scanlines(pixels, depth, |p, row| row.extend_from_slice(&p[..channels])),
+            plte: Vec::new(),
+            trns: key.map(|k| key_bytes(&k)).unwrap_or_default(),
+        });
+        if let Some(candidate) = palette(pixels) {
+            candidates.push(candidate);
+        }
+    }
+    if allow_gray && gray {
+        if alpha {
+            candidates.push(Candidate {
+                color_type: GRAY_ALPHA,
+                depth,
+                raw: scanlines(pixels, depth, |p, row| row.extend_from_slice(&[p[0], p[3]])),
+                plte: Vec::new(),
+                trns: Vec::new(),
+            });
+        } else {
+            let depth = if pixels.wide { 16 } else { gray_depth(pixels.data.iter().map(|p| p[0])) };
+            let scale = if depth < 8 { 255 / ((1u16 << depth) - 1) } else { 1 };
+            candidates.push(Candidate {
+                color_type: GRAY,
+                depth,
+                raw: scanlines(pixels, depth, |p, row| row.push(p[0] / scale)),
+                plte: Vec::new(),
+                trns: key.map(|k| key_bytes(&[k[0] / scale])).unwrap_or_default(),
+            });
+        }
+    }
+    candidates
+}
+
+/// The single color shared by every fully transparent pixel, when all
+/// other pixels are opaque and none of them uses that color
+fn color_key(pixels: &Pixels) -> Option<[u16; 3]> {
+    let max = pixels.max();
+    let mut key = None;
+    for p in &pixels.data {
+        match p[3] {
+            0 if key.is_none() => key = Some([p[0], p[1], p[2]]),
+            0 if key != Some([p[0], p[1], p[2]]) => return None,
+            0 => {}
+            a if a != max => return None,
+            _ => {}
+        }
+    }
+    let key = key?;
+    let clash = pixels.data.iter().any(|p| p[3] == max && p[..3] == key);
+    (!clash).then_some(key)
+}
+
+/// Smallest bit depth that holds every 8-bit gray level exactly
+fn gray_depth(mut levels: impl Iterator<Item = u16> + Clone) -> u8 {
+    if levels.clone().all(|v| v == 0 || v == 255) {
+        1
+    } else if levels.clone().all(|v| v % 85 == 0) {
+        2
+    } else if levels.all(|v| v % 17 == 0) {
+        4
+    } else {
+        8
+    }
+}
+
+/// Indexed candidate for 8-bit images with at most 256 colors. Translucent
+/// entries come first so tRNS stays short, then the most frequent colors.
+fn palette(pixels: &Pixels) -> Option<Candidate> {
+    if pixels.wide {
+        return None;
+    }
+    let mut counts: HashMap<[u16; 4], u32> = HashMap::new();
+    for p in &pixels.data {
+        *counts.entry(*p).or_insert(0) += 1;
+        if counts.len() > 256 {
+            return None;
+        }
+    }
+    let mut entries: Vec<([u16; 4], u32)> = counts.into_iter().collect();
+    entries.sort_unstable_by_key(|(c, n)| (c[3] == 255, std::cmp::Reverse(*n), *c));
+    let index: HashMap<[u16; 4], u16> = entries.iter().enumerate().map(|(i, (c, _))| (*c, i as u16)).collect();
+
+    let depth = match entries.len() {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+    Some(Candidate {
+        color_type: INDEXED,
+        depth,
+        raw: scanlines(pixels, depth, |p, row| row.push(index[p])),
+        plte: entries.iter().flat_map(|(c, _)| [c[0] as u8, c[1] as u8, c[2] as u8]).collect(),
+        trns: entries.iter().take_while(|(c, _)| c[3] != 255).map(|(c, _)| c[3] as u8).collect(),
+    })
+}
+
+/// Unfiltered scanlines; `samples` appends the stored samples of one pixel
+fn scanlines<F: Fn(&[u16; 4], &mut Vec<u16>)>(pixels: &Pixels, depth: u8, samples: F) -> Vec<u8> {
+    let mut raw = Vec::new();
+    let mut row = Vec::new();
+    for line in pixels.data.chunks_exact(pixels.width) {
+        row.clear();
+        line.iter().for_each(|p| samples(p, &mut row));
+        match depth {
+            16 => row.iter().for_each(|s| raw.extend_from_slice(&s.to_be_bytes())),
+            8 => raw.extend(row.iter().map(|&s| s as u8)),
+            _ => {
+                let per_byte = 8 / depth as usize;
+                for group in row.chunks(per_byte) {
+                    let byte = group
+                        .iter()
+                        .enumerate()
+                        .fold(0u8, |b, (i, &s)| b | (s as u8) << (8 - depth as usize * (i + 1)));
+                    raw.push(byte);
+                }
+            }
+        }
+    }
+    raw
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Append `row` filtered with filter `kind` against the row above
+fn apply_filter(kind: u8, row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prev[i];
+        let c = if i >= bpp { prev[i - bpp] } else { 0 };
+        let predicted = match kind {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        out.push(row[i].wrapping_sub(predicted));
+    }
+}
+
+fn entropy(data: &[u8]) -> u64 {
+    let mut counts = [0u32; 256];
+    data.iter().for_each(|&b| counts[b as usize] += 1);
+    let n = data.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| -(c as f64) * (c as f64 / n).log2())
+        .sum();
+    (bits * 16.0) as u64
+}
+
+fn filter(raw: &[u8], stride: usize, bpp: usize, strategy: Strategy) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len() + raw.len() / stride.max(1));
+    let zero = vec![0u8; stride];
+    let mut trial = Vec::with_capacity(2 * stride + 2);
+    let mut brute = Compress::new(Compression::new(1), false);
+    let mut sink = Vec::with_capacity(4 * stride + 64);
+
+    for (y, row) in raw.chunks_exact(stride).enumerate() {
+        let prev = if y == 0 { &zero[..] } else { &raw[(y - 1) * stride..y * stride] };
+        let kind = match strategy {
+            Strategy::Fixed(kind) => kind,
+            _ => (0..5)
+                .min_by_key(|&kind| {
+                    trial.clear();
+                    if let Strategy::Brute = strategy {
+                        // Score with the previous filtered row as context
+                        trial.extend_from_slice(&out[out.len().saturating_sub(stride + 1)..]);
+                    }
+                    let start = trial.len();
+                    trial.push(kind);
+                    apply_filter(kind, row, prev, bpp, &mut trial);
+                    match strategy {
+                        Strategy::MinSum => trial[start + 1..].iter().map(|&b| (b as i8).unsigned_abs() as u64).sum(),
+                        Strategy::Entropy => entropy(&trial[start + 1..]),
+                        _ => {
+                            brute.reset();
+                            sink.clear();
+                            brute
+                                .compress_vec(&trial, &mut sink, FlushCompress::Finish)
+                                .expect("in-memory deflate");
+                            sink.len() as u64
+                        }
+                    }
+                })
+                .unwrap_or(0),
+        };
+        out.push(kind);
+        apply_filter(kind, row, prev, bpp, &mut out);
+    }
+    out
+}
+
+fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::new(level));
+    // Writing to a Vec cannot fail
+    encoder.write_all(data).expect("in-memory write");
+    encoder.finish().expect("in-memory write")
+}
+
+fn zopfli_deflate(data: &[u8]) -> Vec<u8> {
+    let options = zopfli::Options {
+        iteration_count: NonZeroU64::new((ZOPFLI_BUDGET / data.len().max(1)).clamp(1, MAX_ZOPFLI_ITERATIONS) as u64)
+            .unwrap(),
+        ..zopfli::Options::default()
+    };
+    let mut out = Vec::new();
+    zopfli::compress(options, zopfli::Format::Zlib, data, &mut out).expect("in-memory write");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    use image::{ExtendedColorType, ImageEncoder};
+
+    fn encode(data: &[u8], width: u32, height: u32, color: ExtendedColorType) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::NoFilter)
+            .write_image(data, width, height, color)
+            .unwrap();
+        png
+    }
+
+    fn ihdr(png: &[u8]) -> (u8, u8) {
+        (png[24], png[25])
+    }
+
+    #[test]
+    fn opaque_gray_rgba_becomes_low_depth_grayscale() {
+        // Four gray levels stored as RGBA, plus a text chunk to strip
+        let rgba: Vec<u8> = (0..48 * 32)
+            .flat_map(|i: u32| {
+                let v = ((i % 48 / 12) * 85) as u8;
+                [v, v, v, 255]
+            })
+            .collect();
+        let mut png = encode(&rgba, 48, 32, ExtendedColorType::Rgba8);
+        let iend = png.len() - 12;
+        let mut comment = Vec::new();
+        metadata::write_chunk(&mut comment, b"tEXt", b"Comment\0made by a test");
+        png.splice(iend..iend, comment);
+
+        let optimized = optimize_png(&png).unwrap();
+        assert!(optimized.len() < png.len() / 2);
+        assert_eq!(ihdr(&optimized).0, 2);
+        assert!(!optimized.windows(4).any(|w| w == b"tEXt"));
+
+        let decoded = image::load_from_memory(&optimized).unwrap().into_rgba8().into_raw();
+        assert_eq!(decoded, rgba);
+    }
+
+    #[test]
+    fn sixteen_bit_with_binary_alpha_narrows_to_a_color_key() {
+        // 16-bit samples that are repeated bytes, binary alpha with one key color
+        let samples: Vec<u16> = (0..40 * 40u32)
+            .flat_map(|i| {
+                let (x, y) = (i % 40, i / 40);
+                if (x + y) % 7 == 0 {
+                    [0, 0, 0, 0]
+                } else {
+                    [(x * 6 + 1) as u16 * 257, (y * 6 + 1) as u16 * 257, 100 * 257, 65535]
+                }
+            })
+            .collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let png = encode(&bytes, 40, 40, ExtendedColorType::Rgba16);
+
+        let optimized = optimize_png(&png).unwrap();
+        assert_eq!(ihdr(&optimized), (8, RGB));
+        assert!(optimized.windows(4).any(|w| w == b"tRNS"));
+
+        let decoded = image::load_from_memory(&optimized).unwrap().into_rgba16().into_raw();
+        assert_eq!(decoded, samples);
+    }
+}
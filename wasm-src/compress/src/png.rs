@@ -1,8 +1,49 @@
 use image::{ImageBuffer, RgbaImage, DynamicImage, ImageEncoder};
 use image::codecs::png::PngEncoder;
 use std::io::Cursor;
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+use crate::{optimize, quantize};
+
+/**
+ * PNG advanced compression parameters
+ *
+ * With `quantize`, the image is reduced to an adaptive palette and written
+ * as an indexed PNG (with tRNS for transparency). This is lossy, but
+ * screenshots and illustrations usually shrink by 60-80%.
+ *
+ * `optimize` runs the lossless optimizer on the result (see `optimize_png`);
+ * it takes seconds on large images.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct PngAdvancedParams {
+    pub quantize: bool,  // Lossy palette quantization
+    pub max_colors: u16, // 2-256, palette size limit when quantizing
+    pub dither: bool,    // Floyd-Steinberg dithering when quantizing
+    pub optimize: bool,  // Lossless reduction, filter search and zopfli pass
+}
+
+impl Default for PngAdvancedParams {
+    fn default() -> Self {
+        Self {
+            quantize: false,
+            max_colors: 256,
+            dither: true,
+            optimize: false,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl PngAdvancedParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> PngAdvancedParams {
+        PngAdvancedParams::default()
+    }
+}
+
 /**
  * Compress RGBA image data to PNG format
  *
@@ -14,37 +55,191 @@ use wasm_bindgen::prelude::*;
  * * `width` - Image width in pixels
  * * `height` - Image height in pixels
  * * `quality` - PNG compression level (1-100, maps to Fast/High/Best compression)
- * * `output` - Output buffer for compressed PNG data
  *
  * # Returns
- * Ok(number_of_bytes_written) on success
- * Err(error_message) on failure
+ * Ok(png_bytes) on success
+ * Err(error) on failure
  */
 pub fn compress_to_png(
     input: &[u8],
     width: u32,
     height: u32,
     quality: u8,
-    output: &mut [u8],
-) -> Result<usize, &'static str> {
-    web_sys::console::log_1(&format!("compress_to_png: {}x{}, compression_hint={}", width, height, quality).into());
+) -> Result<Vec<u8>, Error> {
+    log_debug!("compress_to_png: {}x{}, compression_hint={}", width, height, quality);
 
     // Create RGBA image from input buffer
     let rgba_image: RgbaImage =
         match ImageBuffer::from_raw(width, height, input.to_vec()) {
             Some(img) => {
-                web_sys::console::log_1(&"ImageBuffer created successfully".into());
+                log_debug!("ImageBuffer created successfully");
                 img
             },
             None => {
-                web_sys::console::log_1(&"Failed to create ImageBuffer".into());
-                return Err("Failed to create image buffer from input data");
+                log_error!("Failed to create ImageBuffer");
+                return Err(Error::input_size_mismatch("Failed to create image buffer from input data"));
             }
         };
 
     let dynamic_image = DynamicImage::ImageRgba8(rgba_image);
 
-    web_sys::console::log_1(&"Creating PNG encoder...".into());
+    encode_png(
+        dynamic_image.as_bytes(),
+        width,
+        height,
+        image::ExtendedColorType::Rgba8, // PNG supports RGBA with transparency
+        quality,
+    )
+}
+
+/**
+ * Compress RGBA image data to PNG format (advanced)
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - PNG compression level (1-100, maps to Fast/High/Best compression)
+ * * `params` - Advanced encoding parameters
+ *
+ * # Returns
+ * Ok(png_bytes) on success
+ * Err(error) on failure
+ */
+pub fn compress_to_png_advanced(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &PngAdvancedParams,
+) -> Result<Vec<u8>, Error> {
+    log_debug!(
+        "PNG params - quantize: {}, max_colors: {}, dither: {}, optimize: {}",
+        params.quantize, params.max_colors, params.dither, params.optimize
+    );
+
+    let png_data = if params.quantize {
+        compress_quantized(input, width, height, quality, params)?
+    } else {
+        compress_to_png(input, width, height, quality)?
+    };
+    if !params.optimize {
+        return Ok(png_data);
+    }
+    optimize::optimize_png(&png_data)
+}
+
+fn compress_quantized(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &PngAdvancedParams,
+) -> Result<Vec<u8>, Error> {
+    if input.len() != (width as usize) * (height as usize) * 4 {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
+    }
+
+    let quantized = quantize::quantize(input, width as usize, params.max_colors as usize, params.dither);
+    log_debug!("Quantized to {} colors", quantized.palette.len());
+
+    let png_data = encode_indexed(&quantized, width, height, quality)?;
+    log_debug!("PNG encoded successfully, size: {}", png_data.len());
+    Ok(png_data)
+}
+
+/// Indexed PNG at the smallest bit depth that holds the palette; the tRNS
+/// chunk is trimmed after the last non-opaque entry
+fn encode_indexed(quantized: &quantize::Quantized, width: u32, height: u32, quality: u8) -> Result<Vec<u8>, Error> {
+    let depth = match quantized.palette.len() {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    };
+    let bits = depth as usize;
+    let per_byte = 8 / bits;
+
+    let row_bytes = (width as usize).div_ceil(per_byte);
+    let mut packed = vec![0u8; row_bytes * height as usize];
+    for (row, indices) in packed.chunks_exact_mut(row_bytes).zip(quantized.indices.chunks_exact(width as usize)) {
+        for (x, &index) in indices.iter().enumerate() {
+            let shift = 8 - bits * (x % per_byte + 1);
+            row[x / per_byte] |= index << shift;
+        }
+    }
+
+    let palette: Vec<u8> = quantized.palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let alpha: Vec<u8> = quantized.palette.iter().map(|c| c[3]).collect();
+    let trns_len = alpha.iter().rposition(|&a| a != 255).map_or(0, |i| i + 1);
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(palette);
+    if trns_len > 0 {
+        encoder.set_trns(alpha[..trns_len].to_vec());
+    }
+    encoder.set_compression(png_compression(quality));
+
+    let mut writer = encoder.write_header().map_err(|_| Error::encode_failed("PNG encoding failed"))?;
+    writer.write_image_data(&packed).map_err(|_| Error::encode_failed("PNG encoding failed"))?;
+    writer.finish().map_err(|_| Error::encode_failed("PNG encoding failed"))?;
+    Ok(buffer)
+}
+
+/// Same quality mapping as the RGBA path
+fn png_compression(quality: u8) -> png::Compression {
+    if quality < 50 {
+        png::Compression::Fast
+    } else if quality < 90 {
+        png::Compression::Balanced
+    } else {
+        png::Compression::High
+    }
+}
+
+/**
+ * Compress 16-bit RGBA image data to a 16-bit PNG
+ *
+ * Keeps the full precision of high bit-depth edits; no narrowing happens.
+ *
+ * # Arguments
+ * * `input` - RGBA samples (4 per pixel, 0-65535, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - PNG compression level (1-100, maps to Fast/High/Best compression)
+ *
+ * # Returns
+ * Ok(png_bytes) on success
+ * Err(error) on failure
+ */
+pub fn compress_to_png_16(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, Error> {
+    log_debug!("compress_to_png_16: {}x{}, compression_hint={}", width, height, quality);
+
+    if input.len() != (width * height * 4) as usize {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
+    }
+
+    // The encoder expects native-endian samples and swaps to big endian itself
+    let bytes: Vec<u8> = input.iter().flat_map(|v| v.to_ne_bytes()).collect();
+    encode_png(&bytes, width, height, image::ExtendedColorType::Rgba16, quality)
+}
+
+fn encode_png(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: image::ExtendedColorType,
+    quality: u8,
+) -> Result<Vec<u8>, Error> {
+    log_debug!("Creating PNG encoder...");
 
     // Encode to PNG with compression level based on quality
     let mut buffer = Cursor::new(Vec::new());
@@ -52,13 +247,13 @@ pub fn compress_to_png(
     // Map quality (1-100) to PNG compression level
     // PNG compression: Fast -> Default -> Best
     let compression_level = if quality < 50 {
-        web_sys::console::log_1(&"Using Fast PNG compression".into());
+        log_debug!("Using Fast PNG compression");
         image::codecs::png::CompressionType::Fast
     } else if quality < 90 {
-        web_sys::console::log_1(&"Using Default PNG compression".into());
+        log_debug!("Using Default PNG compression");
         image::codecs::png::CompressionType::Default
     } else {
-        web_sys::console::log_1(&"Using Best PNG compression".into());
+        log_debug!("Using Best PNG compression");
         image::codecs::png::CompressionType::Best
     };
 
@@ -71,32 +266,17 @@ pub fn compress_to_png(
         filter_type
     );
 
-    web_sys::console::log_1(&"Encoding image...".into());
+    log_debug!("Encoding image...");
 
-    match encoder.write_image(
-        dynamic_image.as_bytes(),
-        width,
-        height,
-        image::ExtendedColorType::Rgba8,  // PNG supports RGBA with transparency
-    ) {
+    match encoder.write_image(data, width, height, color_type) {
         Ok(_) => {
             let png_data = buffer.into_inner();
-            web_sys::console::log_2(&"PNG encoded successfully, size:".into(), &png_data.len().into());
-
-            // Copy to output buffer
-            if png_data.len() > output.len() {
-                web_sys::console::log_2(&"Output buffer too small".into(),
-                    &format!("needed {}, got {}", png_data.len(), output.len()).into());
-                return Err("Output buffer too small for compressed data");
-            }
-
-            output[..png_data.len()].copy_from_slice(&png_data);
-            web_sys::console::log_2(&"Compression complete, copied".into(), &png_data.len().into());
-            Ok(png_data.len())
+            log_debug!("PNG encoded successfully, size: {}", png_data.len());
+            Ok(png_data)
         }
         Err(e) => {
-            web_sys::console::log_1(&format!("PNG encoding error: {:?}", e).into());
-            Err("PNG encoding failed")
+            log_error!("PNG encoding error: {:?}", e);
+            Err(Error::encode_failed("PNG encoding failed"))
         }
     }
 }
@@ -115,22 +295,80 @@ mod tests {
             255, 255, 0, 255, // Yellow pixel
         ];
 
-        let mut output = vec![0u8; 4096];
-        let result = compress_to_png(&rgba_data, 2, 2, 80, &mut output);
+        let result = compress_to_png(&rgba_data, 2, 2, 80);
 
         assert!(result.is_ok());
-        assert!(result.unwrap() > 0);
+        assert!(!result.unwrap().is_empty());
     }
 
     #[test]
     fn test_compress_to_png_compression_levels() {
         let rgba_data = vec![255u8; 16]; // 2x2 white image
-        let mut output = vec![0u8; 4096];
 
         // Test different compression levels
         for quality in [1, 50, 80, 100] {
-            let result = compress_to_png(&rgba_data, 2, 2, quality, &mut output);
+            let result = compress_to_png(&rgba_data, 2, 2, quality);
             assert!(result.is_ok());
         }
     }
+
+    #[test]
+    fn test_quantized_png_is_indexed_and_smaller() {
+        // Sensor noise over a gradient with a translucent band
+        let (width, height) = (64u32, 64u32);
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let mut h = i.wrapping_mul(0x9E37_79B9);
+                h ^= h >> 15;
+                h = h.wrapping_mul(0x85EB_CA6B);
+                h ^= h >> 13;
+                let noise = |shift: u32| ((h >> shift) & 15) as u8;
+                let a = if (20..30).contains(&y) { 128 } else { 255 };
+                [(x * 3) as u8 + noise(0), (y * 3) as u8 + noise(8), 200 - noise(16), a]
+            })
+            .collect();
+
+        let lossless = compress_to_png(&rgba, width, height, 80).unwrap();
+        let params = PngAdvancedParams {
+            quantize: true,
+            max_colors: 64,
+            ..PngAdvancedParams::default()
+        };
+        let quantized = compress_to_png_advanced(&rgba, width, height, 80, &params).unwrap();
+        assert!(quantized.len() < lossless.len() / 2);
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(&quantized));
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+        assert!(reader.info().palette.as_ref().unwrap().len() <= 64 * 3);
+
+        let decoded = image::load_from_memory(&quantized).unwrap().into_rgba8().into_raw();
+        assert!(decoded.chunks(4).zip(rgba.chunks(4)).all(|(d, s)| d[3].abs_diff(s[3]) <= 8));
+    }
+
+    #[test]
+    fn test_compress_to_png_16_keeps_precision() {
+        let samples: Vec<u16> = vec![1000, 1001, 1002, 65535, 40000, 40001, 40002, 32768];
+        let encoded = compress_to_png_16(&samples, 2, 1, 80).unwrap();
+
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.into_rgba16().into_raw(), samples);
+    }
+
+    #[test]
+    fn test_noise_may_encode_larger_than_raw_rgba() {
+        let rgba: Vec<u8> = (0..16 * 16 * 4u32)
+            .map(|i| {
+                let mut h = i.wrapping_mul(0x9E37_79B9);
+                h ^= h >> 15;
+                h = h.wrapping_mul(0x85EB_CA6B);
+                (h ^ (h >> 13)) as u8
+            })
+            .collect();
+
+        let encoded = compress_to_png(&rgba, 16, 16, 80).unwrap();
+        assert!(encoded.len() > rgba.len());
+        assert_eq!(image::load_from_memory(&encoded).unwrap().into_rgba8().into_raw(), rgba);
+    }
 }
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+// Histogram size above which colors are merged into coarser buckets
+const MAX_HISTOGRAM: usize = 1 << 16;
+
+// Lloyd iterations refining the median-cut palette
+const KMEANS_ITERATIONS: usize = 4;
+
+// Bits dropped per channel for the cells of the nearest-color hint cache
+const HINT_SHIFT: u32 = 3;
+
+/// A palette image: up to 256 RGBA entries plus one index per pixel
+pub(crate) struct Quantized {
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+}
+
+/// Color in premultiplied space, where the distance between two fully
+/// transparent colors is zero and faint colors matter less than opaque ones
+type Vector = [f32; 4];
+
+fn to_vector(c: [u8; 4]) -> Vector {
+    let a = c[3] as f32 / 255.0;
+    [c[0] as f32 * a, c[1] as f32 * a, c[2] as f32 * a, c[3] as f32]
+}
+
+fn from_vector(v: Vector) -> [u8; 4] {
+    let a = v[3].round().clamp(0.0, 255.0);
+    if a == 0.0 {
+        return [0, 0, 0, 0];
+    }
+    let scale = 255.0 / a;
+    let channel = |x: f32| (x * scale).round().clamp(0.0, 255.0) as u8;
+    [channel(v[0]), channel(v[1]), channel(v[2]), a as u8]
+}
+
+fn accumulate(sum: &mut Vector, v: &Vector, weight: f32) {
+    for (s, x) in sum.iter_mut().zip(v) {
+        *s += x * weight;
+    }
+}
+
+fn distance(a: &Vector, b: &Vector) -> f32 {
+    (0..4).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+/// Histogram entry: the mean color of a bucket and its pixel count
+#[derive(Clone, Copy)]
+struct Entry {
+    color: Vector,
+    weight: f32,
+}
+
+/**
+ * Reduce RGBA pixels to a palette of at most `max_colors` entries
+ *
+ * The palette comes from median cut over a color histogram, refined with a
+ * few k-means passes. Images that already have `max_colors` or fewer
+ * distinct colors are converted exactly. With `dither`, Floyd-Steinberg
+ * error diffusion (serpentine) hides banding in gradients.
+ */
+pub(crate) fn quantize(input: &[u8], width: usize, max_colors: usize, dither: bool) -> Quantized {
+    let max_colors = max_colors.clamp(2, 256);
+
+    if let Some(quantized) = exact_palette(input, max_colors) {
+        return quantized;
+    }
+
+    let histogram = histogram(input);
+    let mut palette: Vec<Vector> = median_cut(histogram.clone(), max_colors);
+    refine(&histogram, &mut palette);
+
+    let rgba: Vec<[u8; 4]> = palette.iter().map(|v| from_vector(*v)).collect();
+    // Snap the centroids onto the exact colors they will be stored as
+    let mut palette = Palette::new(rgba.iter().map(|c| to_vector(*c)).collect());
+    let indices = if dither {
+        remap_dithered(input, width, &mut palette)
+    } else {
+        input.chunks_exact(4).map(|p| palette.nearest(&to_vector(key(p)))).collect()
+    };
+
+    Quantized { palette: rgba, indices }
+}
+
+fn key(p: &[u8]) -> [u8; 4] {
+    if p[3] == 0 { [0; 4] } else { [p[0], p[1], p[2], p[3]] }
+}
+
+/// Lossless conversion when the image has few enough distinct colors; all
+/// fully transparent pixels share one entry
+fn exact_palette(input: &[u8], max_colors: usize) -> Option<Quantized> {
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    for p in input.chunks_exact(4) {
+        let len = lookup.len();
+        lookup.entry(key(p)).or_insert(len as u8);
+        if lookup.len() > max_colors {
+            return None;
+        }
+    }
+
+    let mut palette: Vec<[u8; 4]> = lookup.keys().copied().collect();
+    palette.sort_unstable();
+    for (i, c) in palette.iter().enumerate() {
+        lookup.insert(*c, i as u8);
+    }
+    let indices = input.chunks_exact(4).map(|p| lookup[&key(p)]).collect();
+    Some(Quantized { palette, indices })
+}
+
+/// Color histogram, dropping low bits until it is small enough for k-means.
+/// Each bucket holds the mean of the colors that fell into it.
+fn histogram(input: &[u8]) -> Vec<Entry> {
+    let mut shift = 0;
+    'coarser: loop {
+        let mut buckets: HashMap<[u8; 4], (Vector, f32)> = HashMap::new();
+        for p in input.chunks_exact(4) {
+            let c = key(p);
+            let bucket = buckets.entry(c.map(|x| x >> shift)).or_insert(([0.0; 4], 0.0));
+            accumulate(&mut bucket.0, &to_vector(c), 1.0);
+            bucket.1 += 1.0;
+            if buckets.len() > MAX_HISTOGRAM && shift < 4 {
+                shift += 1;
+                continue 'coarser;
+            }
+        }
+        // HashMap order differs between runs; sort so the palette doesn't
+        let mut buckets: Vec<_> = buckets.into_iter().collect();
+        buckets.sort_unstable_by_key(|(key, _)| *key);
+        return buckets
+            .into_iter()
+            .map(|(_, (sum, weight))| Entry {
+                color: sum.map(|x| x / weight),
+                weight,
+            })
+            .collect();
+    }
+}
+
+fn mean(entries: &[Entry]) -> Vector {
+    let mut sum = [0.0f32; 4];
+    let mut total = 0.0;
+    for e in entries {
+        accumulate(&mut sum, &e.color, e.weight);
+        total += e.weight;
+    }
+    sum.map(|x| x / total.max(f32::EPSILON))
+}
+
+/// Split the box with the largest weighted error along its widest channel,
+/// at the weighted median, until there are enough boxes
+fn median_cut(entries: Vec<Entry>, max_colors: usize) -> Vec<Vector> {
+    let mut boxes = vec![entries];
+    while boxes.len() < max_colors {
+        let scored = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let m = mean(b);
+                let error: f32 = b.iter().map(|e| distance(&e.color, &m) * e.weight).sum();
+                (i, error)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((index, _)) = scored else { break };
+
+        let mut entries = boxes.swap_remove(index);
+        let channel = (0..4)
+            .max_by(|&a, &b| {
+                let range = |c: usize| {
+                    let (lo, hi) = entries.iter().fold((f32::MAX, f32::MIN), |(lo, hi), e| (lo.min(e.color[c]), hi.max(e.color[c])));
+                    hi - lo
+                };
+                range(a).total_cmp(&range(b))
+            })
+            .unwrap_or(0);
+        entries.sort_unstable_by(|a, b| a.color[channel].total_cmp(&b.color[channel]));
+
+        let half: f32 = entries.iter().map(|e| e.weight).sum::<f32>() / 2.0;
+        let mut acc = 0.0;
+        let split = entries
+            .iter()
+            .position(|e| {
+                acc += e.weight;
+                acc >= half
+            })
+            .unwrap_or(0)
+            .clamp(0, entries.len() - 2)
+            + 1;
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| mean(b)).collect()
+}
+
+/// Lloyd's algorithm over the histogram; empty clusters keep their color
+fn refine(histogram: &[Entry], palette: &mut [Vector]) {
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![([0.0f32; 4], 0.0f32); palette.len()];
+        for e in histogram {
+            let (sum, weight) = &mut sums[nearest(palette, &e.color) as usize];
+            accumulate(sum, &e.color, e.weight);
+            *weight += e.weight;
+        }
+        for (entry, (sum, weight)) in palette.iter_mut().zip(sums) {
+            if weight > 0.0 {
+                *entry = sum.map(|x| x / weight);
+            }
+        }
+    }
+}
+
+fn nearest(palette: &[Vector], v: &Vector) -> u8 {
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+    for (i, p) in palette.iter().enumerate() {
+        let d = distance(p, v);
+        if d < best_distance {
+            best = i;
+            best_distance = d;
+        }
+    }
+    best as u8
+}
+
+/// Final palette, sorted along its widest channel so a nearest-color search
+/// can walk outwards from the query and stop once the difference in that
+/// channel alone exceeds the best distance found. The answer for a nearby
+/// color, cached per coarse cell, seeds the search so it stops early.
+struct Palette {
+    colors: Vec<Vector>,
+    axis: usize,
+    sorted: Vec<(Vector, u8)>,
+    hints: HashMap<[u8; 4], u8>,
+}
+
+impl Palette {
+    fn new(colors: Vec<Vector>) -> Palette {
+        let spread = |c: usize| {
+            let (lo, hi) = colors.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v[c]), hi.max(v[c])));
+            hi - lo
+        };
+        let axis = (0..4).max_by(|&a, &b| spread(a).total_cmp(&spread(b))).unwrap_or(0);
+        let mut sorted: Vec<(Vector, u8)> = colors.iter().enumerate().map(|(i, c)| (*c, i as u8)).collect();
+        sorted.sort_by(|a, b| a.0[axis].total_cmp(&b.0[axis]));
+        Palette {
+            colors,
+            axis,
+            sorted,
+            hints: HashMap::new(),
+        }
+    }
+
+    fn color(&self, index: u8) -> Vector {
+        self.colors[index as usize]
+    }
+
+    fn nearest(&mut self, v: &Vector) -> u8 {
+        let cell = v.map(|x| (x as u8) >> HINT_SHIFT);
+        let hint = self.hints.get(&cell).copied();
+        let mut best = match hint {
+            Some(hint) => (distance(&self.colors[hint as usize], v), hint),
+            None => (f32::MAX, 0u8),
+        };
+
+        let x = v[self.axis];
+        let start = self.sorted.partition_point(|(c, _)| c[self.axis] < x);
+        let (mut below, mut above) = (start, start);
+        loop {
+            let gap_below = below.checked_sub(1).map(|i| x - self.sorted[i].0[self.axis]);
+            let gap_above = self.sorted.get(above).map(|(c, _)| c[self.axis] - x);
+            let (gap, (color, index)) = match (gap_below, gap_above) {
+                (Some(b), a) if a.is_none_or(|a| b <= a) => {
+                    below -= 1;
+                    (b, &self.sorted[below])
+                }
+                (_, Some(a)) => {
+                    above += 1;
+                    (a, &self.sorted[above - 1])
+                }
+                _ => break,
+            };
+            if gap * gap >= best.0 {
+                break;
+            }
+            let d = distance(color, v);
+            if d < best.0 {
+                best = (d, *index);
+            }
+        }
+        if hint.is_none() {
+            self.hints.insert(cell, best.1);
+        }
+        best.1
+    }
+}
+
+/// Floyd-Steinberg in premultiplied space, alternating direction per row
+fn remap_dithered(input: &[u8], width: usize, palette: &mut Palette) -> Vec<u8> {
+    let height = input.len() / 4 / width;
+    let mut indices = vec![0u8; width * height];
+    let mut current = vec![[0.0f32; 4]; width + 2];
+    let mut next = vec![[0.0f32; 4]; width + 2];
+
+    for y in 0..height {
+        let reverse = y % 2 == 1;
+        for step in 0..width {
+            let x = if reverse { width - 1 - step } else { step };
+            let p = &input[(y * width + x) * 4..][..4];
+            let source = to_vector([p[0], p[1], p[2], p[3]]);
+
+            let err = current[x + 1];
+            let mut target = [0.0f32; 4];
+            for i in 0..4 {
+                target[i] = (source[i] + err[i]).clamp(0.0, 255.0);
+            }
+            // Color can't exceed its own coverage in premultiplied space
+            for i in 0..3 {
+                target[i] = target[i].min(target[3]);
+            }
+
+            let index = palette.nearest(&target);
+            indices[y * width + x] = index;
+
+            let chosen = palette.color(index);
+            let mut e = [0.0f32; 4];
+            for i in 0..4 {
+                e[i] = target[i] - chosen[i];
+            }
+            let (ahead, behind) = if reverse { (x, x + 2) } else { (x + 2, x) };
+            for i in 0..4 {
+                current[ahead][i] += e[i] * 7.0 / 16.0;
+                next[behind][i] += e[i] * 3.0 / 16.0;
+                next[x + 1][i] += e[i] * 5.0 / 16.0;
+                next[ahead][i] += e[i] / 16.0;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|e| *e = [0.0; 4]);
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let colors = [[255, 0, 0, 255], [0, 0, 255, 128], [7, 8, 9, 0], [1, 2, 3, 0]];
+        let input: Vec<u8> = (0..16).flat_map(|i| colors[i % 4]).collect();
+        let q = quantize(&input, 4, 256, true);
+
+        // Both fully transparent colors share one entry
+        assert_eq!(q.palette.len(), 3);
+        for (i, index) in q.indices.iter().enumerate() {
+            let expected = if colors[i % 4][3] == 0 { [0; 4] } else { colors[i % 4] };
+            assert_eq!(q.palette[*index as usize], expected);
+        }
+    }
+
+    #[test]
+    fn dithering_preserves_gradient_average() {
+        // A smooth ramp forced into four colors
+        let width = 256;
+        let input: Vec<u8> = (0..width * 8).flat_map(|i| [(i % width) as u8, 0, 0, 255]).collect();
+        let plain = quantize(&input, width, 4, false);
+        let dithered = quantize(&input, width, 4, true);
+        assert!(plain.palette.len() <= 4 && dithered.palette.len() <= 4);
+
+        let column_mean = |q: &Quantized, x: usize| {
+            (0..8).map(|y| q.palette[q.indices[y * width + x] as usize][0] as f32).sum::<f32>() / 8.0
+        };
+        let error = |q: &Quantized| (0..width).map(|x| (column_mean(q, x) - x as f32).abs()).sum::<f32>() / width as f32;
+        assert!(error(&dithered) < error(&plain));
+    }
+}
@@ -0,0 +1,1067 @@
+//! Lossy WebP: a key-frame-only VP8 encoder (RFC 6386).
+//!
+//! Macroblocks are predicted with the whole-block luma and chroma modes
+//! (DC, V, H, TM), transformed, quantized and arithmetic coded into a single
+//! token partition. Everything after the quality mapping is integer
+//! arithmetic, so the same input always produces the same bytes.
+
+use photo_editor_common::Error;
+
+use crate::vp8_tables::{
+    TokenProbs, AC_QUANT, COEFF_BANDS, COEFF_PROBS, COEFF_UPDATE_PROBS, DC_QUANT, DCT_CAT_BASE,
+    PROB_DCT_CAT, ZIGZAG,
+};
+use crate::webp::WebPAdvancedParams;
+
+/// Largest width or height a VP8 frame header can describe
+pub(crate) const MAX_DIMENSION: u32 = 16383;
+
+/// The frame tag stores the first partition's size in 19 bits
+const MAX_PARTITION0_SIZE: usize = 1 << 19;
+
+// Whole-block prediction modes, numbered as in the bitstream
+const DC_PRED: u8 = 0;
+const V_PRED: u8 = 1;
+const H_PRED: u8 = 2;
+const TM_PRED: u8 = 3;
+const MODES: [u8; 4] = [DC_PRED, V_PRED, H_PRED, TM_PRED];
+
+// Token probability planes
+const PLANE_Y_AFTER_Y2: usize = 0;
+const PLANE_Y2: usize = 1;
+const PLANE_UV: usize = 2;
+
+// Largest level a DCT_CAT6 token can carry
+const MAX_LEVEL: i32 = 2048;
+
+// Rounding offsets in 1/128 of a quantizer step; AC is biased towards zero
+const DC_ROUNDING: i32 = 64;
+const AC_ROUNDING: i32 = 44;
+
+/**
+ * Boolean entropy encoder (RFC 6386 section 7.3)
+ */
+struct BoolEncoder {
+    output: Vec<u8>,
+    range: u32,
+    bottom: u32,
+    bit_count: i32,
+}
+
+impl BoolEncoder {
+    fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            range: 255,
+            bottom: 0,
+            bit_count: 24,
+        }
+    }
+
+    /// Write `bit`, where `prob` is the probability (out of 256) of a zero
+    fn put(&mut self, prob: u8, bit: bool) {
+        let split = 1 + (((self.range - 1) * prob as u32) >> 8);
+        if bit {
+            self.bottom += split;
+            self.range -= split;
+        } else {
+            self.range = split;
+        }
+
+        while self.range < 128 {
+            self.range <<= 1;
+            if self.bottom & (1 << 31) != 0 {
+                self.carry();
+            }
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+            if self.bit_count == 0 {
+                self.output.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+
+    fn carry(&mut self) {
+        for byte in self.output.iter_mut().rev() {
+            if *byte == 255 {
+                *byte = 0;
+            } else {
+                *byte += 1;
+                return;
+            }
+        }
+    }
+
+    fn put_flag(&mut self, bit: bool) {
+        self.put(128, bit);
+    }
+
+    fn put_literal(&mut self, value: u32, bits: u32) {
+        for i in (0..bits).rev() {
+            self.put_flag((value >> i) & 1 != 0);
+        }
+    }
+
+    fn put_optional_signed(&mut self, value: i32, bits: u32) {
+        self.put_flag(value != 0);
+        if value != 0 {
+            self.put_literal(value.unsigned_abs(), bits);
+            self.put_flag(value < 0);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let mut count = self.bit_count;
+        let mut value = self.bottom;
+        if value & (1 << (32 - count)) != 0 {
+            self.carry();
+        }
+        value <<= count & 7;
+        count >>= 3;
+        while count > 0 {
+            value <<= 8;
+            count -= 1;
+        }
+        for _ in 0..4 {
+            self.output.push((value >> 24) as u8);
+            value <<= 8;
+        }
+        self.output
+    }
+}
+
+type TokenCounts = [[[[[u32; 2]; 11]; 3]; 8]; 4];
+
+/**
+ * Writes DCT tokens, optionally counting every branch so the token
+ * probabilities can be re-estimated for a second pass
+ */
+struct TokenWriter {
+    encoder: BoolEncoder,
+    probs: TokenProbs,
+    counts: Option<Box<TokenCounts>>,
+}
+
+impl TokenWriter {
+    fn put(&mut self, slot: (usize, usize, usize), node: usize, bit: bool) {
+        let (plane, band, ctx) = slot;
+        if let Some(counts) = &mut self.counts {
+            counts[plane][band][ctx][node][bit as usize] += 1;
+        }
+        self.encoder.put(self.probs[plane][band][ctx][node], bit);
+    }
+
+    /// Code one 4x4 block of levels in zigzag order; returns whether any
+    /// token other than an immediate end-of-block was written
+    fn put_block(&mut self, levels: &[i32; 16], first: usize, plane: usize, mut ctx: usize) -> bool {
+        let last = match (first..16).rev().find(|&i| levels[i] != 0) {
+            Some(last) => last,
+            None => {
+                self.put((plane, COEFF_BANDS[first], ctx), 0, false);
+                return false;
+            }
+        };
+
+        let mut after_zero = false;
+        for (i, &level) in levels.iter().enumerate().take(last + 1).skip(first) {
+            let slot = (plane, COEFF_BANDS[i], ctx);
+            // DCT_EOB cannot follow DCT_0, so that branch is not coded
+            if !after_zero {
+                self.put(slot, 0, true);
+            }
+
+            let value = level.unsigned_abs() as u16;
+            self.put(slot, 1, value != 0);
+            after_zero = value == 0;
+            if value == 0 {
+                ctx = 0;
+                continue;
+            }
+
+            self.put(slot, 2, value > 1);
+            if value > 1 {
+                self.put(slot, 3, value > 4);
+                if value <= 4 {
+                    self.put(slot, 4, value > 2);
+                    if value > 2 {
+                        self.put(slot, 5, value == 4);
+                    }
+                } else {
+                    let cat = DCT_CAT_BASE.iter().rposition(|&base| value >= base).unwrap_or(0);
+                    self.put(slot, 6, cat >= 2);
+                    if cat < 2 {
+                        self.put(slot, 7, cat == 1);
+                    } else {
+                        self.put(slot, 8, cat >= 4);
+                        if cat < 4 {
+                            self.put(slot, 9, cat == 3);
+                        } else {
+                            self.put(slot, 10, cat == 5);
+                        }
+                    }
+
+                    let extra = value - DCT_CAT_BASE[cat];
+                    let probs = PROB_DCT_CAT[cat];
+                    let bits = probs.iter().position(|&p| p == 0).unwrap_or(probs.len());
+                    for (b, &prob) in probs[..bits].iter().enumerate() {
+                        self.encoder.put(prob, (extra >> (bits - 1 - b)) & 1 != 0);
+                    }
+                }
+            }
+            self.encoder.put_flag(level < 0);
+            ctx = if value > 1 { 2 } else { 1 };
+        }
+
+        if last < 15 {
+            self.put((plane, COEFF_BANDS[last + 1], ctx), 0, false);
+        }
+        true
+    }
+}
+
+/// Quantizer steps for one segment; luma DC is carried by the Y2 block
+#[derive(Clone, Copy, Default)]
+struct Quant {
+    y_ac: i32,
+    y2_dc: i32,
+    y2_ac: i32,
+    uv_dc: i32,
+    uv_ac: i32,
+}
+
+impl Quant {
+    /// Mirrors the decoder's derivation from the quantizer index
+    fn new(index: i32) -> Self {
+        let index = index.clamp(0, 127) as usize;
+        Self {
+            y_ac: AC_QUANT[index],
+            y2_dc: DC_QUANT[index] * 2,
+            y2_ac: (AC_QUANT[index] * 155 / 100).max(8),
+            uv_dc: DC_QUANT[index].min(132),
+            uv_ac: AC_QUANT[index],
+        }
+    }
+
+    /// Rate-distortion weight: squared error per estimated bit, scaled by 64
+    fn lambda(&self) -> i64 {
+        (self.y_ac * self.y_ac) as i64 * 64 / 40
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct MacroblockInfo {
+    segment: u8,
+    luma: u8,
+    chroma: u8,
+    skip: bool,
+}
+
+/// Quantized luma of one macroblock and its reconstruction
+struct LumaResult {
+    y2: [i32; 16],
+    y: [[i32; 16]; 16],
+    recon: [u8; 256],
+}
+
+/// Quantized chroma (4 U then 4 V blocks) and its reconstruction
+struct ChromaResult {
+    blocks: [[i32; 16]; 8],
+    recon_u: [u8; 64],
+    recon_v: [u8; 64],
+}
+
+/// Prediction edges of a block: row above, column to the left, corner
+struct Edges<const N: usize> {
+    above: [u8; N],
+    left: [u8; N],
+    corner: u8,
+    has_above: bool,
+    has_left: bool,
+}
+
+struct Plane {
+    data: Vec<u8>,
+    stride: usize,
+}
+
+impl Plane {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            data: vec![0; width * height],
+            stride: width,
+        }
+    }
+
+    fn block<const N: usize, const S: usize>(&self, bx: usize, by: usize) -> [u8; S] {
+        let mut out = [0u8; S];
+        for y in 0..N {
+            let start = (by * N + y) * self.stride + bx * N;
+            out[y * N..(y + 1) * N].copy_from_slice(&self.data[start..start + N]);
+        }
+        out
+    }
+
+    fn store<const N: usize>(&mut self, bx: usize, by: usize, pixels: &[u8]) {
+        for y in 0..N {
+            let start = (by * N + y) * self.stride + bx * N;
+            self.data[start..start + N].copy_from_slice(&pixels[y * N..(y + 1) * N]);
+        }
+    }
+
+    /// Edges as the decoder sees them: 127 above the frame, 129 left of it
+    fn edges<const N: usize>(&self, bx: usize, by: usize) -> Edges<N> {
+        let mut edges = Edges {
+            above: [127; N],
+            left: [129; N],
+            corner: if by == 0 { 127 } else { 129 },
+            has_above: by > 0,
+            has_left: bx > 0,
+        };
+        if by > 0 {
+            let start = (by * N - 1) * self.stride + bx * N;
+            edges.above.copy_from_slice(&self.data[start..start + N]);
+            if bx > 0 {
+                edges.corner = self.data[start - 1];
+            }
+        }
+        if bx > 0 {
+            for (y, left) in edges.left.iter_mut().enumerate() {
+                *left = self.data[(by * N + y) * self.stride + bx * N - 1];
+            }
+        }
+        edges
+    }
+}
+
+struct Encoder {
+    mb_width: usize,
+    mb_height: usize,
+    source: [Plane; 3],
+    recon: [Plane; 3],
+    method: u8,
+    quant: [Quant; 4],
+    segment_map: Vec<u8>,
+    top_nz: Vec<[bool; 9]>,
+    left_nz: [bool; 9],
+}
+
+/**
+ * Encode RGBA pixels as a VP8 key frame
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels (at most 16383)
+ * * `height` - Image height in pixels (at most 16383)
+ * * `quality` - Quality (1-100), mapped to the quantizer index
+ * * `params` - Search effort, loop filter and spatial noise shaping
+ *
+ * # Returns
+ * The VP8 bitstream, ready to be wrapped in a "VP8 " chunk. Alpha is ignored.
+ * Fails when the mode data outgrows the first partition's size field.
+ */
+pub(crate) fn encode(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &WebPAdvancedParams,
+) -> Result<Vec<u8>, Error> {
+    let base_index = quality_to_index(quality);
+    let mut encoder = Encoder::new(input, width as usize, height as usize, params.method);
+    let (segment_deltas, segments_enabled) = encoder.assign_segments(base_index, params.sns_strength);
+    for (quant, delta) in encoder.quant.iter_mut().zip(segment_deltas) {
+        *quant = Quant::new(base_index + delta);
+    }
+
+    // Token statistics only change the probabilities, never the decisions,
+    // so a counting pass gives exactly the tokens of the final pass
+    let mut probs = COEFF_PROBS;
+    let mut updated = [[[[false; 11]; 3]; 8]; 4];
+    if params.method >= 5 {
+        let (_, writer) = encoder.encode_macroblocks(COEFF_PROBS, true);
+        if let Some(counts) = writer.counts {
+            (probs, updated) = optimize_probs(&counts);
+        }
+        encoder.reset();
+    }
+    let (info, writer) = encoder.encode_macroblocks(probs, false);
+    let tokens = writer.encoder.finish();
+
+    let mut header = BoolEncoder::new();
+    header.put_literal(0, 1); // color space: YUV
+    header.put_literal(0, 1); // clamping required
+
+    header.put_flag(segments_enabled);
+    let tree_probs = segment_tree_probs(&info);
+    if segments_enabled {
+        header.put_flag(true); // update map
+        header.put_flag(true); // update data
+        header.put_flag(false); // quantizers are deltas
+        for delta in segment_deltas {
+            header.put_optional_signed(delta, 7);
+        }
+        for _ in 0..4 {
+            header.put_optional_signed(0, 6); // loop filter deltas
+        }
+        for prob in tree_probs {
+            header.put_flag(true);
+            header.put_literal(prob as u32, 8);
+        }
+    }
+
+    header.put_flag(false); // normal loop filter
+    header.put_literal(filter_level(base_index, params.filter_strength), 6);
+    header.put_literal(params.filter_sharpness.min(100) as u32 * 7 / 100, 3);
+    header.put_flag(false); // no mode/reference loop filter adjustments
+    header.put_literal(0, 2); // one token partition
+
+    header.put_literal(base_index as u32, 7);
+    for _ in 0..5 {
+        header.put_optional_signed(0, 4); // no per-plane quantizer deltas
+    }
+    header.put_literal(0, 1); // refresh entropy probabilities
+
+    for (i, plane) in COEFF_UPDATE_PROBS.iter().enumerate() {
+        for (j, band) in plane.iter().enumerate() {
+            for (k, ctx) in band.iter().enumerate() {
+                for (t, &update_prob) in ctx.iter().enumerate() {
+                    header.put(update_prob, updated[i][j][k][t]);
+                    if updated[i][j][k][t] {
+                        header.put_literal(probs[i][j][k][t] as u32, 8);
+                    }
+                }
+            }
+        }
+    }
+
+    let coded = info.iter().filter(|mb| !mb.skip).count();
+    let skip_prob = probability(coded, info.len());
+    header.put_flag(true); // per-macroblock skip flags
+    header.put_literal(skip_prob as u32, 8);
+
+    for mb in &info {
+        if segments_enabled {
+            header.put(tree_probs[0], mb.segment >= 2);
+            header.put(tree_probs[1 + (mb.segment >= 2) as usize], mb.segment & 1 != 0);
+        }
+        header.put(skip_prob, mb.skip);
+        // Luma tree: B_PRED is the first branch and is never used
+        header.put(145, true);
+        header.put(156, mb.luma >= H_PRED);
+        header.put(if mb.luma >= H_PRED { 128 } else { 163 }, mb.luma & 1 != 0);
+        header.put(142, mb.chroma != DC_PRED);
+        if mb.chroma != DC_PRED {
+            header.put(114, mb.chroma != V_PRED);
+            if mb.chroma != V_PRED {
+                header.put(183, mb.chroma == TM_PRED);
+            }
+        }
+    }
+    let first_partition = header.finish();
+
+    let mut out = Vec::with_capacity(10 + first_partition.len() + tokens.len());
+    out.extend_from_slice(&frame_tag(first_partition.len())?);
+    out.extend_from_slice(&[0x9d, 0x01, 0x2a]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.extend_from_slice(&first_partition);
+    out.extend_from_slice(&tokens);
+    Ok(out)
+}
+
+/// Key frame, version 0, shown, followed by the first partition size
+fn frame_tag(first_partition_len: usize) -> Result<[u8; 3], Error> {
+    if first_partition_len >= MAX_PARTITION0_SIZE {
+        return Err(Error::encode_failed("VP8 first partition exceeds 512 KiB; lower the quality or size"));
+    }
+    let tag = (1 << 4) | ((first_partition_len as u32) << 5);
+    let [a, b, c, _] = tag.to_le_bytes();
+    Ok([a, b, c])
+}
+
+/// Map quality 1-100 to a quantizer index 127-0 with the same shape as
+/// libwebp, so quality settings feel familiar
+fn quality_to_index(quality: u8) -> i32 {
+    let c = quality.clamp(1, 100) as f64 / 100.0;
+    let linear = if c < 0.75 { c * (2.0 / 3.0) } else { 2.0 * c - 1.0 };
+    (127.0 * (1.0 - linear.cbrt())).round().clamp(0.0, 127.0) as i32
+}
+
+/// Loop filter level grows with the quantizer; strength 0 disables it
+fn filter_level(index: i32, strength: u8) -> u32 {
+    let full = (8 + index / 2).min(63) as u32;
+    full * strength.min(100) as u32 / 100
+}
+
+/// Probability of a zero bit given `zeros` out of `total`
+fn probability(zeros: usize, total: usize) -> u8 {
+    if total == 0 {
+        return 128;
+    }
+    (zeros * 255 / total).clamp(1, 255) as u8
+}
+
+fn segment_tree_probs(info: &[MacroblockInfo]) -> [u8; 3] {
+    let mut counts = [0usize; 4];
+    for mb in info {
+        counts[mb.segment as usize] += 1;
+    }
+    [
+        probability(counts[0] + counts[1], info.len()),
+        probability(counts[0], counts[0] + counts[1]),
+        probability(counts[2], counts[2] + counts[3]),
+    ]
+}
+
+/// Cost in 1/256 bits of coding `bit` with probability `prob`
+fn bit_cost(prob: u8, bit: bool) -> f64 {
+    let p = if bit { 256 - prob as u32 } else { prob as u32 };
+    -(p as f64 / 256.0).log2() * 256.0
+}
+
+/// Re-estimate token probabilities from counts, keeping only updates that
+/// pay for their own signalling
+fn optimize_probs(counts: &TokenCounts) -> (TokenProbs, [[[[bool; 11]; 3]; 8]; 4]) {
+    let mut probs = COEFF_PROBS;
+    let mut updated = [[[[false; 11]; 3]; 8]; 4];
+    for i in 0..4 {
+        for j in 0..8 {
+            for k in 0..3 {
+                for t in 0..11 {
+                    let [zeros, ones] = counts[i][j][k][t];
+                    let total = (zeros + ones) as usize;
+                    if total == 0 {
+                        continue;
+                    }
+                    let old = COEFF_PROBS[i][j][k][t];
+                    let new = probability(zeros as usize, total);
+                    let cost = |p: u8| zeros as f64 * bit_cost(p, false) + ones as f64 * bit_cost(p, true);
+                    let update_prob = COEFF_UPDATE_PROBS[i][j][k][t];
+                    let signalling = bit_cost(update_prob, true) + 8.0 * 256.0 - bit_cost(update_prob, false);
+                    if cost(new) + signalling < cost(old) {
+                        probs[i][j][k][t] = new;
+                        updated[i][j][k][t] = true;
+                    }
+                }
+            }
+        }
+    }
+    (probs, updated)
+}
+
+impl Encoder {
+    fn new(input: &[u8], width: usize, height: usize, method: u8) -> Self {
+        let mb_width = width.div_ceil(16);
+        let mb_height = height.div_ceil(16);
+        let (luma_w, luma_h) = (mb_width * 16, mb_height * 16);
+
+        let mut source = [
+            Plane::new(luma_w, luma_h),
+            Plane::new(luma_w / 2, luma_h / 2),
+            Plane::new(luma_w / 2, luma_h / 2),
+        ];
+
+        // Pad to whole macroblocks by repeating the last row and column
+        let pixel = |x: usize, y: usize| {
+            let i = (y.min(height - 1) * width + x.min(width - 1)) * 4;
+            [input[i] as i32, input[i + 1] as i32, input[i + 2] as i32]
+        };
+        for y in 0..luma_h {
+            for x in 0..luma_w {
+                let [r, g, b] = pixel(x, y);
+                source[0].data[y * luma_w + x] =
+                    ((16839 * r + 33059 * g + 6420 * b + (16 << 16) + (1 << 15)) >> 16) as u8;
+            }
+        }
+        for y in 0..luma_h / 2 {
+            for x in 0..luma_w / 2 {
+                let (mut r, mut g, mut b) = (0, 0, 0);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let [pr, pg, pb] = pixel(2 * x + dx, 2 * y + dy);
+                    r += pr;
+                    g += pg;
+                    b += pb;
+                }
+                let u = (-9719 * r - 19081 * g + 28800 * b + (128 << 18) + (1 << 17)) >> 18;
+                let v = (28800 * r - 24116 * g - 4684 * b + (128 << 18) + (1 << 17)) >> 18;
+                source[1].data[y * luma_w / 2 + x] = u.clamp(0, 255) as u8;
+                source[2].data[y * luma_w / 2 + x] = v.clamp(0, 255) as u8;
+            }
+        }
+
+        Self {
+            mb_width,
+            mb_height,
+            source,
+            recon: [
+                Plane::new(luma_w, luma_h),
+                Plane::new(luma_w / 2, luma_h / 2),
+                Plane::new(luma_w / 2, luma_h / 2),
+            ],
+            method,
+            quant: [Quant::default(); 4],
+            segment_map: vec![0; mb_width * mb_height],
+            top_nz: vec![[false; 9]; mb_width],
+            left_nz: [false; 9],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.top_nz.iter_mut().for_each(|nz| *nz = [false; 9]);
+        self.left_nz = [false; 9];
+    }
+
+    /**
+     * Spatial noise shaping: split macroblocks into four segments by luma
+     * variance and give busy segments, where noise is masked, a coarser
+     * quantizer than flat ones
+     *
+     * # Returns
+     * (quantizer deltas per segment, whether segmentation is used)
+     */
+    fn assign_segments(&mut self, base_index: i32, sns_strength: u8) -> ([i32; 4], bool) {
+        let count = self.mb_width * self.mb_height;
+        let amplitude = sns_strength.min(100) as i32 * 24 / 100;
+        if amplitude == 0 || count < 4 {
+            return ([0; 4], false);
+        }
+
+        let activity: Vec<u32> = (0..count)
+            .map(|i| {
+                let block: [u8; 256] = self.source[0].block::<16, 256>(i % self.mb_width, i / self.mb_width);
+                let sum: u32 = block.iter().map(|&p| p as u32).sum();
+                let mean = (sum + 128) / 256;
+                block.iter().map(|&p| (p as i32 - mean as i32).unsigned_abs()).sum()
+            })
+            .collect();
+        let mut sorted = activity.clone();
+        sorted.sort_unstable();
+        let thresholds = [sorted[count / 4], sorted[count / 2], sorted[count * 3 / 4]];
+        for (segment, &act) in self.segment_map.iter_mut().zip(&activity) {
+            *segment = thresholds.iter().filter(|&&t| act > t).count() as u8;
+        }
+
+        // Keep every segment's index inside the valid range
+        let deltas = [-amplitude / 2, -amplitude / 6, amplitude / 6, amplitude / 2];
+        (deltas.map(|d| (base_index + d).clamp(0, 127) - base_index), true)
+    }
+
+    fn encode_macroblocks(&mut self, probs: TokenProbs, count: bool) -> (Vec<MacroblockInfo>, TokenWriter) {
+        let mut writer = TokenWriter {
+            encoder: BoolEncoder::new(),
+            probs,
+            counts: count.then(|| Box::new([[[[[0; 2]; 11]; 3]; 8]; 4])),
+        };
+        let mut info = Vec::with_capacity(self.mb_width * self.mb_height);
+        for mby in 0..self.mb_height {
+            self.left_nz = [false; 9];
+            for mbx in 0..self.mb_width {
+                info.push(self.encode_macroblock(mbx, mby, &mut writer));
+            }
+        }
+        (info, writer)
+    }
+
+    fn encode_macroblock(&mut self, mbx: usize, mby: usize, writer: &mut TokenWriter) -> MacroblockInfo {
+        let segment = self.segment_map[mby * self.mb_width + mbx];
+        let quant = self.quant[segment as usize];
+        let candidates: &[u8] = if self.method >= 2 { &MODES } else { &MODES[..1] };
+
+        let src_y: [u8; 256] = self.source[0].block::<16, 256>(mbx, mby);
+        let edges_y = self.recon[0].edges::<16>(mbx, mby);
+        let (luma, luma_mode) = self.choose(candidates, |mode| {
+            let pred = predict::<16, 256>(mode, &edges_y);
+            let result = quantize_luma(&src_y, &pred, &quant);
+            let rate = result.y.iter().chain([&result.y2]).map(rate_estimate).sum();
+            let recon_sse = sse(&src_y, &result.recon);
+            (result, sse(&src_y, &pred), recon_sse, rate)
+        }, &quant);
+
+        let src_u: [u8; 64] = self.source[1].block::<8, 64>(mbx, mby);
+        let src_v: [u8; 64] = self.source[2].block::<8, 64>(mbx, mby);
+        let edges_u = self.recon[1].edges::<8>(mbx, mby);
+        let edges_v = self.recon[2].edges::<8>(mbx, mby);
+        let (chroma, chroma_mode) = self.choose(candidates, |mode| {
+            let pred_u = predict::<8, 64>(mode, &edges_u);
+            let pred_v = predict::<8, 64>(mode, &edges_v);
+            let result = quantize_chroma(&src_u, &pred_u, &src_v, &pred_v, &quant);
+            let rate = result.blocks.iter().map(rate_estimate).sum();
+            let pred_sse = sse(&src_u, &pred_u) + sse(&src_v, &pred_v);
+            let recon_sse = sse(&src_u, &result.recon_u) + sse(&src_v, &result.recon_v);
+            (result, pred_sse, recon_sse, rate)
+        }, &quant);
+
+        self.recon[0].store::<16>(mbx, mby, &luma.recon);
+        self.recon[1].store::<8>(mbx, mby, &chroma.recon_u);
+        self.recon[2].store::<8>(mbx, mby, &chroma.recon_v);
+
+        let skip = luma.y2.iter().all(|&l| l == 0)
+            && luma.y.iter().flatten().all(|&l| l == 0)
+            && chroma.blocks.iter().flatten().all(|&l| l == 0);
+        if skip {
+            self.top_nz[mbx] = [false; 9];
+            self.left_nz = [false; 9];
+        } else {
+            self.put_residuals(mbx, &luma, &chroma, writer);
+        }
+
+        MacroblockInfo {
+            segment,
+            luma: luma_mode,
+            chroma: chroma_mode,
+            skip,
+        }
+    }
+
+    /**
+     * Pick the best prediction mode
+     *
+     * Fast methods compare prediction error only; from method 4 on each
+     * mode is fully quantized and scored by reconstruction error plus
+     * estimated rate.
+     */
+    fn choose<T>(&self, candidates: &[u8], mut trial: impl FnMut(u8) -> (T, u64, u64, u32), quant: &Quant) -> (T, u8) {
+        let mut best: Option<(T, u8, i64)> = None;
+        for &mode in candidates {
+            let (result, pred_sse, recon_sse, rate) = trial(mode);
+            let score = if self.method >= 4 {
+                recon_sse as i64 * 64 + quant.lambda() * rate as i64
+            } else {
+                pred_sse as i64
+            };
+            if best.as_ref().is_none_or(|(_, _, s)| score < *s) {
+                best = Some((result, mode, score));
+            }
+        }
+        let (result, mode, _) = best.expect("at least one prediction mode");
+        (result, mode)
+    }
+
+    fn put_residuals(&mut self, mbx: usize, luma: &LumaResult, chroma: &ChromaResult, writer: &mut TokenWriter) {
+        let top = &mut self.top_nz[mbx];
+        let left = &mut self.left_nz;
+
+        let ctx = top[0] as usize + left[0] as usize;
+        let nz = writer.put_block(&zigzag(&luma.y2), 0, PLANE_Y2, ctx);
+        top[0] = nz;
+        left[0] = nz;
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let ctx = top[1 + x] as usize + left[1 + y] as usize;
+                let nz = writer.put_block(&zigzag(&luma.y[y * 4 + x]), 1, PLANE_Y_AFTER_Y2, ctx);
+                top[1 + x] = nz;
+                left[1 + y] = nz;
+            }
+        }
+
+        // U uses context slots 5-6, V uses 7-8
+        for (i, block) in chroma.blocks.iter().enumerate() {
+            let base = 5 + (i / 4) * 2;
+            let (x, y) = (i % 2, (i / 2) % 2);
+            let ctx = top[base + x] as usize + left[base + y] as usize;
+            let nz = writer.put_block(&zigzag(block), 0, PLANE_UV, ctx);
+            top[base + x] = nz;
+            left[base + y] = nz;
+        }
+    }
+}
+
+fn zigzag(raster: &[i32; 16]) -> [i32; 16] {
+    ZIGZAG.map(|i| raster[i])
+}
+
+fn sse(a: &[u8], b: &[u8]) -> u64 {
+    a.iter().zip(b).map(|(&x, &y)| (x as i64 - y as i64).pow(2) as u64).sum()
+}
+
+/// Rough bit count of a block of levels, used for mode decisions
+fn rate_estimate(levels: &[i32; 16]) -> u32 {
+    levels
+        .iter()
+        .filter(|&&l| l != 0)
+        .map(|&l| 3 + 2 * (32 - l.unsigned_abs().leading_zeros()))
+        .sum()
+}
+
+/// Whole-block prediction, bit-exact with the decoder
+fn predict<const N: usize, const S: usize>(mode: u8, edges: &Edges<N>) -> [u8; S] {
+    let mut out = [0u8; S];
+    match mode {
+        V_PRED => {
+            for row in out.chunks_exact_mut(N) {
+                row.copy_from_slice(&edges.above);
+            }
+        }
+        H_PRED => {
+            for (row, &left) in out.chunks_exact_mut(N).zip(&edges.left) {
+                row.fill(left);
+            }
+        }
+        TM_PRED => {
+            for (row, &left) in out.chunks_exact_mut(N).zip(&edges.left) {
+                for (p, &above) in row.iter_mut().zip(&edges.above) {
+                    *p = (left as i32 + above as i32 - edges.corner as i32).clamp(0, 255) as u8;
+                }
+            }
+        }
+        _ => {
+            let mut shift = if N == 8 { 2 } else { 3 };
+            let mut sum = 0u32;
+            if edges.has_above {
+                sum += edges.above.iter().map(|&p| p as u32).sum::<u32>();
+                shift += 1;
+            }
+            if edges.has_left {
+                sum += edges.left.iter().map(|&p| p as u32).sum::<u32>();
+                shift += 1;
+            }
+            let dc = if edges.has_above || edges.has_left {
+                (sum + (1 << (shift - 1))) >> shift
+            } else {
+                128
+            };
+            out.fill(dc as u8);
+        }
+    }
+    out
+}
+
+fn quantize(coeff: i32, step: i32, rounding: i32) -> i32 {
+    let level = ((coeff.abs() * 128 + step * rounding) / (step * 128)).min(MAX_LEVEL);
+    if coeff < 0 { -level } else { level }
+}
+
+/// Residual of the 4x4 block at (`bx`, `by`) of an `n`-wide macroblock
+fn residual(src: &[u8], pred: &[u8], n: usize, bx: usize, by: usize) -> [i32; 16] {
+    let mut out = [0i32; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            let i = (by * 4 + y) * n + bx * 4 + x;
+            out[y * 4 + x] = src[i] as i32 - pred[i] as i32;
+        }
+    }
+    out
+}
+
+fn add_residual(recon: &mut [u8], pred: &[u8], res: &[i32; 16], n: usize, bx: usize, by: usize) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let i = (by * 4 + y) * n + bx * 4 + x;
+            recon[i] = (pred[i] as i32 + res[y * 4 + x]).clamp(0, 255) as u8;
+        }
+    }
+}
+
+fn quantize_luma(src: &[u8; 256], pred: &[u8; 256], q: &Quant) -> LumaResult {
+    let mut y = [[0i32; 16]; 16];
+    let mut dc = [0i32; 16];
+    for (i, levels) in y.iter_mut().enumerate() {
+        let coeffs = fdct(&residual(src, pred, 16, i % 4, i / 4));
+        dc[i] = coeffs[0];
+        for k in 1..16 {
+            levels[k] = quantize(coeffs[k], q.y_ac, AC_ROUNDING);
+        }
+    }
+
+    let wht = fwht(&dc);
+    let mut y2 = [0i32; 16];
+    for k in 0..16 {
+        y2[k] = if k == 0 {
+            quantize(wht[k], q.y2_dc, DC_ROUNDING)
+        } else {
+            quantize(wht[k], q.y2_ac, DC_ROUNDING)
+        };
+    }
+
+    // Reconstruct exactly as the decoder will
+    let mut dc = [0i32; 16];
+    for k in 0..16 {
+        dc[k] = y2[k] * if k == 0 { q.y2_dc } else { q.y2_ac };
+    }
+    iwht(&mut dc);
+    let mut recon = [0u8; 256];
+    for (i, levels) in y.iter().enumerate() {
+        let mut coeffs = [0i32; 16];
+        coeffs[0] = dc[i];
+        for k in 1..16 {
+            coeffs[k] = levels[k] * q.y_ac;
+        }
+        idct(&mut coeffs);
+        add_residual(&mut recon, pred, &coeffs, 16, i % 4, i / 4);
+    }
+
+    LumaResult { y2, y, recon }
+}
+
+fn quantize_chroma(src_u: &[u8; 64], pred_u: &[u8; 64], src_v: &[u8; 64], pred_v: &[u8; 64], q: &Quant) -> ChromaResult {
+    let mut blocks = [[0i32; 16]; 8];
+    let mut recon_u = [0u8; 64];
+    let mut recon_v = [0u8; 64];
+    for (i, levels) in blocks.iter_mut().enumerate() {
+        let (src, pred, recon) = if i < 4 {
+            (src_u, pred_u, &mut recon_u)
+        } else {
+            (src_v, pred_v, &mut recon_v)
+        };
+        let (bx, by) = (i % 2, (i / 2) % 2);
+        let coeffs = fdct(&residual(src, pred, 8, bx, by));
+        let mut dequant = [0i32; 16];
+        for k in 0..16 {
+            let (step, rounding) = if k == 0 { (q.uv_dc, DC_ROUNDING) } else { (q.uv_ac, AC_ROUNDING) };
+            levels[k] = quantize(coeffs[k], step, rounding);
+            dequant[k] = levels[k] * step;
+        }
+        idct(&mut dequant);
+        add_residual(recon, pred, &dequant, 8, bx, by);
+    }
+    ChromaResult { blocks, recon_u, recon_v }
+}
+
+/// Forward DCT matching the decoder's inverse (libvpx `short_fdct4x4`)
+fn fdct(input: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let row = &input[i * 4..i * 4 + 4];
+        let a1 = (row[0] + row[3]) * 8;
+        let b1 = (row[1] + row[2]) * 8;
+        let c1 = (row[1] - row[2]) * 8;
+        let d1 = (row[0] - row[3]) * 8;
+        tmp[i * 4] = a1 + b1;
+        tmp[i * 4 + 2] = a1 - b1;
+        tmp[i * 4 + 1] = (c1 * 2217 + d1 * 5352 + 14500) >> 12;
+        tmp[i * 4 + 3] = (d1 * 2217 - c1 * 5352 + 7500) >> 12;
+    }
+
+    let mut out = [0i32; 16];
+    for i in 0..4 {
+        let a1 = tmp[i] + tmp[12 + i];
+        let b1 = tmp[4 + i] + tmp[8 + i];
+        let c1 = tmp[4 + i] - tmp[8 + i];
+        let d1 = tmp[i] - tmp[12 + i];
+        out[i] = (a1 + b1 + 7) >> 4;
+        out[8 + i] = (a1 - b1 + 7) >> 4;
+        out[4 + i] = ((c1 * 2217 + d1 * 5352 + 12000) >> 16) + (d1 != 0) as i32;
+        out[12 + i] = (d1 * 2217 - c1 * 5352 + 51000) >> 16;
+    }
+    out
+}
+
+/// Inverse DCT (RFC 6386 section 14.3)
+fn idct(block: &mut [i32; 16]) {
+    const C1: i64 = 20091;
+    const C2: i64 = 35468;
+    let mut tmp = [0i64; 16];
+    for i in 0..4 {
+        let (x0, x1, x2, x3) = (block[i] as i64, block[4 + i] as i64, block[8 + i] as i64, block[12 + i] as i64);
+        let a1 = x0 + x2;
+        let b1 = x0 - x2;
+        let c1 = ((x1 * C2) >> 16) - (x3 + ((x3 * C1) >> 16));
+        let d1 = (x1 + ((x1 * C1) >> 16)) + ((x3 * C2) >> 16);
+        tmp[i] = a1 + d1;
+        tmp[4 + i] = b1 + c1;
+        tmp[8 + i] = b1 - c1;
+        tmp[12 + i] = a1 - d1;
+    }
+    for i in 0..4 {
+        let (x0, x1, x2, x3) = (tmp[4 * i], tmp[4 * i + 1], tmp[4 * i + 2], tmp[4 * i + 3]);
+        let a1 = x0 + x2;
+        let b1 = x0 - x2;
+        let c1 = ((x1 * C2) >> 16) - (x3 + ((x3 * C1) >> 16));
+        let d1 = (x1 + ((x1 * C1) >> 16)) + ((x3 * C2) >> 16);
+        block[4 * i] = ((a1 + d1 + 4) >> 3) as i32;
+        block[4 * i + 3] = ((a1 - d1 + 4) >> 3) as i32;
+        block[4 * i + 1] = ((b1 + c1 + 4) >> 3) as i32;
+        block[4 * i + 2] = ((b1 - c1 + 4) >> 3) as i32;
+    }
+}
+
+/// Forward Walsh-Hadamard transform of the 16 luma DC coefficients
+fn fwht(input: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let row = &input[i * 4..i * 4 + 4];
+        let a1 = (row[0] + row[2]) * 4;
+        let d1 = (row[1] + row[3]) * 4;
+        let c1 = (row[1] - row[3]) * 4;
+        let b1 = (row[0] - row[2]) * 4;
+        tmp[i * 4] = a1 + d1 + (a1 != 0) as i32;
+        tmp[i * 4 + 1] = b1 + c1;
+        tmp[i * 4 + 2] = b1 - c1;
+        tmp[i * 4 + 3] = a1 - d1;
+    }
+
+    let mut out = [0i32; 16];
+    for i in 0..4 {
+        let a1 = tmp[i] + tmp[8 + i];
+        let d1 = tmp[4 + i] + tmp[12 + i];
+        let c1 = tmp[4 + i] - tmp[12 + i];
+        let b1 = tmp[i] - tmp[8 + i];
+        let values = [a1 + d1, b1 + c1, b1 - c1, a1 - d1];
+        for (k, v) in values.into_iter().enumerate() {
+            out[4 * k + i] = (v + (v < 0) as i32 + 3) >> 3;
+        }
+    }
+    out
+}
+
+/// Inverse Walsh-Hadamard transform (RFC 6386 section 14.3)
+fn iwht(block: &mut [i32; 16]) {
+    for i in 0..4 {
+        let a1 = block[i] + block[12 + i];
+        let b1 = block[4 + i] + block[8 + i];
+        let c1 = block[4 + i] - block[8 + i];
+        let d1 = block[i] - block[12 + i];
+        block[i] = a1 + b1;
+        block[4 + i] = c1 + d1;
+        block[8 + i] = a1 - b1;
+        block[12 + i] = d1 - c1;
+    }
+    for row in block.chunks_exact_mut(4) {
+        let a1 = row[0] + row[3];
+        let b1 = row[1] + row[2];
+        let c1 = row[1] - row[2];
+        let d1 = row[0] - row[3];
+        row[0] = (a1 + b1 + 3) >> 3;
+        row[1] = (c1 + d1 + 3) >> 3;
+        row[2] = (a1 - b1 + 3) >> 3;
+        row[3] = (d1 - c1 + 3) >> 3;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_transforms_invert_through_decoder_transforms() {
+        let mut seed = 1u32;
+        for _ in 0..200 {
+            let mut residual = [0i32; 16];
+            let mut dc = [0i32; 16];
+            for (r, d) in residual.iter_mut().zip(dc.iter_mut()) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                *r = (seed >> 16) as i32 % 511 - 255;
+                *d = *r * 8;
+            }
+
+            let mut coeffs = fdct(&residual);
+            idct(&mut coeffs);
+            assert!(coeffs.iter().zip(&residual).all(|(a, b)| (a - b).abs() <= 1));
+
+            let mut wht = fwht(&dc);
+            iwht(&mut wht);
+            assert!(wht.iter().zip(&dc).all(|(a, b)| (a - b).abs() <= 1));
+        }
+    }
+    #[test]
+    fn frame_tag_rejects_oversized_first_partition() {
+        assert_eq!(frame_tag(0x1234).unwrap(), [0x10 | 0x80, 0x46, 0x02]);
+        assert!(frame_tag(MAX_PARTITION0_SIZE - 1).is_ok());
+        let error = frame_tag(MAX_PARTITION0_SIZE).unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::EncodeFailed);
+    }
+}
@@ -0,0 +1,471 @@
+// JavaScript exports: thin wrappers around the native API in lib.rs.
+// They throw a JavaScript error with a numeric `code`
+// (photo_editor_common::ErrorCode) instead of returning 0 on failure.
+// Pixel inputs are taken as `Vec` so they move into an `Image` without
+// another copy.
+
+use photo_editor_common::{Image, Progress, ProgressToken};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    metrics, AvifAdvancedParams, CompressionFormat, CompressionResult, JpegAdvancedParams, LogLevel, MetadataInfo,
+    MetadataOptions, PngAdvancedParams, QualityMetrics, WebPAdvancedParams,
+};
+
+/**
+ * Compress RGBA image data to JPEG format
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - JPEG quality (1-100)
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_jpeg(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_jpeg(&Image::new(width, height, input)?, quality)?)
+}
+
+/**
+ * Compress RGBA image data to JPEG format with advanced parameters
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - JPEG quality (1-100, ignored for custom quantization tables)
+ * * `params` - Progressive scans, Huffman optimization, chroma subsampling,
+ *   restart interval and quantization tables
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_jpeg_advanced(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &JpegAdvancedParams,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_jpeg_advanced(&Image::new(width, height, input)?, quality, params)?)
+}
+
+/**
+ * Compress RGBA image data to WebP format
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - WebP quality (1-100)
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_webp(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_webp(&Image::new(width, height, input)?, quality)?)
+}
+
+/**
+ * Compress RGBA image data to WebP format with advanced parameters
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - WebP quality (1-100, ignored when `params.lossless` is set)
+ * * `params` - Method, loop filter, spatial noise shaping and lossless mode
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_webp_advanced(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &WebPAdvancedParams,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_webp_advanced(&Image::new(width, height, input)?, quality, params)?)
+}
+
+/**
+ * Compress RGBA image data to AVIF format
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - AVIF quality (1-100)
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_avif(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_avif(&Image::new(width, height, input)?, quality)?)
+}
+
+/**
+ * Compress RGBA image data to AVIF format with advanced parameters
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - AVIF quality (1-100)
+ * * `params` - Encoder speed and alpha quality
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_avif_advanced(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &AvifAdvancedParams,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_avif_advanced(&Image::new(width, height, input)?, quality, params)?)
+}
+
+/**
+ * Compress RGBA image data to PNG format
+ *
+ * Note: PNG is lossless compression. The quality parameter controls
+ * compression level (Fast/Default/High/Best) for trade-off between size and speed.
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - PNG compression level hint (1-100, maps to compression type)
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_png(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_png(&Image::new(width, height, input)?, quality)?)
+}
+
+/**
+ * Compress RGBA image data to PNG format with advanced parameters
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `quality` - PNG compression level hint (1-100, maps to compression type)
+ * * `params` - Palette quantization and dithering
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_png_advanced(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: u8,
+    params: &PngAdvancedParams,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_png_advanced(&Image::new(width, height, input)?, quality, params)?)
+}
+
+/**
+ * Compress 16-bit RGBA image data
+ *
+ * PNG keeps all 16 bits per channel. JPEG, WebP and AVIF are encoded from
+ * 8-bit samples, so they are narrowed here, at encode time, optionally with
+ * dithering to avoid banding in smooth gradients.
+ *
+ * # Arguments
+ * * `input` - RGBA samples (4 per pixel, 0-65535, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `format` - Compression format (Jpeg, WebP, Png or Avif)
+ * * `quality` - Quality (1-100); compression level hint for PNG
+ * * `dither` - Dither when narrowing to 8 bits (ignored for PNG)
+ *
+ * # Returns
+ * The encoded file
+ */
+#[wasm_bindgen]
+pub fn compress_16(
+    input: Vec<u16>,
+    width: u32,
+    height: u32,
+    format: CompressionFormat,
+    quality: u8,
+    dither: bool,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::compress_16(&Image::new(width, height, input)?, format, quality, dither)?)
+}
+
+/**
+ * Compress image to target file size using binary search
+ *
+ * Uses binary search to find the optimal quality parameter that
+ * produces a compressed image close to the target file size. PNG stays
+ * lossless if that fits, otherwise the palette size of a quantized PNG
+ * is searched instead.
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `target_size` - Target file size in bytes
+ * * `format` - Compression format (Jpeg, WebP, Png or Avif)
+ * * `progress` - Optional ProgressToken, reported per encoding attempt
+ *
+ * # Returns
+ * CompressionResult with the encoded file, its size, quality and the strategy used;
+ * throws with ErrorCode.Cancelled if cancelled through `progress`
+ */
+#[wasm_bindgen]
+pub fn compress_to_size(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    target_size: usize,
+    format: CompressionFormat,
+    progress: Option<ProgressToken>,
+) -> Result<CompressionResult, JsValue> {
+    let mut progress = Progress::from_token(progress.as_ref());
+    Ok(crate::compress_to_size(&Image::new(width, height, input)?, target_size, format, &mut progress)?)
+}
+
+/**
+ * Compress image to target file size, downscaling if quality alone can't
+ *
+ * Like compress_to_size, but never goes below `min_quality`; when the
+ * target is still missed there, the image is downscaled until it fits,
+ * keeping the largest resolution that does. Check `width`/`height` in the
+ * result for the dimensions that were encoded.
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `target_size` - Target file size in bytes
+ * * `format` - Compression format (Jpeg, WebP, Png or Avif)
+ * * `min_quality` - Quality floor (1-100); for PNG, the percentage of 256 palette colors
+ * * `progress` - Optional ProgressToken, reported per encoding attempt
+ *
+ * # Returns
+ * CompressionResult with the encoded file, its size, quality, strategy and final dimensions;
+ * throws with ErrorCode.Cancelled if cancelled through `progress`
+ */
+#[wasm_bindgen]
+pub fn compress_to_size_with_downscale(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    target_size: usize,
+    format: CompressionFormat,
+    min_quality: u8,
+    progress: Option<ProgressToken>,
+) -> Result<CompressionResult, JsValue> {
+    let image = Image::new(width, height, input)?;
+    let mut progress = Progress::from_token(progress.as_ref());
+    Ok(crate::compress_to_size_with_downscale(&image, target_size, format, min_quality, &mut progress)?)
+}
+
+/**
+ * Compress image to the smallest size that keeps a minimum SSIM
+ *
+ * Searches encoder quality (PNG: palette size) for the smallest file whose
+ * decoded pixels stay at or above `min_ssim` structural similarity with the
+ * input. Fixed quality numbers look very different across images; this
+ * holds the visual result steady instead.
+ *
+ * # Arguments
+ * * `input` - RGBA image data (4 bytes per pixel, row-major order)
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `format` - Compression format (Jpeg, WebP or Png; Avif is not supported)
+ * * `min_ssim` - Required SSIM, e.g. 0.95 (0-1, 1 = identical)
+ *
+ * # Returns
+ * CompressionResult with the encoded file, its size, quality, strategy and the SSIM reached
+ */
+#[wasm_bindgen]
+pub fn compress_to_quality_target(
+    input: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: CompressionFormat,
+    min_ssim: f64,
+) -> Result<CompressionResult, JsValue> {
+    Ok(crate::compress_to_quality_target(&Image::new(width, height, input)?, format, min_ssim)?)
+}
+
+/**
+ * Measure PSNR, SSIM and MS-SSIM between two RGBA images
+ *
+ * # Arguments
+ * * `reference` - Original RGBA image data
+ * * `distorted` - RGBA image data to score, same dimensions
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `heatmap` - Also return a per-pixel difference heatmap (RGBA, same size)
+ *
+ * # Returns
+ * QualityMetrics; throws if the buffers don't match the dimensions
+ */
+#[wasm_bindgen]
+pub fn compare_images(
+    reference: &[u8],
+    distorted: &[u8],
+    width: u32,
+    height: u32,
+    heatmap: bool,
+) -> Result<QualityMetrics, JsValue> {
+    Ok(metrics::compare(reference, distorted, width, height, heatmap)?)
+}
+
+/**
+ * Measure how much quality compression lost
+ *
+ * Decodes the compressed file and compares it with the original pixels.
+ *
+ * # Arguments
+ * * `reference` - Original RGBA image data
+ * * `encoded` - JPEG, PNG or WebP produced by the compress functions
+ * * `width` - Image width in pixels
+ * * `height` - Image height in pixels
+ * * `heatmap` - Also return a per-pixel difference heatmap (RGBA, same size)
+ *
+ * # Returns
+ * QualityMetrics; throws if the file can't be decoded or has other dimensions
+ */
+#[wasm_bindgen]
+pub fn compare_encoded(
+    reference: &[u8],
+    encoded: &[u8],
+    width: u32,
+    height: u32,
+    heatmap: bool,
+) -> Result<QualityMetrics, JsValue> {
+    Ok(metrics::compare_encoded(reference, encoded, width, height, heatmap)?)
+}
+
+/**
+ * Read EXIF/XMP/IPTC metadata from a JPEG, PNG or WebP file
+ *
+ * # Arguments
+ * * `bytes` - Complete image file
+ *
+ * # Returns
+ * MetadataInfo describing what was found (empty for other formats)
+ */
+#[wasm_bindgen]
+pub fn read_metadata(bytes: &[u8]) -> MetadataInfo {
+    crate::read_metadata(bytes)
+}
+
+/**
+ * Embed the source file's metadata into an exported JPEG or PNG
+ *
+ * # Arguments
+ * * `encoded` - JPEG or PNG produced by compress_jpeg/compress_png
+ * * `source` - Original file the pixels were decoded from
+ * * `options` - Policy (preserve all, strip all, strip location, preserve with edits)
+ *
+ * # Returns
+ * The exported file with metadata
+ */
+#[wasm_bindgen]
+pub fn apply_metadata(encoded: &[u8], source: &[u8], options: &MetadataOptions) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::apply_metadata(encoded, source, options)?)
+}
+
+/**
+ * Losslessly shrink an existing PNG file
+ *
+ * Reduces color type and bit depth where the pixels allow, searches row
+ * filters, recompresses with zopfli and drops chunks that don't affect
+ * display (metadata and color profiles are kept).
+ *
+ * # Arguments
+ * * `bytes` - Complete PNG file
+ *
+ * # Returns
+ * The optimized PNG, or the input when it can't be made smaller
+ */
+#[wasm_bindgen]
+pub fn optimize_png(bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::optimize_png(bytes)?)
+}
+
+/**
+ * Embed an ICC color profile into an exported JPEG or PNG
+ *
+ * Use this to keep wide-gamut sources (Display P3, Adobe RGB) from looking
+ * washed out when their pixels were not converted to sRGB on decode.
+ *
+ * # Arguments
+ * * `encoded` - JPEG or PNG produced by compress_jpeg/compress_png
+ * * `profile` - ICC profile of the encoded pixels
+ *
+ * # Returns
+ * The exported file with the profile (JPEG APP2 / PNG iCCP)
+ */
+#[wasm_bindgen]
+pub fn embed_icc_profile(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::embed_icc_profile(encoded, profile)?)
+}
+
+/**
+ * Set how much the compress module logs
+ *
+ * Defaults to LogLevel::Error. Messages go to the browser console (to the
+ * `log` crate in native builds).
+ *
+ * # Arguments
+ * * `level` - Off, Error, Info (one line per call) or Debug (encoder steps
+ *   and every target-size attempt)
+ */
+#[wasm_bindgen]
+pub fn set_log_level(level: LogLevel) {
+    crate::set_log_level(level);
+}
+
+/**
+ * Log how long each exported compress call takes
+ *
+ * # Arguments
+ * * `enabled` - Write "<function> took N ms" after every call, at any log level but Off
+ */
+#[wasm_bindgen]
+pub fn set_log_timings(enabled: bool) {
+    crate::set_log_timings(enabled);
+}
@@ -1,18 +1,36 @@
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use photo_editor_common::Error;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+use crate::vp8;
+
+// RIFF header plus the chunk header of a simple (single chunk) WebP file
+const SIMPLE_HEADER_LEN: usize = 20;
+
+// VP8L signature byte and the packed dimensions/alpha/version fields
+const VP8L_HEADER_LEN: usize = 5;
+
+// VP8X flag for an alpha channel
+const VP8X_ALPHA: u8 = 0x10;
+
+// ALPH header byte: no preprocessing, no filtering, lossless compression
+const ALPH_LOSSLESS: u8 = 0x01;
+
 /**
  * WebP advanced compression parameters
  *
- * Note: These parameters are currently logged but not fully utilized in WASM.
- * The actual WebP encoding uses browser's Canvas API (OffscreenCanvas) as fallback.
- * This interface is maintained for future implementation with WASM-compatible encoders.
+ * Encoding happens in Rust for both modes, so the output is identical in
+ * every browser.
  */
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct WebPAdvancedParams {
     pub method: u8,           // 0-6, compression method (0=fastest, 6=slowest best)
     pub filter_strength: u8,  // 0-100, filter strength
     pub filter_sharpness: u8, // 0-100, filter sharpness
     pub sns_strength: u8,     // 0-100, spatial noise shielding
+    pub lossless: bool,       // VP8L lossless encoding; quality and the params above are ignored
 }
 
 impl Default for WebPAdvancedParams {
@@ -22,10 +40,19 @@ impl Default for WebPAdvancedParams {
             filter_strength: 60,
             filter_sharpness: 0,
             sns_strength: 50,
+            lossless: false,
         }
     }
 }
 
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl WebPAdvancedParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> WebPAdvancedParams {
+        WebPAdvancedParams::default()
+    }
+}
+
 /**
  * Compress RGBA image data to WebP format (basic)
  *
@@ -34,29 +61,20 @@ impl Default for WebPAdvancedParams {
  * * `width` - Image width in pixels
  * * `height` - Image height in pixels
  * * `quality` - WebP quality (1-100)
- * * `output` - Output buffer for compressed WebP data
  *
  * # Returns
- * Ok(0) to trigger Canvas fallback in worker
+ * Ok(webp_bytes) on success
+ * Err(error) on failure
  *
- * Note: Pure Rust WebP encoders require libwebp-sys (needs clang compiler)
- * For WASM compatibility, we return 0 to trigger OffscreenCanvas fallback
+ * Lossy encoding with the default advanced parameters.
  */
 pub fn compress_to_webp(
-    _input: &[u8],
+    input: &[u8],
     width: u32,
     height: u32,
     quality: u8,
-    _output: &mut [u8],
-) -> Result<usize, &'static str> {
-    web_sys::console::log_1(&format!(
-        "compress_to_webp: {}x{}, quality={} - using Canvas fallback",
-        width, height, quality
-    ).into());
-
-    // Return 0 to signal worker to use Canvas fallback
-    // The compressWorker.ts will use OffscreenCanvas for actual WebP encoding
-    Ok(0)
+) -> Result<Vec<u8>, Error> {
+    compress_to_webp_advanced(input, width, height, quality, &WebPAdvancedParams::default())
 }
 
 /**
@@ -68,64 +86,170 @@ pub fn compress_to_webp(
  * * `height` - Image height in pixels
  * * `quality` - WebP quality (1-100)
  * * `params` - Advanced encoding parameters
- * * `output` - Output buffer for compressed WebP data
  *
  * # Returns
- * Ok(0) to trigger Canvas fallback in worker
+ * Ok(webp_bytes) on success
+ * Err(error) on failure
  *
- * Note: Logs advanced parameters for debugging, but uses Canvas fallback.
- * Future: Implement with WASM-compatible WebP encoder that supports these params.
+ * Lossy output is a VP8 key frame; images with transparency get a
+ * losslessly compressed ALPH chunk alongside it. Lossless output is VP8L.
  */
 pub fn compress_to_webp_advanced(
-    _input: &[u8],
+    input: &[u8],
     width: u32,
     height: u32,
     quality: u8,
     params: &WebPAdvancedParams,
-    _output: &mut [u8],
-) -> Result<usize, &'static str> {
-    web_sys::console::log_1(&format!(
-        "compress_to_webp_advanced: {}x{}, quality={} - params logged, using Canvas fallback",
-        width, height, quality
-    ).into());
-
-    // Log advanced parameters for debugging
-    web_sys::console::log_1(&format!(
+) -> Result<Vec<u8>, Error> {
+    log_debug!(
+        "compress_to_webp_advanced: {}x{}, quality={}, lossless={}",
+        width, height, quality, params.lossless
+    );
+    log_debug!(
         "WebP params - method: {}, filter_strength: {}, filter_sharpness: {}, sns_strength: {}",
         params.method, params.filter_strength, params.filter_sharpness, params.sns_strength
-    ).into());
+    );
+
+    if input.len() != (width as usize) * (height as usize) * 4 {
+        return Err(Error::input_size_mismatch("Input length does not match dimensions"));
+    }
+    if width > vp8::MAX_DIMENSION || height > vp8::MAX_DIMENSION {
+        return Err(Error::invalid_dimensions("Image dimensions exceed the WebP limit of 16383 pixels"));
+    }
 
-    // Return 0 to signal worker to use Canvas fallback
-    // The compressWorker.ts will use OffscreenCanvas for actual WebP encoding
-    Ok(0)
+    let has_alpha = input.chunks_exact(4).any(|p| p[3] != 255);
+    let webp = if params.lossless {
+        if has_alpha {
+            encode_lossless(input, width, height, ExtendedColorType::Rgba8)?
+        } else {
+            let rgb: Vec<u8> = input.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+            encode_lossless(&rgb, width, height, ExtendedColorType::Rgb8)?
+        }
+    } else {
+        let frame = vp8::encode(input, width, height, quality, params)?;
+        if has_alpha {
+            let alpha: Vec<u8> = input.chunks_exact(4).map(|p| p[3]).collect();
+            let alpha_stream = encode_alpha(&alpha, width, height)?;
+            let mut vp8x = vec![VP8X_ALPHA, 0, 0, 0];
+            vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            riff(&[(b"VP8X", &vp8x), (b"ALPH", &alpha_stream), (b"VP8 ", &frame)])
+        } else {
+            riff(&[(b"VP8 ", &frame)])
+        }
+    };
+    log_debug!("WebP encoded successfully, size: {}", webp.len());
+    Ok(webp)
+}
+
+fn encode_lossless(data: &[u8], width: u32, height: u32, color_type: ExtendedColorType) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::new();
+    WebPEncoder::new_lossless(&mut buffer)
+        .write_image(data, width, height, color_type)
+        .map_err(|_| Error::encode_failed("WebP lossless encoding failed"))?;
+    Ok(buffer)
+}
+
+/// ALPH payload: the alpha plane as a header-less VP8L image stream
+fn encode_alpha(alpha: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let webp = encode_lossless(alpha, width, height, ExtendedColorType::L8)?;
+    let len = u32::from_le_bytes([webp[16], webp[17], webp[18], webp[19]]) as usize;
+    let stream = webp
+        .get(SIMPLE_HEADER_LEN + VP8L_HEADER_LEN..SIMPLE_HEADER_LEN + len)
+        .ok_or_else(|| Error::encode_failed("WebP alpha encoding failed"))?;
+    Ok([&[ALPH_LOSSLESS], stream].concat())
+}
+
+fn riff(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut body = b"WEBP".to_vec();
+    for (name, data) in chunks {
+        body.extend_from_slice(*name);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    out
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_compress_to_webp_triggers_fallback() {
-        let rgba_data = vec![255u8; 16]; // 2x2 white image
-        let mut output = vec![0u8; 4096];
-        let result = compress_to_webp(&rgba_data, 2, 2, 80, &mut output);
-
-        // Should return Ok(0) to trigger Canvas fallback
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 0);
+    /// Color gradients with a sharp-edged gray square, the kind of content
+    /// that exercises every prediction mode
+    fn sample_image(width: u32, height: u32, alpha: bool) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let inside = (10..30).contains(&x) && (12..28).contains(&y);
+                let base = (x * 127 / width + y * 85 / height) as u8;
+                let (r, g, b) = if inside { (230, 230, 230) } else { (base, base / 2 + 60, 200 - base / 2) };
+                let a = if alpha { ((x * 7 + y * 3) % 256) as u8 } else { 255 };
+                rgba.extend_from_slice(&[r, g, b, a]);
+            }
+        }
+        rgba
+    }
+
+    fn decode(webp: &[u8]) -> Vec<u8> {
+        image::load_from_memory_with_format(webp, image::ImageFormat::WebP)
+            .unwrap()
+            .into_rgba8()
+            .into_raw()
+    }
+
+    fn psnr(a: &[u8], b: &[u8]) -> f64 {
+        let (sum, count) = a
+            .chunks(4)
+            .zip(b.chunks(4))
+            .flat_map(|(p, q)| (0..3).map(move |c| (p[c] as f64 - q[c] as f64).powi(2)))
+            .fold((0.0, 0), |(s, n), e| (s + e, n + 1));
+        10.0 * (255.0f64 * 255.0 / (sum / count as f64)).log10()
     }
 
     #[test]
-    fn test_compress_to_webp_advanced_triggers_fallback() {
-        let rgba_data = vec![255u8; 16];
-        let mut output = vec![0u8; 4096];
-        let params = WebPAdvancedParams::default();
+    fn test_lossy_webp_round_trip_is_deterministic() {
+        // Odd size to cover partial macroblocks
+        let rgba = sample_image(45, 37, false);
+
+        let first = compress_to_webp(&rgba, 45, 37, 90).unwrap();
+        assert_eq!(compress_to_webp(&rgba, 45, 37, 90).unwrap(), first);
+
+        let decoded = decode(&first);
+        assert!(psnr(&rgba, &decoded) > 35.0);
+
+        // Every method and the extremes of the other params decode cleanly
+        for method in 0..=6 {
+            let params = WebPAdvancedParams {
+                method,
+                filter_strength: 100,
+                filter_sharpness: 100,
+                sns_strength: 100,
+                lossless: false,
+            };
+            let webp = compress_to_webp_advanced(&rgba, 45, 37, 75, &params).unwrap();
+            assert!(psnr(&rgba, &decode(&webp)) > 30.0);
+        }
+    }
 
-        let result = compress_to_webp_advanced(&rgba_data, 2, 2, 80, &params, &mut output);
+    #[test]
+    fn test_lossless_and_alpha_are_exact() {
+        let rgba = sample_image(40, 40, true);
+        let params = WebPAdvancedParams {
+            lossless: true,
+            ..WebPAdvancedParams::default()
+        };
+        let webp = compress_to_webp_advanced(&rgba, 40, 40, 80, &params).unwrap();
+        assert_eq!(decode(&webp), rgba);
 
-        // Should return Ok(0) to trigger Canvas fallback
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 0);
+        // Lossy color keeps its alpha channel bit-exact
+        let decoded = decode(&compress_to_webp(&rgba, 40, 40, 80).unwrap());
+        assert!(decoded.chunks(4).zip(rgba.chunks(4)).all(|(d, s)| d[3] == s[3]));
     }
 
     #[test]
@@ -135,6 +259,6 @@ mod tests {
         assert_eq!(params.filter_strength, 60);
         assert_eq!(params.filter_sharpness, 0);
         assert_eq!(params.sns_strength, 50);
+        assert!(!params.lossless);
     }
 }
-
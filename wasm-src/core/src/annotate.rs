@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use photo_editor_common::Error;
+
+use crate::{geometry, utils};
+
+/// A single vector annotation. Serialized as a flat JSON object, e.g.
+/// `{"type":"arrow","x1":10,"y1":10,"x2":80,"y2":40,"color":[255,0,0],"strokeWidth":4}`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(flatten)]
+    pub shape: Shape,
+    #[serde(flatten)]
+    pub style: AnnotationStyle,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Shape {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    RoundedRect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        radius: f32,
+    },
+    Ellipse {
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+    },
+    Line {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+    },
+    Arrow {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        #[serde(default, rename = "headSize")]
+        head_size: Option<f32>,
+    },
+    Freehand {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnnotationStyle {
+    pub color: [u8; 3],
+    pub stroke_width: f32,
+    pub fill: Option<[u8; 3]>,
+    pub opacity: f32,
+}
+
+impl Default for AnnotationStyle {
+    fn default() -> Self {
+        Self {
+            color: [255, 0, 0],
+            stroke_width: 3.0,
+            fill: None,
+            opacity: 1.0,
+        }
+    }
+}
+
+pub fn parse_annotations(json: &str) -> Result<Vec<Annotation>, Error> {
+    serde_json::from_str(json)
+        .map_err(|e| Error::invalid_parameter(format!("Invalid annotation list: {}", e)))
+}
+
+pub fn draw_annotations(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    annotations: &[Annotation],
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    output[..out_len].copy_from_slice(input);
+    for annotation in annotations {
+        rasterize(&mut output[..out_len], width, height, annotation);
+    }
+
+    Ok(out_len)
+}
+
+/// Rasterize one annotation onto an RGBA buffer in place.
+///
+/// Fill and stroke coverage are evaluated once per pixel so overlapping
+/// segments of the same shape (freehand joints, arrow heads) never blend twice.
+pub fn rasterize(data: &mut [u8], width: u32, height: u32, annotation: &Annotation) {
+    let style = &annotation.style;
+    let half_stroke = style.stroke_width.max(0.0) / 2.0;
+    let opacity = style.opacity.clamp(0.0, 1.0);
+    if opacity == 0.0 {
+        return;
+    }
+
+    let (min_x, min_y, max_x, max_y) = shape_bounds(&annotation.shape, style);
+    let pad = half_stroke + 1.0;
+    let x0 = (min_x - pad).floor().max(0.0) as u32;
+    let y0 = (min_y - pad).floor().max(0.0) as u32;
+    let x1 = ((max_x + pad).ceil().max(0.0) as u32).min(width);
+    let y1 = ((max_y + pad).ceil().max(0.0) as u32).min(height);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let (fill_cov, stroke_cov) = shape_coverage(&annotation.shape, px, py, half_stroke);
+            let idx = utils::pixel_index(width, x, y);
+            let pixel = &mut data[idx..idx + 4];
+
+            if let Some(fill) = style.fill {
+                if fill_cov > 0.0 {
+                    blend_pixel(pixel, fill, fill_cov * opacity);
+                }
+            }
+            if stroke_cov > 0.0 {
+                blend_pixel(pixel, style.color, stroke_cov * opacity);
+            }
+        }
+    }
+}
+
+fn shape_bounds(shape: &Shape, style: &AnnotationStyle) -> (f32, f32, f32, f32) {
+    match shape {
+        Shape::Rect { x, y, width, height } | Shape::RoundedRect { x, y, width, height, .. } => {
+            (*x, *y, x + width, y + height)
+        }
+        Shape::Ellipse { cx, cy, rx, ry } => (cx - rx, cy - ry, cx + rx, cy + ry),
+        Shape::Line { x1, y1, x2, y2 } => (x1.min(*x2), y1.min(*y2), x1.max(*x2), y1.max(*y2)),
+        Shape::Arrow { x1, y1, x2, y2, head_size } => {
+            let head = arrow_head_size(*head_size, style.stroke_width);
+            (
+                x1.min(*x2) - head,
+                y1.min(*y2) - head,
+                x1.max(*x2) + head,
+                y1.max(*y2) + head,
+            )
+        }
+        Shape::Freehand { points } => geometry::bounds(points),
+    }
+}
+
+/// Returns (fill coverage, stroke coverage) in 0.0..=1.0 for a pixel center.
+fn shape_coverage(shape: &Shape, px: f32, py: f32, half_stroke: f32) -> (f32, f32) {
+    let stroke = |d: f32| {
+        if half_stroke > 0.0 {
+            geometry::coverage(d.abs() - half_stroke)
+        } else {
+            0.0
+        }
+    };
+
+    match shape {
+        Shape::Rect { x, y, width, height } => {
+            let d = geometry::box_sdf(px, py, x + width / 2.0, y + height / 2.0, width / 2.0, height / 2.0, 0.0);
+            (geometry::coverage(d), stroke(d))
+        }
+        Shape::RoundedRect { x, y, width, height, radius } => {
+            let d = geometry::box_sdf(px, py, x + width / 2.0, y + height / 2.0, width / 2.0, height / 2.0, *radius);
+            (geometry::coverage(d), stroke(d))
+        }
+        Shape::Ellipse { cx, cy, rx, ry } => {
+            let d = geometry::ellipse_sdf(px, py, *cx, *cy, *rx, *ry);
+            (geometry::coverage(d), stroke(d))
+        }
+        Shape::Line { x1, y1, x2, y2 } => {
+            (0.0, stroke(geometry::segment_distance(px, py, *x1, *y1, *x2, *y2)))
+        }
+        Shape::Arrow { x1, y1, x2, y2, head_size } => {
+            let head = arrow_head_size(*head_size, half_stroke * 2.0);
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len == 0.0 {
+                return (0.0, stroke(geometry::segment_distance(px, py, *x1, *y1, *x2, *y2)));
+            }
+            let (ux, uy) = (dx / len, dy / len);
+            let head_len = head.min(len);
+            let (bx, by) = (x2 - ux * head_len, y2 - uy * head_len);
+            let half_base = head_len * 0.5;
+            let triangle = [
+                [*x2, *y2],
+                [bx - uy * half_base, by + ux * half_base],
+                [bx + uy * half_base, by - ux * half_base],
+            ];
+
+            let shaft = stroke(geometry::segment_distance(px, py, *x1, *y1, bx, by));
+            let tip = geometry::coverage(geometry::polygon_sdf(px, py, &triangle));
+            (0.0, shaft.max(tip))
+        }
+        Shape::Freehand { points } => {
+            let d = match points.len() {
+                0 => f32::MAX,
+                1 => geometry::segment_distance(px, py, points[0][0], points[0][1], points[0][0], points[0][1]),
+                _ => points
+                    .windows(2)
+                    .map(|w| geometry::segment_distance(px, py, w[0][0], w[0][1], w[1][0], w[1][1]))
+                    .fold(f32::MAX, f32::min),
+            };
+            (0.0, stroke(d))
+        }
+    }
+}
+
+fn arrow_head_size(head_size: Option<f32>, stroke_width: f32) -> f32 {
+    head_size.unwrap_or((stroke_width * 4.0).max(10.0)).max(0.0)
+}
+
+/// Composite a straight-alpha color over an RGBA pixel (source-over).
+pub fn blend_pixel(pixel: &mut [u8], color: [u8; 3], alpha: f32) {
+    let src_a = alpha.clamp(0.0, 1.0);
+    let dst_a = pixel[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return;
+    }
+
+    for c in 0..3 {
+        let src = color[c] as f32;
+        let dst = pixel[c] as f32;
+        let value = (src * src_a + dst * dst_a * (1.0 - src_a)) / out_a;
+        pixel[c] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    pixel[3] = (out_a * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_fills_rect() {
+        let shapes = parse_annotations(
+            r#"[{"type":"rect","x":1,"y":1,"width":2,"height":2,"color":[0,0,255],"strokeWidth":0,"fill":[0,255,0]}]"#,
+        )
+        .unwrap();
+        let input = vec![0u8; 4 * 4 * 4];
+        let mut output = vec![0u8; input.len()];
+        draw_annotations(&input, 4, 4, &shapes, &mut output).unwrap();
+
+        let inside = utils::pixel_index(4, 1, 1);
+        assert_eq!(&output[inside..inside + 4], &[0, 255, 0, 255]);
+        assert_eq!(&output[0..4], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn line_is_antialiased() {
+        let shapes = vec![Annotation {
+            shape: Shape::Line { x1: 0.0, y1: 2.0, x2: 8.0, y2: 2.0 },
+            style: AnnotationStyle {
+                color: [255, 255, 255],
+                stroke_width: 1.0,
+                fill: None,
+                opacity: 1.0,
+            },
+        }];
+        let input = [0, 0, 0, 255].repeat(8 * 4);
+        let mut output = vec![0u8; input.len()];
+        draw_annotations(&input, 8, 4, &shapes, &mut output).unwrap();
+
+        // The line sits on the boundary between rows 1 and 2, so both rows get half coverage.
+        let row1 = utils::pixel_index(8, 4, 1);
+        let row2 = utils::pixel_index(8, 4, 2);
+        assert!(output[row1] > 100 && output[row1] < 160);
+        assert_eq!(output[row1], output[row2]);
+    }
+}
@@ -0,0 +1,84 @@
+use moxcms::{ColorProfile, Layout, TransformOptions};
+use photo_editor_common::Error;
+
+use crate::utils;
+
+/// Convert RGBA pixels from the color space described by an ICC profile to sRGB.
+///
+/// Alpha is passed through unchanged and the rendering intent is taken from
+/// the profile header. Only RGB profiles can be applied; gray and CMYK
+/// profiles are rejected because the pixels are already expanded to RGBA.
+pub fn convert_to_srgb(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    icc_profile: &[u8],
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    let transform = srgb_transform(icc_profile)?;
+    transform
+        .transform(input, &mut output[..out_len])
+        .map_err(|e| Error::unsupported(format!("Color conversion failed: {}", e)))?;
+
+    Ok(out_len)
+}
+
+/// In-place variant used by the decoder.
+pub fn convert_to_srgb_in_place(data: &mut [u8], icc_profile: &[u8]) -> Result<(), Error> {
+    let transform = srgb_transform(icc_profile)?;
+    let source = data.to_vec();
+    transform
+        .transform(&source, data)
+        .map_err(|e| Error::unsupported(format!("Color conversion failed: {}", e)))
+}
+
+/// 16-bit in-place variant used by the high-precision decoder.
+pub fn convert_to_srgb_in_place_16(data: &mut [u16], icc_profile: &[u8]) -> Result<(), Error> {
+    let source = parse_profile(icc_profile)?;
+    let transform = source
+        .create_transform_16bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, transform_options(&source))
+        .map_err(|e| Error::unsupported(format!("Unsupported ICC profile: {}", e)))?;
+    let input = data.to_vec();
+    transform
+        .transform(&input, data)
+        .map_err(|e| Error::unsupported(format!("Color conversion failed: {}", e)))
+}
+
+fn parse_profile(icc_profile: &[u8]) -> Result<ColorProfile, Error> {
+    ColorProfile::new_from_slice(icc_profile).map_err(|e| Error::invalid_parameter(format!("Invalid ICC profile: {}", e)))
+}
+
+fn transform_options(source: &ColorProfile) -> TransformOptions {
+    TransformOptions {
+        rendering_intent: source.rendering_intent,
+        ..TransformOptions::default()
+    }
+}
+
+fn srgb_transform(icc_profile: &[u8]) -> Result<Box<moxcms::Transform8BitExecutor>, Error> {
+    let source = parse_profile(icc_profile)?;
+    source
+        .create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, transform_options(&source))
+        .map_err(|e| Error::unsupported(format!("Unsupported ICC profile: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_p3_red_is_clipped_into_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        // Mid gray is identical in both spaces; pure P3 red lies outside sRGB
+        let input = [128, 128, 128, 200, 255, 0, 0, 255];
+        let mut output = [0u8; 8];
+        convert_to_srgb(&input, 2, 1, &p3, &mut output).unwrap();
+
+        assert!(output[0..3].iter().all(|&c| c.abs_diff(128) <= 1));
+        assert_eq!(output[3], 200);
+        assert!(output[4] >= 250 && output[5] < 20 && output[6] < 20);
+    }
+}
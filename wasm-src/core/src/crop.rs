@@ -1,4 +1,4 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::Error;
 
 use crate::{utils, CropRect};
 
@@ -8,20 +8,20 @@ pub fn crop_image(
     height: u32,
     crop_rect: &CropRect,
     output: &mut [u8],
-) -> Result<usize, JsValue> {
+) -> Result<usize, Error> {
     utils::validate_input(input, width, height)?;
 
     let x2 = crop_rect
         .x
         .checked_add(crop_rect.width)
-        .ok_or_else(|| JsValue::from_str("Crop width overflow"))?;
+        .ok_or_else(|| Error::out_of_bounds("Crop width overflow"))?;
     let y2 = crop_rect
         .y
         .checked_add(crop_rect.height)
-        .ok_or_else(|| JsValue::from_str("Crop height overflow"))?;
+        .ok_or_else(|| Error::out_of_bounds("Crop height overflow"))?;
 
     if x2 > width || y2 > height {
-        return Err(JsValue::from_str("Crop rectangle out of bounds"));
+        return Err(Error::out_of_bounds("Crop rectangle out of bounds"));
     }
 
     let out_len = utils::checked_len(crop_rect.width, crop_rect.height)?;
@@ -41,13 +41,13 @@ pub fn crop_image(
 
         // Check bounds BEFORE slicing to prevent panic
         if src_idx + row_bytes > input.len() {
-            return Err(JsValue::from_str(&format!(
+            return Err(Error::out_of_bounds(format!(
                 "Source slice out of bounds: row={}, src_idx={}, row_bytes={}, input.len()={}",
                 row, src_idx, row_bytes, input.len()
             )));
         }
         if dst_idx + row_bytes > output.len() {
-            return Err(JsValue::from_str(&format!(
+            return Err(Error::output_too_small(format!(
                 "Destination slice out of bounds: row={}, dst_idx={}, row_bytes={}, output.len()={}",
                 row, dst_idx, row_bytes, output.len()
             )));
@@ -81,4 +81,18 @@ mod tests {
         assert_eq!(written, 4);
         assert_eq!(output, vec![20, 0, 0, 255]);
     }
+
+    #[test]
+    fn rejects_out_of_bounds_crop() {
+        let input = vec![0u8; 16];
+        let crop = CropRect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 1,
+        };
+        let mut output = vec![0u8; 8];
+        let error = crop_image(&input, 2, 2, &crop, &mut output).unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::OutOfBounds);
+    }
 }
@@ -0,0 +1,415 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use photo_editor_common::{Error, Image};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{color_profile, rotate};
+
+/// Pixels and source information of a decoded image file.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct DecodedImage {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: String,
+    bit_depth: u8,
+    orientation: u8,
+    icc_profile: Option<Vec<u8>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DecodedImage {
+    /// RGBA pixels, 4 bytes per pixel, row-major order
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Container format of the source file ("jpeg", "png", "webp", ...)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn format(&self) -> String {
+        self.format.clone()
+    }
+
+    /// Bits per channel of the source pixels (8, 16 or 32)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// EXIF Orientation of the source file (1-8, 1 when absent). The pixels
+    /// are already upright unless decoding was asked to skip the correction.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn orientation(&self) -> u8 {
+        self.orientation
+    }
+
+    /// ICC profile describing the returned pixels, if the file embeds one.
+    /// Cleared once the pixels have been converted to sRGB.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.icc_profile.clone()
+    }
+}
+
+impl DecodedImage {
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The pixels, without the source information
+    pub fn into_image(self) -> Result<Image, Error> {
+        Image::new(self.width, self.height, self.data)
+    }
+}
+
+/// Short lowercase name for a container format.
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Png => "png",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Gif => "gif",
+        ImageFormat::Bmp => "bmp",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Ico => "ico",
+        _ => "unknown",
+    }
+}
+
+/// Cargo feature that enables decoding of a format, if it is optional.
+fn feature_for(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::Bmp => Some("bmp"),
+        ImageFormat::Tiff => Some("tiff"),
+        ImageFormat::Ico => Some("ico"),
+        _ => None,
+    }
+}
+
+/// Pixels of a decoded image with 16 bits per channel, for edits that
+/// would band at 8 bits (curves, levels, heavy resampling).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct DecodedImage16 {
+    data: Vec<u16>,
+    width: u32,
+    height: u32,
+    format: String,
+    bit_depth: u8,
+    orientation: u8,
+    icc_profile: Option<Vec<u8>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DecodedImage16 {
+    /// RGBA samples, 4 per pixel, row-major order, full range 0-65535
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn data(&self) -> Vec<u16> {
+        self.data.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn format(&self) -> String {
+        self.format.clone()
+    }
+
+    /// Bits per channel of the source pixels; 8-bit sources are widened
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn orientation(&self) -> u8 {
+        self.orientation
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.icc_profile.clone()
+    }
+}
+
+impl DecodedImage16 {
+    pub fn into_data(self) -> Vec<u16> {
+        self.data
+    }
+
+    /// The pixels, without the source information
+    pub fn into_image(self) -> Result<Image<u16>, Error> {
+        Image::new(self.width, self.height, self.data)
+    }
+}
+
+/// Decoded pixels at their native depth plus the source information.
+struct Decoded {
+    image: DynamicImage,
+    format: ImageFormat,
+    bit_depth: u8,
+    orientation: u8,
+    icc_profile: Option<Vec<u8>>,
+}
+
+fn decode(bytes: &[u8]) -> Result<Decoded, Error> {
+    if bytes.is_empty() {
+        return Err(Error::decode_failed("Input is empty"));
+    }
+
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| Error::decode_failed(format!("Failed to read image: {}", e)))?;
+    let format = reader
+        .format()
+        .ok_or_else(|| Error::unsupported("Unrecognized image format"))?;
+
+    if !format.reading_enabled() {
+        let message = match feature_for(format) {
+            Some(feature) => format!(
+                "{} decoding is not enabled (build with the `{}` feature)",
+                format_name(format),
+                feature
+            ),
+            None => format!("Unsupported image format: {:?}", format),
+        };
+        return Err(Error::unsupported(message));
+    }
+
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|e| Error::decode_failed(format!("Failed to decode {}: {}", format_name(format), e)))?;
+    let color = decoder.color_type();
+    // Unreadable EXIF is treated like a missing tag rather than a decode failure
+    let orientation = decoder.orientation().map(|o| o.to_exif()).unwrap_or(1);
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let bit_depth = (color.bits_per_pixel() / color.channel_count() as u16) as u8;
+
+    let image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| Error::decode_failed(format!("Failed to decode {}: {}", format_name(format), e)))?;
+
+    Ok(Decoded {
+        image,
+        format,
+        bit_depth,
+        orientation,
+        icc_profile,
+    })
+}
+
+/// Apply an EXIF Orientation (1-8) with the same mapping as the exported
+/// `apply_exif_orientation`, at either depth
+fn make_upright<T: Copy + Default>(
+    data: Vec<T>,
+    width: u32,
+    height: u32,
+    orientation: u8,
+) -> Result<(Vec<T>, u32, u32), Error> {
+    if orientation == 1 {
+        return Ok((data, width, height));
+    }
+    rotate::apply_exif_orientation(&data, width, height, orientation)
+}
+
+/// Decode a compressed image file (format sniffed from its magic bytes) to RGBA8.
+///
+/// JPEG, PNG and WebP are always available; BMP, GIF, TIFF and ICO are
+/// enabled by the cargo features of the same name. Animated GIFs decode
+/// to their first frame. With `auto_orient`, the EXIF Orientation tag is
+/// applied so the returned pixels are upright. With `to_srgb`, pixels are
+/// converted from the embedded ICC profile to sRGB; profiles that cannot be
+/// applied (gray, CMYK or malformed) leave the pixels and profile as decoded.
+pub fn decode_image(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage, Error> {
+    let Decoded {
+        image,
+        format,
+        bit_depth,
+        orientation,
+        mut icc_profile,
+    } = decode(bytes)?;
+
+    let (mut width, mut height) = (image.width(), image.height());
+    let mut data = image.into_rgba8().into_raw();
+    if auto_orient {
+        (data, width, height) = make_upright(data, width, height, orientation)?;
+    }
+
+    if let Some(profile) = icc_profile.as_deref().filter(|_| to_srgb) {
+        if color_profile::convert_to_srgb_in_place(&mut data, profile).is_ok() {
+            icc_profile = None;
+        }
+    }
+
+    Ok(DecodedImage {
+        data,
+        width,
+        height,
+        format: format_name(format).to_string(),
+        bit_depth,
+        orientation,
+        icc_profile,
+    })
+}
+
+/// Decode a compressed image file to RGBA16, keeping the full precision of
+/// 16-bit PNG and TIFF sources. Options behave as in [`decode_image`].
+pub fn decode_image_16(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage16, Error> {
+    let Decoded {
+        image,
+        format,
+        bit_depth,
+        orientation,
+        mut icc_profile,
+    } = decode(bytes)?;
+
+    let (mut width, mut height) = (image.width(), image.height());
+    let mut data = image.into_rgba16().into_raw();
+    if auto_orient {
+        (data, width, height) = make_upright(data, width, height, orientation)?;
+    }
+
+    if let Some(profile) = icc_profile.as_deref().filter(|_| to_srgb) {
+        if color_profile::convert_to_srgb_in_place_16(&mut data, profile).is_ok() {
+            icc_profile = None;
+        }
+    }
+
+    Ok(DecodedImage16 {
+        data,
+        width,
+        height,
+        format: format_name(format).to_string(),
+        bit_depth,
+        orientation,
+        icc_profile,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageEncoder, Rgba, RgbaImage};
+
+    #[test]
+    fn decodes_png_to_rgba() {
+        let source = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 128]));
+        let mut bytes = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut bytes)
+            .write_image(source.as_raw(), 3, 2, image::ExtendedColorType::Rgba8)
+            .unwrap();
+
+        let decoded = decode_image(&bytes, true, false).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+        assert_eq!(decoded.format(), "png");
+        assert_eq!(decoded.bit_depth(), 8);
+        assert_eq!(decoded.orientation(), 1);
+        assert_eq!(decoded.icc_profile(), None);
+        assert_eq!(decoded.into_data(), source.into_raw());
+    }
+
+    #[test]
+    fn applies_exif_orientation_from_jpeg() {
+        let source = image::RgbImage::from_pixel(16, 8, image::Rgb([200, 100, 50]));
+        let jpeg = jpeg_with_orientation(&source, 6);
+
+        let upright = decode_image(&jpeg, true, false).unwrap();
+        assert_eq!(upright.orientation(), 6);
+        assert_eq!((upright.width(), upright.height()), (8, 16));
+        let upright16 = decode_image_16(&jpeg, true, false).unwrap();
+        assert_eq!((upright16.width(), upright16.height()), (8, 16));
+
+        let raw = decode_image(&jpeg, false, false).unwrap();
+        assert_eq!(raw.orientation(), 6);
+        assert_eq!((raw.width(), raw.height()), (16, 8));
+    }
+
+    #[test]
+    fn keeps_sixteen_bit_precision() {
+        let samples: Vec<u16> = vec![1000, 1001, 65535, 65535, 1002, 1003, 0, 32768];
+        let source = image::ImageBuffer::<Rgba<u16>, _>::from_raw(2, 1, samples.clone()).unwrap();
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba16(source)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+
+        let decoded = decode_image_16(&bytes, true, false).unwrap();
+        assert_eq!(decoded.bit_depth(), 16);
+        assert_eq!(decoded.into_data(), samples);
+    }
+
+    /// JPEG of `source` with an APP1 Exif segment holding a single
+    /// big-endian Orientation entry
+    fn jpeg_with_orientation(source: &image::RgbImage, orientation: u8) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg)
+            .write_image(source.as_raw(), source.width(), source.height(), image::ExtendedColorType::Rgb8)
+            .unwrap();
+
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0, 0, 0, 0, 0]);
+        let mut app1 = vec![0xFF, 0xE1];
+        app1.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        app1.extend_from_slice(b"Exif\0\0");
+        app1.extend_from_slice(&tiff);
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    #[test]
+    fn every_orientation_matches_on_decode_and_export() {
+        let (w, h) = (5u32, 3u32);
+        let source = image::RgbImage::from_fn(w, h, |x, y| image::Rgb([(x * 50) as u8, (y * 100) as u8, 128]));
+
+        for orientation in 1..=8u8 {
+            let jpeg = jpeg_with_orientation(&source, orientation);
+            let raw = decode_image(&jpeg, false, false).unwrap().into_image().unwrap();
+            let upright = decode_image(&jpeg, true, false).unwrap().into_image().unwrap();
+            let upright16 = decode_image_16(&jpeg, true, false).unwrap().into_image().unwrap();
+            let exported = crate::apply_exif_orientation(&raw, orientation).unwrap();
+
+            let (ow, oh) = if orientation >= 5 { (h, w) } else { (w, h) };
+            assert_eq!((upright.width(), upright.height()), (ow, oh));
+            assert_eq!(upright, exported, "orientation {}", orientation);
+            for v in 0..oh {
+                for u in 0..ow {
+                    // Stored pixel shown at (u, v), from the EXIF definitions
+                    let (x, y) = match orientation {
+                        1 => (u, v),
+                        2 => (w - 1 - u, v),
+                        3 => (w - 1 - u, h - 1 - v),
+                        4 => (u, h - 1 - v),
+                        5 => (v, u),
+                        6 => (v, h - 1 - u),
+                        7 => (w - 1 - v, h - 1 - u),
+                        _ => (w - 1 - v, u),
+                    };
+                    let (src, dst) = (crate::utils::pixel_index(w, x, y), crate::utils::pixel_index(ow, u, v));
+                    assert_eq!(upright.data()[dst..dst + 4], raw.data()[src..src + 4], "orientation {}", orientation);
+                    let wide: Vec<u16> = raw.data()[src..src + 4].iter().map(|&c| c as u16 * 257).collect();
+                    assert_eq!(upright16.data()[dst..dst + 4], wide[..]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use photo_editor_common::Error;
+
+use crate::utils;
+
+/// Widen 8-bit RGBA to 16 bits per channel (0-255 maps onto 0-65535).
+pub fn convert_to_rgba16(input: &[u8], width: u32, height: u32, output: &mut [u16]) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    for (src, dst) in input.iter().zip(output.iter_mut()) {
+        *dst = *src as u16 * 257;
+    }
+    Ok(out_len)
+}
+
+/// Narrow 16-bit RGBA to 8 bits per channel, optionally dithered; see
+/// [`photo_editor_common::depth::rgba16_to_rgba8`].
+pub fn convert_to_rgba8(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    dither: bool,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    photo_editor_common::depth::rgba16_to_rgba8(input, dither, &mut output[..out_len]);
+    Ok(out_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dithering_preserves_average_between_steps() {
+        // Exactly halfway between 8-bit codes 100 and 101
+        let half = (100.5 * 257.0) as u16;
+        let input: Vec<u16> = (0..64 * 64).flat_map(|_| [half, half, half, 65535]).collect();
+        let mut plain = vec![0u8; input.len()];
+        let mut dithered = vec![0u8; input.len()];
+        convert_to_rgba8(&input, 64, 64, false, &mut plain).unwrap();
+        convert_to_rgba8(&input, 64, 64, true, &mut dithered).unwrap();
+
+        assert!(plain.chunks(4).all(|p| p[0] == plain[0]));
+        let reds: Vec<f32> = dithered.chunks(4).map(|p| p[0] as f32).collect();
+        let mean = reds.iter().sum::<f32>() / reds.len() as f32;
+        assert!((mean - 100.5).abs() < 0.1);
+        assert!(dithered.chunks(4).all(|p| p[3] == 255));
+    }
+}
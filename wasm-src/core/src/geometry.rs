@@ -0,0 +1,91 @@
+//! Signed distance helpers shared by the drawing and region-based modules.
+//!
+//! All functions take pixel-space coordinates and return a distance in
+//! pixels: negative inside the shape, positive outside.
+
+pub fn segment_distance(px: f32, py: f32, ax: f32, ay: f32, bx: f32, by: f32) -> f32 {
+    let (abx, aby) = (bx - ax, by - ay);
+    let (apx, apy) = (px - ax, py - ay);
+    let len_sq = abx * abx + aby * aby;
+    let t = if len_sq > 0.0 {
+        ((apx * abx + apy * aby) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let dx = apx - abx * t;
+    let dy = apy - aby * t;
+    (dx * dx + dy * dy).sqrt()
+}
+
+pub fn box_sdf(px: f32, py: f32, cx: f32, cy: f32, half_w: f32, half_h: f32, radius: f32) -> f32 {
+    let radius = radius.clamp(0.0, half_w.min(half_h).max(0.0));
+    let qx = (px - cx).abs() - half_w + radius;
+    let qy = (py - cy).abs() - half_h + radius;
+    let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+    outside + qx.max(qy).min(0.0) - radius
+}
+
+pub fn ellipse_sdf(px: f32, py: f32, cx: f32, cy: f32, rx: f32, ry: f32) -> f32 {
+    if rx <= 0.0 || ry <= 0.0 {
+        return segment_distance(px, py, cx - rx.max(0.0), cy - ry.max(0.0), cx + rx.max(0.0), cy + ry.max(0.0));
+    }
+    let (x, y) = (px - cx, py - cy);
+    let k0 = ((x / rx).powi(2) + (y / ry).powi(2)).sqrt();
+    let k1 = ((x / (rx * rx)).powi(2) + (y / (ry * ry)).powi(2)).sqrt();
+    if k1 == 0.0 {
+        return -rx.min(ry);
+    }
+    k0 * (k0 - 1.0) / k1
+}
+
+/// Signed distance to a closed polygon (even-odd fill rule).
+pub fn polygon_sdf(px: f32, py: f32, points: &[[f32; 2]]) -> f32 {
+    if points.is_empty() {
+        return f32::MAX;
+    }
+
+    let mut dist = f32::MAX;
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let [xi, yi] = points[i];
+        let [xj, yj] = points[j];
+        dist = dist.min(segment_distance(px, py, xj, yj, xi, yi));
+        if (yi > py) != (yj > py) && px < (xj - xi) * (py - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    if inside {
+        -dist
+    } else {
+        dist
+    }
+}
+
+/// Axis-aligned bounds of a point list as (min_x, min_y, max_x, max_y).
+pub fn bounds(points: &[[f32; 2]]) -> (f32, f32, f32, f32) {
+    points.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(x0, y0, x1, y1), &[x, y]| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+    )
+}
+
+/// Fraction of a pixel covered by a shape edge at signed distance `d`.
+pub fn coverage(d: f32) -> f32 {
+    (0.5 - d).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_sign_matches_containment() {
+        let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        assert!(polygon_sdf(5.0, 5.0, &square) < 0.0);
+        assert!(polygon_sdf(15.0, 5.0, &square) > 0.0);
+        assert!((polygon_sdf(15.0, 5.0, &square) - 5.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,467 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use photo_editor_common::Error;
+
+use crate::{utils, InpaintMethod};
+
+const KNOWN: u8 = 0;
+const BAND: u8 = 1;
+const INSIDE: u8 = 2;
+
+const INF: f32 = 1.0e6;
+
+/// Fill every pixel whose mask value is non-zero from its surroundings.
+///
+/// The mask uses one byte per pixel, the same layout `magic_wand_select` and
+/// `grabcut_segment` produce, so a selection can be removed directly.
+/// `radius` is the Telea neighbourhood radius or the exemplar patch
+/// half-size, depending on the method, and at most the image's longer side.
+pub fn inpaint(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    mask: &[u8],
+    method: InpaintMethod,
+    radius: u32,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+    if mask.len() != (width * height) as usize {
+        return Err(Error::input_size_mismatch("Mask length mismatch"));
+    }
+    // Keeps the i32 pixel arithmetic below from overflowing
+    if radius > width.max(height) {
+        return Err(Error::invalid_parameter("Radius must not exceed the image size"));
+    }
+
+    output[..out_len].copy_from_slice(input);
+    let data = &mut output[..out_len];
+    let hole: Vec<bool> = mask.iter().map(|&m| m != 0).collect();
+    if !hole.contains(&true) {
+        return Ok(out_len);
+    }
+    if !hole.contains(&false) {
+        return Err(Error::invalid_parameter("Mask covers the whole image"));
+    }
+
+    match method {
+        InpaintMethod::Telea => telea(data, width, height, &hole, radius.max(1)),
+        InpaintMethod::Exemplar => {
+            let remaining = exemplar(data, width, height, &hole, radius.max(1));
+            if remaining.contains(&true) {
+                telea(data, width, height, &remaining, radius.max(1));
+            }
+        }
+    }
+
+    Ok(out_len)
+}
+
+#[derive(PartialEq)]
+struct Front {
+    t: f32,
+    idx: usize,
+}
+
+impl Eq for Front {}
+
+impl Ord for Front {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the smallest arrival time first
+        other.t.total_cmp(&self.t).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Front {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Telea's fast marching inpainting: pixels are filled in order of their
+/// distance to the hole boundary, each from a weighted average of the known
+/// pixels within `radius`.
+fn telea(data: &mut [u8], width: u32, height: u32, hole: &[bool], radius: u32) {
+    let (w, h) = (width as i32, height as i32);
+    let mut flag: Vec<u8> = hole.iter().map(|&m| if m { INSIDE } else { KNOWN }).collect();
+    let mut t: Vec<f32> = hole.iter().map(|&m| if m { INF } else { 0.0 }).collect();
+    let mut heap = BinaryHeap::new();
+
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            if flag[idx] == KNOWN && neighbours(x, y, w, h).any(|n| flag[n] == INSIDE) {
+                flag[idx] = BAND;
+                heap.push(Front { t: 0.0, idx });
+            }
+        }
+    }
+
+    while let Some(Front { idx, .. }) = heap.pop() {
+        if flag[idx] == KNOWN {
+            continue;
+        }
+        flag[idx] = KNOWN;
+
+        let (x, y) = ((idx as i32) % w, (idx as i32) / w);
+        for n in neighbours(x, y, w, h).collect::<Vec<_>>() {
+            if flag[n] != INSIDE {
+                continue;
+            }
+            let (nx, ny) = ((n as i32) % w, (n as i32) / w);
+            flag[n] = BAND;
+            t[n] = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+                .iter()
+                .map(|&(dx, dy)| solve_eikonal(&t, &flag, w, h, (nx + dx, ny), (nx, ny + dy)))
+                .fold(INF, f32::min);
+            paint_pixel(data, &t, &flag, w, h, (nx, ny), radius as i32);
+            heap.push(Front { t: t[n], idx: n });
+        }
+    }
+}
+
+fn neighbours(x: i32, y: i32, w: i32, h: i32) -> impl Iterator<Item = usize> {
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < w && ny < h)
+        .map(move |(nx, ny)| (ny * w + nx) as usize)
+}
+
+fn known_time(t: &[f32], flag: &[u8], w: i32, h: i32, (x, y): (i32, i32)) -> f32 {
+    if x < 0 || y < 0 || x >= w || y >= h {
+        return INF;
+    }
+    let idx = (y * w + x) as usize;
+    if flag[idx] == KNOWN {
+        t[idx]
+    } else {
+        INF
+    }
+}
+
+/// First-order solution of |∇T| = 1 from two axis-aligned neighbours.
+fn solve_eikonal(t: &[f32], flag: &[u8], w: i32, h: i32, a: (i32, i32), b: (i32, i32)) -> f32 {
+    let ta = known_time(t, flag, w, h, a);
+    let tb = known_time(t, flag, w, h, b);
+
+    if ta < INF && tb < INF {
+        let diff = ta - tb;
+        if diff.abs() < 1.0 {
+            let r = (2.0 - diff * diff).sqrt();
+            return (ta + tb + r) / 2.0;
+        }
+        return 1.0 + ta.min(tb);
+    }
+    if ta < INF {
+        return 1.0 + ta;
+    }
+    if tb < INF {
+        return 1.0 + tb;
+    }
+    INF
+}
+
+fn paint_pixel(data: &mut [u8], t: &[f32], flag: &[u8], w: i32, h: i32, (x, y): (i32, i32), radius: i32) {
+    let idx = (y * w + x) as usize;
+    let grad = |a: (i32, i32), b: (i32, i32)| {
+        let ta = known_time(t, flag, w, h, a);
+        let tb = known_time(t, flag, w, h, b);
+        match (ta < INF, tb < INF) {
+            (true, true) => (tb - ta) / 2.0,
+            (true, false) => t[idx] - ta,
+            (false, true) => tb - t[idx],
+            (false, false) => 0.0,
+        }
+    };
+    let gx = grad((x - 1, y), (x + 1, y));
+    let gy = grad((x, y - 1), (x, y + 1));
+
+    let mut sum = [0f32; 4];
+    let mut total = 0f32;
+    for ky in (y - radius).max(0)..=(y + radius).min(h - 1) {
+        for kx in (x - radius).max(0)..=(x + radius).min(w - 1) {
+            let k = (ky * w + kx) as usize;
+            if k == idx || flag[k] == INSIDE {
+                continue;
+            }
+            let (rx, ry) = ((x - kx) as f32, (y - ky) as f32);
+            let dist_sq = rx * rx + ry * ry;
+            if dist_sq > (radius as f32).powi(2) {
+                continue;
+            }
+
+            let dir = ((rx * gx + ry * gy).abs() / dist_sq.sqrt()).max(1e-6);
+            let dst = 1.0 / dist_sq;
+            let lev = 1.0 / (1.0 + (t[k] - t[idx]).abs());
+            let weight = dir * dst * lev;
+
+            for (c, s) in sum.iter_mut().enumerate() {
+                *s += data[k * 4 + c] as f32 * weight;
+            }
+            total += weight;
+        }
+    }
+
+    if total > 0.0 {
+        for (c, s) in sum.iter().enumerate() {
+            data[idx * 4 + c] = (s / total).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Exemplar-based inpainting (Criminisi et al.): the fill front is grown
+/// patch by patch, always continuing the most confident, most structured
+/// edge with the best-matching fully known patch nearby.
+///
+/// Returns the pixels that could not be filled, if no source patch exists.
+fn exemplar(data: &mut [u8], width: u32, height: u32, hole: &[bool], half: u32) -> Vec<bool> {
+    let (w, h) = (width as i32, height as i32);
+    let half = half as i32;
+    let patch_area = ((2 * half + 1) as f32).powi(2);
+
+    let mut unknown = hole.to_vec();
+    let mut confidence: Vec<f32> = hole.iter().map(|&m| if m { 0.0 } else { 1.0 }).collect();
+
+    // Integral image of the original hole so a candidate source patch can be
+    // checked for being fully known in O(1).
+    let stride = (w + 1) as usize;
+    let mut hole_sum = vec![0u32; stride * (h + 1) as usize];
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            let cell = (y + 1) as usize * stride + (x + 1) as usize;
+            hole_sum[cell] = hole[idx] as u32 + hole_sum[cell - 1] + hole_sum[cell - stride]
+                - hole_sum[cell - stride - 1];
+        }
+    }
+    let hole_in = |x0: i32, y0: i32, x1: i32, y1: i32| {
+        let (x0, y0, x1, y1) = (x0 as usize, y0 as usize, (x1 + 1) as usize, (y1 + 1) as usize);
+        hole_sum[y1 * stride + x1] + hole_sum[y0 * stride + x0]
+            - hole_sum[y0 * stride + x1]
+            - hole_sum[y1 * stride + x0]
+    };
+
+    let (mut bx0, mut by0, mut bx1, mut by1) = (w, h, 0, 0);
+    for y in 0..h {
+        for x in 0..w {
+            if hole[(y * w + x) as usize] {
+                (bx0, by0, bx1, by1) = (bx0.min(x), by0.min(y), bx1.max(x), by1.max(y));
+            }
+        }
+    }
+    let margin = (2 * half + 1).saturating_mul(6).max(32);
+    let (sx0, sy0) = ((bx0 - margin).max(0), (by0 - margin).max(0));
+    let (sx1, sy1) = ((bx1 + margin).min(w - 1), (by1 + margin).min(h - 1));
+    let step = (half / 2).max(1) as usize;
+
+    loop {
+        // Pick the front pixel with the highest priority
+        let mut target = None;
+        let mut best_priority = -1.0f32;
+        for y in by0..=by1 {
+            for x in bx0..=bx1 {
+                let idx = (y * w + x) as usize;
+                if !unknown[idx] || !neighbours(x, y, w, h).any(|n| !unknown[n]) {
+                    continue;
+                }
+
+                let mut conf = 0.0;
+                for py in (y - half).max(0)..=(y + half).min(h - 1) {
+                    for px in (x - half).max(0)..=(x + half).min(w - 1) {
+                        let p = (py * w + px) as usize;
+                        if !unknown[p] {
+                            conf += confidence[p];
+                        }
+                    }
+                }
+                conf /= patch_area;
+
+                let priority = conf * (data_term(data, &unknown, w, h, x, y) + 1e-3);
+                if priority > best_priority {
+                    best_priority = priority;
+                    target = Some((x, y, conf));
+                }
+            }
+        }
+
+        let Some((tx, ty, conf)) = target else {
+            break;
+        };
+
+        // Find the most similar fully known source patch
+        let mut source = None;
+        let mut best_ssd = u64::MAX;
+        for cy in ((sy0 + half)..=(sy1 - half)).step_by(step) {
+            for cx in ((sx0 + half)..=(sx1 - half)).step_by(step) {
+                if hole_in(cx - half, cy - half, cx + half, cy + half) != 0 {
+                    continue;
+                }
+
+                let mut ssd = 0u64;
+                'patch: for dy in -half..=half {
+                    for dx in -half..=half {
+                        let (px, py) = (tx + dx, ty + dy);
+                        if px < 0 || py < 0 || px >= w || py >= h {
+                            continue;
+                        }
+                        let p = (py * w + px) as usize;
+                        if unknown[p] {
+                            continue;
+                        }
+                        let q = ((cy + dy) * w + cx + dx) as usize;
+                        for c in 0..4 {
+                            let d = data[p * 4 + c] as i64 - data[q * 4 + c] as i64;
+                            ssd += (d * d) as u64;
+                        }
+                        if ssd >= best_ssd {
+                            break 'patch;
+                        }
+                    }
+                }
+
+                if ssd < best_ssd {
+                    best_ssd = ssd;
+                    source = Some((cx, cy));
+                }
+            }
+        }
+
+        let Some((cx, cy)) = source else {
+            break;
+        };
+
+        for dy in -half..=half {
+            for dx in -half..=half {
+                let (px, py) = (tx + dx, ty + dy);
+                if px < 0 || py < 0 || px >= w || py >= h {
+                    continue;
+                }
+                let p = (py * w + px) as usize;
+                if !unknown[p] {
+                    continue;
+                }
+                let q = ((cy + dy) * w + cx + dx) as usize;
+                data.copy_within(q * 4..q * 4 + 4, p * 4);
+                unknown[p] = false;
+                confidence[p] = conf;
+            }
+        }
+    }
+
+    unknown
+}
+
+/// Strength of the isophote flowing into the fill front at (x, y).
+fn data_term(data: &[u8], unknown: &[bool], w: i32, h: i32, x: i32, y: i32) -> f32 {
+    let gray = |px: i32, py: i32| -> Option<f32> {
+        if px < 0 || py < 0 || px >= w || py >= h || unknown[(py * w + px) as usize] {
+            return None;
+        }
+        let i = ((py * w + px) * 4) as usize;
+        Some(0.299 * data[i] as f32 + 0.587 * data[i + 1] as f32 + 0.114 * data[i + 2] as f32)
+    };
+    let filled = |px: i32, py: i32| -> f32 {
+        if px < 0 || py < 0 || px >= w || py >= h {
+            return 0.0;
+        }
+        (!unknown[(py * w + px) as usize]) as u8 as f32
+    };
+
+    // Strongest known gradient around the pixel
+    let (mut gx, mut gy, mut magnitude) = (0.0f32, 0.0f32, 0.0f32);
+    for py in y - 1..=y + 1 {
+        for px in x - 1..=x + 1 {
+            if let (Some(l), Some(r), Some(u), Some(d)) =
+                (gray(px - 1, py), gray(px + 1, py), gray(px, py - 1), gray(px, py + 1))
+            {
+                let (dx, dy) = ((r - l) / 2.0, (d - u) / 2.0);
+                let m = dx * dx + dy * dy;
+                if m > magnitude {
+                    (gx, gy, magnitude) = (dx, dy, m);
+                }
+            }
+        }
+    }
+
+    // Front normal from the known/unknown boundary
+    let nx = filled(x + 1, y) - filled(x - 1, y);
+    let ny = filled(x, y + 1) - filled(x, y - 1);
+    let norm = (nx * nx + ny * ny).sqrt();
+    if norm == 0.0 {
+        return 0.0;
+    }
+
+    // Isophote is perpendicular to the gradient
+    ((-gy * nx + gx * ny) / norm).abs() / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripes(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| if (i % width) % 4 < 2 { [20, 40, 60, 255] } else { [200, 180, 160, 255] })
+            .collect()
+    }
+
+    fn square_mask(width: u32, height: u32, x0: u32, y0: u32, size: u32) -> Vec<u8> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x >= x0 && x < x0 + size && y >= y0 && y < y0 + size { 255 } else { 0 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn telea_fills_flat_region() {
+        let mut input = [90, 120, 150, 255].repeat(16 * 16);
+        let mask = square_mask(16, 16, 5, 5, 4);
+        for (i, &m) in mask.iter().enumerate() {
+            if m != 0 {
+                input[i * 4..i * 4 + 4].copy_from_slice(&[255, 0, 255, 255]);
+            }
+        }
+        let mut output = vec![0u8; input.len()];
+        inpaint(&input, 16, 16, &mask, InpaintMethod::Telea, 3, &mut output).unwrap();
+
+        assert!(output.chunks(4).all(|p| p == [90, 120, 150, 255]));
+    }
+
+    #[test]
+    fn exemplar_continues_texture() {
+        let original = stripes(48, 48);
+        let mask = square_mask(48, 48, 18, 18, 10);
+        let mut input = original.clone();
+        for (i, &m) in mask.iter().enumerate() {
+            if m != 0 {
+                input[i * 4..i * 4 + 4].copy_from_slice(&[0, 255, 0, 255]);
+            }
+        }
+        let mut output = vec![0u8; input.len()];
+        inpaint(&input, 48, 48, &mask, InpaintMethod::Exemplar, 3, &mut output).unwrap();
+
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn huge_radius_is_rejected_or_bounded() {
+        let input = [90, 120, 150, 255].repeat(16 * 16);
+        let mask = square_mask(16, 16, 5, 5, 4);
+        let mut output = vec![0u8; input.len()];
+        let error = inpaint(&input, 16, 16, &mask, InpaintMethod::Telea, 100_000, &mut output).unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::InvalidParameter);
+
+        // A radius up to the longer side of a long strip must not overflow
+        let input = [90, 120, 150, 255].repeat(60_000);
+        let mask = square_mask(60_000, 1, 100, 0, 2);
+        let mut output = vec![0u8; input.len()];
+        for method in [InpaintMethod::Telea, InpaintMethod::Exemplar] {
+            inpaint(&input, 60_000, 1, &mask, method, 60_000, &mut output).unwrap();
+            assert!(output.chunks(4).all(|p| p == [90, 120, 150, 255]));
+        }
+    }
+}
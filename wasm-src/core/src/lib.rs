@@ -1,11 +1,27 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+mod annotate;
+mod color_profile;
 mod crop;
+mod decode;
+mod depth;
+mod geometry;
+mod inpaint;
+mod redact;
 mod resize;
+mod retouch;
 mod rotate;
+mod tone;
 mod utils;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-#[wasm_bindgen]
+pub use decode::{DecodedImage, DecodedImage16};
+pub use photo_editor_common::{Error, ErrorCode, Image, Progress, Result};
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub struct CropRect {
     pub x: u32,
     pub y: u32,
@@ -13,15 +29,16 @@ pub struct CropRect {
     pub height: u32,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl CropRect {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new(x: u32, y: u32, width: u32, height: u32) -> CropRect {
         CropRect { x, y, width, height }
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub enum RotateAngle {
     Degree0 = 0,
     Degree90 = 90,
@@ -29,60 +46,217 @@ pub enum RotateAngle {
     Degree270 = 270,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub enum FlipDirection {
     Horizontal,
     Vertical,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub enum ResizeQuality {
     Fast = 0,
     High = 1,
 }
 
-#[wasm_bindgen]
-pub fn crop_image(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    crop_rect: &CropRect,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
-    crop::crop_image(input, width, height, crop_rect, output)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
+pub enum InpaintMethod {
+    /// Fast marching (Telea); best for scratches, dust and thin defects
+    Telea = 0,
+    /// Exemplar patch copying; best for larger objects on textured backgrounds
+    Exemplar = 1,
 }
 
-#[wasm_bindgen]
-pub fn rotate_image(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    angle: RotateAngle,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
-    rotate::rotate_image(input, width, height, angle, output)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
+pub enum BrushMode {
+    /// Copy pixels from the source offset
+    Clone = 0,
+    /// Copy texture from the source offset while keeping the target's tone
+    Heal = 1,
 }
 
-#[wasm_bindgen]
-pub fn flip_image(
-    input: &[u8],
-    width: u32,
-    height: u32,
-    direction: FlipDirection,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
-    rotate::flip_image(input, width, height, direction, output)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
+pub struct BrushParams {
+    pub radius: f32,
+    /// 0.0 = fully soft edge, 1.0 = hard edge
+    pub hardness: f32,
+    /// Source position relative to the stroke, in pixels
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub opacity: f32,
+    pub mode: BrushMode,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl BrushParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(radius: f32, hardness: f32, offset_x: f32, offset_y: f32, opacity: f32, mode: BrushMode) -> BrushParams {
+        BrushParams { radius, hardness, offset_x, offset_y, opacity, mode }
+    }
+}
+
+
+// Native API: the algorithms behind the JavaScript exports in wasm.rs,
+// taking and returning owned images instead of filling output buffers
+
+pub fn crop_image(image: &Image, crop_rect: &CropRect) -> Result<Image> {
+    with_output(crop_rect.width, crop_rect.height, |output| {
+        crop::crop_image(image.data(), image.width(), image.height(), crop_rect, output)
+    })
+}
+
+pub fn rotate_image(image: &Image, angle: RotateAngle) -> Result<Image> {
+    let (width, height) = match angle {
+        RotateAngle::Degree90 | RotateAngle::Degree270 => (image.height(), image.width()),
+        RotateAngle::Degree0 | RotateAngle::Degree180 => (image.width(), image.height()),
+    };
+    with_output(width, height, |output| {
+        rotate::rotate_image(image.data(), image.width(), image.height(), angle, output)
+    })
 }
 
-#[wasm_bindgen]
+pub fn flip_image(image: &Image, direction: FlipDirection) -> Result<Image> {
+    with_output(image.width(), image.height(), |output| {
+        rotate::flip_image(image.data(), image.width(), image.height(), direction, output)
+    })
+}
+
+/// Fails with `ErrorCode::Cancelled` once `progress` asks to stop.
 pub fn resize_image(
-    input: &[u8],
-    width: u32,
-    height: u32,
+    image: &Image,
     new_width: u32,
     new_height: u32,
     quality: ResizeQuality,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
-    resize::resize_image(input, width, height, new_width, new_height, quality, output)
+    progress: &mut Progress,
+) -> Result<Image> {
+    with_output(new_width, new_height, |output| {
+        resize::resize_image(image.data(), image.width(), image.height(), new_width, new_height, quality, output, progress)
+    })
+}
+
+pub fn resize_image_16(
+    image: &Image<u16>,
+    new_width: u32,
+    new_height: u32,
+    quality: ResizeQuality,
+    progress: &mut Progress,
+) -> Result<Image<u16>> {
+    with_output(new_width, new_height, |output| {
+        resize::resize_image_16(image.data(), image.width(), image.height(), new_width, new_height, quality, output, progress)
+    })
+}
+
+/// Apply a JSON levels/curve spec (normalized 0-1 values).
+pub fn adjust_tone(image: &Image, tone_json: &str) -> Result<Image> {
+    let spec = tone::parse_tone(tone_json)?;
+    with_output(image.width(), image.height(), |output| {
+        tone::adjust_tone(image.data(), image.width(), image.height(), &spec, output)
+    })
+}
+
+pub fn adjust_tone_16(image: &Image<u16>, tone_json: &str) -> Result<Image<u16>> {
+    let spec = tone::parse_tone(tone_json)?;
+    with_output(image.width(), image.height(), |output| {
+        tone::adjust_tone_16(image.data(), image.width(), image.height(), &spec, output)
+    })
+}
+
+/// Draw a JSON list of vector annotations onto a copy of the image.
+pub fn draw_annotations(image: &Image, annotations_json: &str) -> Result<Image> {
+    let annotations = annotate::parse_annotations(annotations_json)?;
+    with_output(image.width(), image.height(), |output| {
+        annotate::draw_annotations(image.data(), image.width(), image.height(), &annotations, output)
+    })
+}
+
+/// Irreversibly obscure the regions of a JSON redaction spec.
+pub fn redact_regions(image: &Image, spec_json: &str) -> Result<Image> {
+    let spec = redact::parse_redaction(spec_json)?;
+    with_output(image.width(), image.height(), |output| {
+        redact::redact_regions(image.data(), image.width(), image.height(), &spec, output)
+    })
+}
+
+/// Fill the pixels selected by `mask` (one byte per pixel, non-zero = remove).
+pub fn inpaint(image: &Image, mask: &[u8], method: InpaintMethod, radius: u32) -> Result<Image> {
+    with_output(image.width(), image.height(), |output| {
+        inpaint::inpaint(image.data(), image.width(), image.height(), mask, method, radius, output)
+    })
+}
+
+/// `path` is a flat list of x, y pairs in pixel coordinates.
+pub fn brush_stroke(image: &Image, path: &[f32], params: &BrushParams) -> Result<Image> {
+    with_output(image.width(), image.height(), |output| {
+        retouch::brush_stroke(image.data(), image.width(), image.height(), path, params, output)
+    })
+}
+
+/// Decode an image file; see `DecodedImage::into_image` for the pixels.
+pub fn decode_image(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage> {
+    decode::decode_image(bytes, auto_orient, to_srgb)
+}
+
+pub fn decode_image_16(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage16> {
+    decode::decode_image_16(bytes, auto_orient, to_srgb)
+}
+
+pub fn convert_to_rgba16(image: &Image) -> Result<Image<u16>> {
+    with_output(image.width(), image.height(), |output| {
+        depth::convert_to_rgba16(image.data(), image.width(), image.height(), output)
+    })
+}
+
+pub fn convert_to_rgba8(image: &Image<u16>, dither: bool) -> Result<Image> {
+    with_output(image.width(), image.height(), |output| {
+        depth::convert_to_rgba8(image.data(), image.width(), image.height(), dither, output)
+    })
+}
+
+/// Orientations 5-8 swap width and height.
+pub fn apply_exif_orientation(image: &Image, orientation: u8) -> Result<Image> {
+    let (data, width, height) = rotate::apply_exif_orientation(image.data(), image.width(), image.height(), orientation)?;
+    Image::new(width, height, data)
+}
+
+pub fn convert_to_srgb(image: &Image, icc_profile: &[u8]) -> Result<Image> {
+    with_output(image.width(), image.height(), |output| {
+        color_profile::convert_to_srgb(image.data(), image.width(), image.height(), icc_profile, output)
+    })
+}
+
+/// Run an operation that writes a `width` x `height` image into a buffer
+fn with_output<T: Copy + Default>(
+    width: u32,
+    height: u32,
+    fill: impl FnOnce(&mut [T]) -> Result<usize>,
+) -> Result<Image<T>> {
+    let mut data = vec![T::default(); utils::checked_len(width, height)?];
+    fill(&mut data)?;
+    Image::new(width, height, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_api_returns_owned_images() {
+        let image = Image::new(3, 2, (0..24).collect()).unwrap();
+
+        let rotated = rotate_image(&image, RotateAngle::Degree90).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+
+        let cropped = crop_image(&image, &CropRect::new(1, 0, 2, 1)).unwrap();
+        assert_eq!(cropped.data(), &(4..12).collect::<Vec<u8>>()[..]);
+
+        let resized = resize_image(&image, 6, 4, ResizeQuality::High, &mut Progress::none()).unwrap();
+        assert_eq!(resized.data().len(), 6 * 4 * 4);
+
+        let error = crop_image(&image, &CropRect::new(2, 0, 2, 1)).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::OutOfBounds);
+    }
 }
@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use photo_editor_common::Error;
+
+use crate::{geometry, utils};
+
+/// Smallest mosaic block accepted; smaller blocks leave text legible.
+const MIN_BLOCK_SIZE: u32 = 8;
+
+/// Smallest blur radius accepted for the same reason.
+const MIN_BLUR_RADIUS: u32 = 8;
+
+/// Box blur passes used to approximate a Gaussian.
+const BLUR_PASSES: usize = 3;
+
+/// A pixel belongs to a region if its center lies within half a pixel
+/// diagonal of the outline, so partially covered edge pixels are redacted too.
+const EDGE_MARGIN: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Redaction request, serialized as
+/// `{"mode":{"type":"pixelate","blockSize":16},"regions":[{"type":"rect","x":0,"y":0,"width":40,"height":20}]}`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RedactionSpec {
+    pub mode: RedactionMode,
+    pub regions: Vec<RedactionRegion>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RedactionRegion {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RedactionMode {
+    Pixelate {
+        #[serde(rename = "blockSize")]
+        block_size: u32,
+    },
+    Blur {
+        radius: u32,
+    },
+    Fill {
+        color: [u8; 3],
+    },
+}
+
+pub fn parse_redaction(json: &str) -> Result<RedactionSpec, Error> {
+    serde_json::from_str(json)
+        .map_err(|e| Error::invalid_parameter(format!("Invalid redaction spec: {}", e)))
+}
+
+/// Irreversibly obscure every region of the spec.
+///
+/// None of the modes are invertible: pixelation replaces each block with its
+/// mean, blur first pixelates with a block the size of the radius and then
+/// smooths the block edges, and fill discards the pixels entirely. Regions
+/// that fall partly or wholly outside the image are clipped, so one spec can
+/// be applied across a batch of differently sized images. Block sizes and
+/// radii larger than the image's longer side are rejected.
+pub fn redact_regions(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    spec: &RedactionSpec,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+    // Larger blocks or radii cover the whole image anyway
+    let max_strength = width.max(height);
+    match spec.mode {
+        RedactionMode::Pixelate { block_size } if block_size > max_strength => {
+            return Err(Error::invalid_parameter("Block size must not exceed the image size"));
+        }
+        RedactionMode::Blur { radius } if radius > max_strength => {
+            return Err(Error::invalid_parameter("Blur radius must not exceed the image size"));
+        }
+        _ => {}
+    }
+
+    output[..out_len].copy_from_slice(input);
+
+    let mask = build_mask(width, height, &spec.regions);
+    let Some(bounds) = mask_bounds(&mask, width, height) else {
+        return Ok(out_len);
+    };
+
+    let data = &mut output[..out_len];
+    match spec.mode {
+        RedactionMode::Pixelate { block_size } => {
+            pixelate(data, width, &mask, bounds, block_size.max(MIN_BLOCK_SIZE));
+        }
+        RedactionMode::Blur { radius } => {
+            let radius = radius.max(MIN_BLUR_RADIUS);
+            pixelate(data, width, &mask, bounds, radius);
+            blur(data, width, height, &mask, bounds, radius);
+        }
+        RedactionMode::Fill { color } => {
+            for (i, &selected) in mask.iter().enumerate() {
+                if selected {
+                    data[i * 4..i * 4 + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+                }
+            }
+        }
+    }
+
+    Ok(out_len)
+}
+
+fn build_mask(width: u32, height: u32, regions: &[RedactionRegion]) -> Vec<bool> {
+    let mut mask = vec![false; (width * height) as usize];
+
+    for region in regions {
+        let points = match region {
+            RedactionRegion::Rect { x, y, width, height } => {
+                vec![[*x, *y], [x + width, *y], [x + width, y + height], [*x, y + height]]
+            }
+            RedactionRegion::Polygon { points } => points.clone(),
+        };
+        if points.len() < 3 {
+            continue;
+        }
+
+        let (min_x, min_y, max_x, max_y) = geometry::bounds(&points);
+        let x0 = (min_x - 1.0).floor().max(0.0) as u32;
+        let y0 = (min_y - 1.0).floor().max(0.0) as u32;
+        let x1 = ((max_x + 1.0).ceil().max(0.0) as u32).min(width);
+        let y1 = ((max_y + 1.0).ceil().max(0.0) as u32).min(height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let d = geometry::polygon_sdf(x as f32 + 0.5, y as f32 + 0.5, &points);
+                if d < EDGE_MARGIN {
+                    mask[(y * width + x) as usize] = true;
+                }
+            }
+        }
+    }
+
+    mask
+}
+
+/// Bounding box of the selected pixels as (x0, y0, x1, y1), exclusive end.
+fn mask_bounds(mask: &[bool], width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for y in 0..height {
+        for x in 0..width {
+            if mask[(y * width + x) as usize] {
+                let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x + 1, y + 1));
+                bounds = Some((x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)));
+            }
+        }
+    }
+    bounds
+}
+
+/// Replace the selected pixels of every grid-aligned block with their mean.
+fn pixelate(data: &mut [u8], width: u32, mask: &[bool], bounds: (u32, u32, u32, u32), block: u32) {
+    let (x0, y0, x1, y1) = bounds;
+    let start_x = x0 - x0 % block;
+    let start_y = y0 - y0 % block;
+
+    for by in (start_y..y1).step_by(block as usize) {
+        for bx in (start_x..x1).step_by(block as usize) {
+            let ex = (bx + block).min(x1);
+            let ey = (by + block).min(y1);
+
+            let mut sum = [0u64; 4];
+            let mut count = 0u64;
+            for y in by..ey {
+                for x in bx..ex {
+                    if mask[(y * width + x) as usize] {
+                        let idx = utils::pixel_index(width, x, y);
+                        for c in 0..4 {
+                            sum[c] += data[idx + c] as u64;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+
+            let mean = sum.map(|s| ((s + count / 2) / count) as u8);
+            for y in by..ey {
+                for x in bx..ex {
+                    if mask[(y * width + x) as usize] {
+                        let idx = utils::pixel_index(width, x, y);
+                        data[idx..idx + 4].copy_from_slice(&mean);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Approximate Gaussian blur of the selected pixels via repeated box blurs.
+fn blur(data: &mut [u8], width: u32, height: u32, mask: &[bool], bounds: (u32, u32, u32, u32), radius: u32) {
+    let (x0, y0, x1, y1) = bounds;
+    let x0 = x0.saturating_sub(radius);
+    let y0 = y0.saturating_sub(radius);
+    let x1 = x1.saturating_add(radius).min(width);
+    let y1 = y1.saturating_add(radius).min(height);
+    let w = (x1 - x0) as usize;
+    let h = (y1 - y0) as usize;
+
+    let mut buf = vec![[0f32; 4]; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let idx = utils::pixel_index(width, x0 + x as u32, y0 + y as u32);
+            for c in 0..4 {
+                buf[y * w + x][c] = data[idx + c] as f32;
+            }
+        }
+    }
+
+    let mut tmp = buf.clone();
+    let r = radius as usize;
+    for _ in 0..BLUR_PASSES {
+        box_blur_pass(&buf, &mut tmp, w, h, r, true);
+        box_blur_pass(&tmp, &mut buf, w, h, r, false);
+    }
+
+    for y in 0..h {
+        for x in 0..w {
+            let (ix, iy) = (x0 + x as u32, y0 + y as u32);
+            if mask[(iy * width + ix) as usize] {
+                let idx = utils::pixel_index(width, ix, iy);
+                for c in 0..4 {
+                    data[idx + c] = buf[y * w + x][c].round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Mean over the window `i - r..=i + r`, clipped to the line, kept as a
+/// running sum so the cost doesn't grow with the radius.
+fn box_blur_pass(src: &[[f32; 4]], dst: &mut [[f32; 4]], w: usize, h: usize, r: usize, horizontal: bool) {
+    let (lines, len) = if horizontal { (h, w) } else { (w, h) };
+    let at = |line: usize, i: usize| if horizontal { line * w + i } else { i * w + line };
+
+    for line in 0..lines {
+        // f64 so the adds and subtracts along a long line don't drift
+        let mut sum = [0f64; 4];
+        for j in 0..=r.min(len - 1) {
+            let p = src[at(line, j)];
+            for c in 0..4 {
+                sum[c] += p[c] as f64;
+            }
+        }
+        for i in 0..len {
+            let lo = i.saturating_sub(r);
+            let hi = (i + r).min(len - 1);
+            let n = (hi - lo + 1) as f64;
+            dst[at(line, i)] = sum.map(|s| (s / n) as f32);
+
+            if i + r + 1 < len {
+                let p = src[at(line, i + r + 1)];
+                for c in 0..4 {
+                    sum[c] += p[c] as f64;
+                }
+            }
+            if i >= r {
+                let p = src[at(line, i - r)];
+                for c in 0..4 {
+                    sum[c] -= p[c] as f64;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 7 % 256) as u8, (i * 13 % 256) as u8, (i * 29 % 256) as u8, 255])
+            .collect()
+    }
+
+    #[test]
+    fn pixelate_flattens_blocks_inside_region_only() {
+        let input = gradient(16, 16);
+        let mut output = vec![0u8; input.len()];
+        let spec = parse_redaction(
+            r#"{"mode":{"type":"pixelate","blockSize":8},"regions":[{"type":"rect","x":0,"y":0,"width":8,"height":8}]}"#,
+        )
+        .unwrap();
+        redact_regions(&input, 16, 16, &spec, &mut output).unwrap();
+
+        let first = utils::pixel_index(16, 0, 0);
+        let last = utils::pixel_index(16, 7, 7);
+        assert_eq!(output[first..first + 4], output[last..last + 4]);
+        let outside = utils::pixel_index(16, 12, 12);
+        assert_eq!(output[outside..outside + 4], input[outside..outside + 4]);
+    }
+
+    #[test]
+    fn blur_enforces_minimum_strength() {
+        let input = gradient(32, 32);
+        let mut output = vec![0u8; input.len()];
+        let spec = RedactionSpec {
+            mode: RedactionMode::Blur { radius: 1 },
+            regions: vec![RedactionRegion::Polygon {
+                points: vec![[0.0, 0.0], [32.0, 0.0], [32.0, 32.0], [0.0, 32.0]],
+            }],
+        };
+        redact_regions(&input, 32, 32, &spec, &mut output).unwrap();
+
+        let changed = input.chunks(4).zip(output.chunks(4)).filter(|(a, b)| a != b).count();
+        assert!(changed > 32 * 32 * 9 / 10);
+    }
+
+    #[test]
+    fn oversized_strength_is_rejected() {
+        let input = gradient(16, 16);
+        let mut output = vec![0u8; input.len()];
+        let region = r#""regions":[{"type":"rect","x":0,"y":0,"width":8,"height":8}]"#;
+        for mode in [r#"{"type":"blur","radius":4294967295}"#, r#"{"type":"pixelate","blockSize":17}"#] {
+            let spec = parse_redaction(&format!(r#"{{"mode":{},{}}}"#, mode, region)).unwrap();
+            let error = redact_regions(&input, 16, 16, &spec, &mut output).unwrap_err();
+            assert_eq!(error.code(), photo_editor_common::ErrorCode::InvalidParameter);
+        }
+    }
+
+    #[test]
+    fn running_sum_box_blur_matches_direct_mean() {
+        let src: Vec<[f32; 4]> = (0..7 * 5).map(|i| [(i * 37 % 256) as f32, i as f32, 0.0, 255.0]).collect();
+        let mut dst = vec![[0f32; 4]; src.len()];
+        box_blur_pass(&src, &mut dst, 7, 5, 2, true);
+        for (i, out) in dst.iter().enumerate() {
+            let (line, x) = (i / 7, i % 7);
+            let window = &src[line * 7 + x.saturating_sub(2)..=line * 7 + (x + 2).min(6)];
+            let mean = window.iter().map(|p| p[0]).sum::<f32>() / window.len() as f32;
+            assert!((out[0] - mean).abs() < 1e-3);
+        }
+    }
+}
@@ -1,10 +1,8 @@
-use wasm_bindgen::prelude::*;
-
-use image::imageops::FilterType;
-use image::RgbaImage;
+use photo_editor_common::{Error, Progress};
 
 use crate::{utils, ResizeQuality};
 
+#[allow(clippy::too_many_arguments)]
 pub fn resize_image(
     input: &[u8],
     width: u32,
@@ -13,35 +11,192 @@ pub fn resize_image(
     new_height: u32,
     quality: ResizeQuality,
     output: &mut [u8],
-) -> Result<usize, JsValue> {
+    progress: &mut Progress,
+) -> Result<usize, Error> {
     utils::validate_input(input, width, height)?;
     if new_width == 0 || new_height == 0 {
-        return Err(JsValue::from_str("New dimensions must be > 0"));
+        return Err(Error::invalid_dimensions("New dimensions must be > 0"));
     }
 
     let out_len = utils::checked_len(new_width, new_height)?;
     utils::validate_output(output, out_len)?;
 
-    let src = RgbaImage::from_raw(width, height, input.to_vec())
-        .ok_or_else(|| JsValue::from_str("Failed to build source image"))?;
+    let data = resample(input, width, height, new_width, new_height, quality, progress)?;
 
-    let filter = match quality {
-        ResizeQuality::Fast => FilterType::Triangle,
-        ResizeQuality::High => FilterType::CatmullRom,
-    };
+    utils::copy_into_output(output, &data)
+}
+
+/// 16-bit variant of [`resize_image`]; filtering runs in f32 so no
+/// precision is lost to intermediate rounding.
+#[allow(clippy::too_many_arguments)]
+pub fn resize_image_16(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    quality: ResizeQuality,
+    output: &mut [u16],
+    progress: &mut Progress,
+) -> Result<usize, Error> {
+    utils::validate_input(input, width, height)?;
+    if new_width == 0 || new_height == 0 {
+        return Err(Error::invalid_dimensions("New dimensions must be > 0"));
+    }
+
+    let out_len = utils::checked_len(new_width, new_height)?;
+    utils::validate_output(output, out_len)?;
 
-    let resized = image::imageops::resize(&src, new_width, new_height, filter);
-    let data = resized.into_raw();
+    let data = resample(input, width, height, new_width, new_height, quality, progress)?;
 
     utils::copy_into_output(output, &data)
 }
 
+/// RGBA channel types the resampler reads and writes
+trait Channel: Copy {
+    const MAX: f32;
+    fn to_f32(self) -> f32;
+    /// From a value already clamped to 0..=MAX and rounded
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    const MAX: f32 = u8::MAX as f32;
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value as u8
+    }
+}
+
+impl Channel for u16 {
+    const MAX: f32 = u16::MAX as f32;
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value as u16
+    }
+}
+
+/**
+ * Separable resampling with the same arithmetic as `image::imageops::resize`
+ * (Triangle for Fast, Catmull-Rom for High): a vertical pass into f32, then
+ * a horizontal pass that clamps and rounds. It is done here rather than in
+ * `image` so progress is reported, and cancellation checked, between rows;
+ * each pass is half of `progress`.
+ */
+fn resample<T: Channel>(
+    input: &[T],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    quality: ResizeQuality,
+    progress: &mut Progress,
+) -> Result<Vec<T>, Error> {
+    if (new_width, new_height) == (width, height) {
+        return Ok(input.to_vec());
+    }
+
+    let filter: (fn(f32) -> f32, f32) = match quality {
+        ResizeQuality::Fast => (triangle_kernel, 1.0),
+        ResizeQuality::High => (catmull_rom_kernel, 2.0),
+    };
+    let width = width as usize;
+
+    let mut rows = vec![0f32; width * new_height as usize * 4];
+    for (out_y, (top, ws)) in weights(height, new_height, filter).into_iter().enumerate() {
+        progress.report(out_y as f64 / new_height as f64 * 0.5)?;
+        for x in 0..width {
+            let mut t = [0f32; 4];
+            for (i, w) in ws.iter().enumerate() {
+                let src = ((top + i) * width + x) * 4;
+                for (sum, value) in t.iter_mut().zip(&input[src..src + 4]) {
+                    *sum += value.to_f32() * w;
+                }
+            }
+            let dst = (out_y * width + x) * 4;
+            rows[dst..dst + 4].copy_from_slice(&t);
+        }
+    }
+
+    let columns = weights(width as u32, new_width, filter);
+    let mut out = Vec::with_capacity(new_width as usize * new_height as usize * 4);
+    for y in 0..new_height as usize {
+        progress.report(0.5 + y as f64 / new_height as f64 * 0.5)?;
+        for (left, ws) in &columns {
+            let mut t = [0f32; 4];
+            for (i, w) in ws.iter().enumerate() {
+                let src = (y * width + left + i) * 4;
+                for (sum, value) in t.iter_mut().zip(&rows[src..src + 4]) {
+                    *sum += value * w;
+                }
+            }
+            out.extend(t.iter().map(|v| T::from_f32(v.clamp(0.0, T::MAX).round())));
+        }
+    }
+    Ok(out)
+}
+
+/// First source index and normalized weights for each output position
+/// along one axis
+fn weights(len: u32, new_len: u32, (kernel, support): (fn(f32) -> f32, f32)) -> Vec<(usize, Vec<f32>)> {
+    let ratio = len as f32 / new_len as f32;
+    let sratio = ratio.max(1.0);
+    let src_support = support * sratio;
+
+    (0..new_len)
+        .map(|out| {
+            // Centre of the output pixel in input coordinates
+            let center = (out as f32 + 0.5) * ratio;
+            let left = ((center - src_support).floor() as i64).clamp(0, len as i64 - 1);
+            let right = ((center + src_support).ceil() as i64).clamp(left + 1, len as i64);
+
+            // The kernel treats the centre of a pixel as 0
+            let center = center - 0.5;
+            let mut ws: Vec<f32> = (left..right).map(|i| kernel((i as f32 - center) / sratio)).collect();
+            let mut sum = 0.0;
+            for w in &ws {
+                sum += w;
+            }
+            for w in ws.iter_mut() {
+                *w /= sum;
+            }
+            (left as usize, ws)
+        })
+        .collect()
+}
+
+fn triangle_kernel(x: f32) -> f32 {
+    if x.abs() < 1.0 {
+        1.0 - x.abs()
+    } else {
+        0.0
+    }
+}
+
+/// Catmull-Rom cubic: the B-spline/cardinal family with B = 0, C = 0.5
+fn catmull_rom_kernel(x: f32) -> f32 {
+    let (b, c) = (0.0, 0.5);
+    let a = x.abs();
+    let k = if a < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * a.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * a.powi(2) + (6.0 - 2.0 * b)
+    } else if a < 2.0 {
+        (-b - 6.0 * c) * a.powi(3) + (6.0 * b + 30.0 * c) * a.powi(2) + (-12.0 * b - 48.0 * c) * a + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    k / 6.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn resizes_nearest() {
+    fn resizes_fast() {
         let input = vec![
             1, 0, 0, 255, 2, 0, 0, 255,
             3, 0, 0, 255, 4, 0, 0, 255,
@@ -53,8 +208,9 @@ mod tests {
             2,
             1,
             1,
-            ResizeQuality::Low,
+            ResizeQuality::Fast,
             &mut output,
+            &mut Progress::none(),
         )
         .unwrap();
         assert_eq!(written, 4);
@@ -76,8 +232,35 @@ mod tests {
             2,
             ResizeQuality::High,
             &mut output,
+            &mut Progress::none(),
         )
         .unwrap();
         assert_eq!(written, 16);
     }
+
+    #[test]
+    fn matches_image_resize_and_can_be_cancelled() {
+        use image::imageops::{self, FilterType};
+        use image::RgbaImage;
+
+        let (width, height) = (37u32, 23u32);
+        let input: Vec<u8> = (0..width * height * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let src = RgbaImage::from_raw(width, height, input.clone()).unwrap();
+
+        for (new_width, new_height) in [(13, 9), (80, 41), (37, 10)] {
+            for (quality, filter) in [(ResizeQuality::Fast, FilterType::Triangle), (ResizeQuality::High, FilterType::CatmullRom)] {
+                let expected = imageops::resize(&src, new_width, new_height, filter).into_raw();
+                let mut output = vec![0u8; expected.len()];
+                resize_image(&input, width, height, new_width, new_height, quality, &mut output, &mut Progress::none())
+                    .unwrap();
+                assert_eq!(output, expected);
+            }
+        }
+
+        let mut output = vec![0u8; 80 * 41 * 4];
+        let mut progress = Progress::new(|fraction| fraction < 0.75);
+        let error = resize_image(&input, width, height, 80, 41, ResizeQuality::High, &mut output, &mut progress)
+            .unwrap_err();
+        assert_eq!(error.code(), photo_editor_common::ErrorCode::Cancelled);
+    }
 }
@@ -0,0 +1,364 @@
+use photo_editor_common::Error;
+
+use crate::{geometry, utils, BrushMode, BrushParams};
+
+/// Dab spacing along the stroke as a fraction of the brush radius
+const DAB_SPACING: f32 = 0.25;
+
+/// Over-relaxation factor for the Poisson solver
+const SOR_OMEGA: f32 = 1.9;
+
+const MAX_SOLVER_ITERATIONS: usize = 500;
+
+/// Stop iterating once no pixel moves by more than this
+const SOLVER_TOLERANCE: f32 = 0.05;
+
+/// Stroke bounding box (x0, y0, x1, y1) with exclusive end
+type Bounds = (u32, u32, u32, u32);
+
+/// Apply one clone or heal stroke.
+///
+/// `path` holds x/y pairs in pixel coordinates. Pixels under the brush are
+/// taken from the location offset by (`offset_x`, `offset_y`) in the
+/// original image, so a stroke never samples its own output.
+pub fn brush_stroke(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    path: &[f32],
+    params: &BrushParams,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+    if path.len() < 2 || !path.len().is_multiple_of(2) {
+        return Err(Error::invalid_parameter("Stroke path must contain x, y pairs"));
+    }
+    if !path.iter().all(|v| v.is_finite()) {
+        return Err(Error::invalid_parameter("Stroke path coordinates must be finite"));
+    }
+    if !(params.radius.is_finite() && params.radius > 0.0) {
+        return Err(Error::invalid_parameter("Brush radius must be > 0"));
+    }
+    if !(params.offset_x.is_finite() && params.offset_y.is_finite()) {
+        return Err(Error::invalid_parameter("Brush source offset must be finite"));
+    }
+
+    output[..out_len].copy_from_slice(input);
+
+    let points: Vec<[f32; 2]> = path.chunks(2).map(|p| [p[0], p[1]]).collect();
+    let Some((coverage, bounds)) = stroke_coverage(&points, width, height, params) else {
+        return Ok(out_len);
+    };
+
+    let (x0, y0, x1, y1) = bounds;
+    let bw = (x1 - x0) as usize;
+    let source: Vec<[f32; 4]> = (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            sample_bilinear(input, width, height, x as f32 + params.offset_x, y as f32 + params.offset_y)
+        })
+        .collect();
+
+    let result = match params.mode {
+        BrushMode::Clone => source,
+        BrushMode::Heal => heal(input, width, bounds, &coverage, &source),
+    };
+
+    let opacity = params.opacity.clamp(0.0, 1.0);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let local = (y - y0) as usize * bw + (x - x0) as usize;
+            let alpha = coverage[local] * opacity;
+            if alpha <= 0.0 {
+                continue;
+            }
+            let idx = utils::pixel_index(width, x, y);
+            for c in 0..4 {
+                let orig = input[idx + c] as f32;
+                let value = orig + (result[local][c] - orig) * alpha;
+                output[idx + c] = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    Ok(out_len)
+}
+
+/// Brush coverage (0.0..=1.0) for every pixel in the stroke's bounding box.
+///
+/// Dabs are combined with `max` so overlapping dabs along the path do not
+/// build up opacity.
+fn stroke_coverage(
+    points: &[[f32; 2]],
+    width: u32,
+    height: u32,
+    params: &BrushParams,
+) -> Option<(Vec<f32>, Bounds)> {
+    let radius = params.radius;
+    // One extra pixel keeps a ring of untouched pixels around the stroke,
+    // which the heal solver uses as its boundary condition.
+    let pad = radius + 1.0;
+    let (min_x, min_y, max_x, max_y) = geometry::bounds(points);
+    let x0 = (min_x - pad).floor().max(0.0) as u32;
+    let y0 = (min_y - pad).floor().max(0.0) as u32;
+    let x1 = ((max_x + pad).ceil().max(0.0) as u32).min(width);
+    let y1 = ((max_y + pad).ceil().max(0.0) as u32).min(height);
+    if x0 >= x1 || y0 >= y1 {
+        return None;
+    }
+
+    let mut dabs = vec![points[0]];
+    let spacing = (radius * DAB_SPACING).max(0.5);
+    // Dabs further than a radius from the bounding box can't touch it; only
+    // placing them on the part of a segment inside this area keeps a point
+    // far off the canvas from producing billions of dabs.
+    let reach = [x0 as f32 - radius, y0 as f32 - radius, x1 as f32 + radius, y1 as f32 + radius];
+    for pair in points.windows(2) {
+        let Some((t0, t1)) = clip_segment(pair[0], pair[1], reach) else {
+            continue;
+        };
+        let at = |t: f32| [pair[0][0] + (pair[1][0] - pair[0][0]) * t, pair[0][1] + (pair[1][1] - pair[0][1]) * t];
+        let ([ax, ay], [bx, by]) = (at(t0), at(t1));
+        let len = (bx - ax).hypot(by - ay);
+        let steps = (len / spacing).ceil() as usize;
+        // A segment entering the area gets a dab where it enters
+        let first = if t0 > 0.0 { 0 } else { 1 };
+        for i in first..=steps {
+            let t = i as f32 / steps.max(1) as f32;
+            dabs.push([ax + (bx - ax) * t, ay + (by - ay) * t]);
+        }
+    }
+
+    let hardness = params.hardness.clamp(0.0, 1.0);
+    let inner = radius * hardness;
+    let bw = (x1 - x0) as usize;
+    let mut coverage = vec![0f32; bw * (y1 - y0) as usize];
+    for [dx, dy] in dabs {
+        let ex0 = ((dx - radius).floor().max(x0 as f32) as u32).min(x1);
+        let ey0 = ((dy - radius).floor().max(y0 as f32) as u32).min(y1);
+        let ex1 = ((dx + radius).ceil().max(0.0) as u32).min(x1);
+        let ey1 = ((dy + radius).ceil().max(0.0) as u32).min(y1);
+        for y in ey0..ey1 {
+            for x in ex0..ex1 {
+                let d = ((x as f32 + 0.5 - dx).powi(2) + (y as f32 + 0.5 - dy).powi(2)).sqrt();
+                let value = if d <= inner {
+                    1.0
+                } else if d >= radius {
+                    0.0
+                } else {
+                    let t = (radius - d) / (radius - inner);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                let local = (y - y0) as usize * bw + (x - x0) as usize;
+                coverage[local] = coverage[local].max(value);
+            }
+        }
+    }
+
+    Some((coverage, (x0, y0, x1, y1)))
+}
+
+fn sample_bilinear(data: &[u8], width: u32, height: u32, x: f32, y: f32) -> [f32; 4] {
+    let fx = x.clamp(0.0, (width - 1) as f32);
+    let fy = y.clamp(0.0, (height - 1) as f32);
+    let (ix, iy) = (fx.floor() as u32, fy.floor() as u32);
+    let (nx, ny) = ((ix + 1).min(width - 1), (iy + 1).min(height - 1));
+    let (tx, ty) = (fx - ix as f32, fy - iy as f32);
+
+    let mut out = [0f32; 4];
+    for (c, value) in out.iter_mut().enumerate() {
+        let p = |px: u32, py: u32| data[utils::pixel_index(width, px, py) + c] as f32;
+        let top = p(ix, iy) + (p(nx, iy) - p(ix, iy)) * tx;
+        let bottom = p(ix, ny) + (p(nx, ny) - p(ix, ny)) * tx;
+        *value = top + (bottom - top) * ty;
+    }
+    out
+}
+
+/// Gradient-domain (Poisson) blend of the source texture into the brushed
+/// region: the result keeps the source's gradients while matching the
+/// original pixels along the region boundary, so texture is transplanted
+/// without carrying over its color or brightness.
+fn heal(
+    input: &[u8],
+    width: u32,
+    bounds: Bounds,
+    coverage: &[f32],
+    source: &[[f32; 4]],
+) -> Vec<[f32; 4]> {
+    let (x0, y0, x1, y1) = bounds;
+    let (bw, bh) = ((x1 - x0) as usize, (y1 - y0) as usize);
+    let inside = |lx: usize, ly: usize| coverage[ly * bw + lx] > 0.0;
+    let original = |lx: usize, ly: usize| {
+        let idx = utils::pixel_index(width, x0 + lx as u32, y0 + ly as u32);
+        [input[idx] as f32, input[idx + 1] as f32, input[idx + 2] as f32, input[idx + 3] as f32]
+    };
+
+    // Start from the source shifted by the mean boundary difference; the
+    // solver then only has to remove the low-frequency error.
+    let mut offset = [0f32; 3];
+    let mut boundary = 0usize;
+    for ly in 0..bh {
+        for lx in 0..bw {
+            if inside(lx, ly) && (lx == 0 || ly == 0 || lx == bw - 1 || ly == bh - 1 || !inside(lx - 1, ly)
+                || !inside(lx + 1, ly) || !inside(lx, ly - 1) || !inside(lx, ly + 1))
+            {
+                let orig = original(lx, ly);
+                for c in 0..3 {
+                    offset[c] += orig[c] - source[ly * bw + lx][c];
+                }
+                boundary += 1;
+            }
+        }
+    }
+    if boundary > 0 {
+        offset.iter_mut().for_each(|o| *o /= boundary as f32);
+    }
+
+    let mut result: Vec<[f32; 4]> = (0..bw * bh)
+        .map(|i| {
+            let (lx, ly) = (i % bw, i / bw);
+            if inside(lx, ly) {
+                let s = source[i];
+                [s[0] + offset[0], s[1] + offset[1], s[2] + offset[2], s[3]]
+            } else {
+                original(lx, ly)
+            }
+        })
+        .collect();
+
+    // Untouched pixels inside the box act as fixed boundary values; where
+    // the box is clipped by the image edge the missing neighbours are simply
+    // left out of the stencil.
+    for _ in 0..MAX_SOLVER_ITERATIONS {
+        let mut max_change = 0f32;
+        for ly in 0..bh {
+            for lx in 0..bw {
+                if !inside(lx, ly) {
+                    continue;
+                }
+                let i = ly * bw + lx;
+                let neighbours = [
+                    (lx > 0).then(|| i - 1),
+                    (lx + 1 < bw).then(|| i + 1),
+                    (ly > 0).then(|| i - bw),
+                    (ly + 1 < bh).then(|| i + bw),
+                ];
+
+                for c in 0..3 {
+                    let mut sum = 0f32;
+                    let mut count = 0f32;
+                    for n in neighbours.iter().flatten() {
+                        sum += result[*n][c] + source[i][c] - source[*n][c];
+                        count += 1.0;
+                    }
+                    if count == 0.0 {
+                        continue;
+                    }
+                    let updated = result[i][c] + SOR_OMEGA * (sum / count - result[i][c]);
+                    max_change = max_change.max((updated - result[i][c]).abs());
+                    result[i][c] = updated;
+                }
+            }
+        }
+        if max_change < SOLVER_TOLERANCE {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Range of t (0..=1) for which a + (b - a) * t lies inside `rect`
+/// (x0, y0, x1, y1), by Liang-Barsky clipping
+fn clip_segment(a: [f32; 2], b: [f32; 2], rect: [f32; 4]) -> Option<(f32, f32)> {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let (mut t0, mut t1) = (0f32, 1f32);
+    for (p, q) in [(-dx, a[0] - rect[0]), (dx, rect[2] - a[0]), (-dy, a[1] - rect[1]), (dy, rect[3] - a[1])] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Left half: flat dark gray; right half: bright checkerboard texture.
+    fn two_halves(size: u32) -> Vec<u8> {
+        (0..size * size)
+            .flat_map(|i| {
+                let (x, y) = (i % size, i / size);
+                if x < size / 2 {
+                    [50, 50, 50, 255]
+                } else if (x + y) % 2 == 0 {
+                    [210, 210, 210, 255]
+                } else {
+                    [190, 190, 190, 255]
+                }
+            })
+            .collect()
+    }
+
+    fn params(mode: BrushMode) -> BrushParams {
+        BrushParams {
+            radius: 4.0,
+            hardness: 1.0,
+            offset_x: 16.0,
+            offset_y: 0.0,
+            opacity: 1.0,
+            mode,
+        }
+    }
+
+    #[test]
+    fn clone_copies_source_pixels() {
+        let input = two_halves(32);
+        let mut output = vec![0u8; input.len()];
+        brush_stroke(&input, 32, 32, &[8.0, 16.0, 8.0, 16.0], &params(BrushMode::Clone), &mut output).unwrap();
+
+        let target = utils::pixel_index(32, 8, 16);
+        let source = utils::pixel_index(32, 24, 16);
+        assert_eq!(output[target..target + 4], input[source..source + 4]);
+    }
+
+    #[test]
+    fn heal_keeps_texture_but_matches_surrounding_tone() {
+        let input = two_halves(32);
+        let mut output = vec![0u8; input.len()];
+        brush_stroke(&input, 32, 32, &[8.0, 16.0, 8.0, 16.0], &params(BrushMode::Heal), &mut output).unwrap();
+
+        let a = output[utils::pixel_index(32, 8, 16)] as i32;
+        let b = output[utils::pixel_index(32, 9, 16)] as i32;
+        assert!((a - 50).abs() < 20 && (b - 50).abs() < 20);
+        assert!((a - b).abs() >= 10);
+    }
+
+    #[test]
+    fn far_off_or_non_finite_points_are_bounded() {
+        let input = two_halves(32);
+        let mut output = vec![0u8; input.len()];
+        let path = [8.0, 16.0, 1e30, 16.0];
+        brush_stroke(&input, 32, 32, &path, &params(BrushMode::Clone), &mut output).unwrap();
+        let target = utils::pixel_index(32, 8, 16);
+        let source = utils::pixel_index(32, 24, 16);
+        assert_eq!(output[target..target + 4], input[source..source + 4]);
+
+        for path in [[8.0, 16.0, f32::INFINITY, 16.0], [8.0, 16.0, f32::NAN, 16.0]] {
+            let error = brush_stroke(&input, 32, 32, &path, &params(BrushMode::Clone), &mut output).unwrap_err();
+            assert_eq!(error.code(), photo_editor_common::ErrorCode::InvalidParameter);
+        }
+        let mut nan_radius = params(BrushMode::Clone);
+        nan_radius.radius = f32::NAN;
+        assert!(brush_stroke(&input, 32, 32, &[8.0, 16.0], &nan_radius, &mut output).is_err());
+    }
+}
@@ -1,14 +1,16 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::Error;
 
 use crate::{utils, FlipDirection, RotateAngle};
 
-pub fn rotate_image(
-    input: &[u8],
+/// Rotate RGBA pixels of any sample type (8-bit, or 16-bit from the
+/// high-precision path) clockwise.
+pub fn rotate_image<T: Copy>(
+    input: &[T],
     width: u32,
     height: u32,
     angle: RotateAngle,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
+    output: &mut [T],
+) -> Result<usize, Error> {
     utils::validate_input(input, width, height)?;
 
     let out_len = utils::checked_len(width, height)?;
@@ -60,13 +62,13 @@ pub fn rotate_image(
     }
 }
 
-pub fn flip_image(
-    input: &[u8],
+pub fn flip_image<T: Copy>(
+    input: &[T],
     width: u32,
     height: u32,
     direction: FlipDirection,
-    output: &mut [u8],
-) -> Result<usize, JsValue> {
+    output: &mut [T],
+) -> Result<usize, Error> {
     utils::validate_input(input, width, height)?;
 
     let out_len = utils::checked_len(width, height)?;
@@ -87,6 +89,48 @@ pub fn flip_image(
     Ok(out_len)
 }
 
+/// Apply an EXIF Orientation value (1-8) so the pixels display upright.
+///
+/// Orientations 5-8 swap width and height. Returns the transformed pixels
+/// and their dimensions. Decoding with `auto_orient` goes through here too.
+pub fn apply_exif_orientation<T: Copy + Default>(
+    input: &[T],
+    width: u32,
+    height: u32,
+    orientation: u8,
+) -> Result<(Vec<T>, u32, u32), Error> {
+    let len = utils::validate_input(input, width, height)?;
+
+    let (angle, flip) = match orientation {
+        1 => (RotateAngle::Degree0, None),
+        2 => (RotateAngle::Degree0, Some(FlipDirection::Horizontal)),
+        3 => (RotateAngle::Degree180, None),
+        4 => (RotateAngle::Degree0, Some(FlipDirection::Vertical)),
+        5 => (RotateAngle::Degree90, Some(FlipDirection::Horizontal)),
+        6 => (RotateAngle::Degree90, None),
+        7 => (RotateAngle::Degree270, Some(FlipDirection::Horizontal)),
+        8 => (RotateAngle::Degree270, None),
+        _ => return Err(Error::invalid_parameter("EXIF orientation must be between 1 and 8")),
+    };
+
+    let (out_w, out_h) = match angle {
+        RotateAngle::Degree90 | RotateAngle::Degree270 => (height, width),
+        _ => (width, height),
+    };
+
+    let mut rotated = vec![T::default(); len];
+    rotate_image(input, width, height, angle, &mut rotated)?;
+
+    match flip {
+        Some(direction) => {
+            let mut flipped = vec![T::default(); len];
+            flip_image(&rotated, out_w, out_h, direction, &mut flipped)?;
+            Ok((flipped, out_w, out_h))
+        }
+        None => Ok((rotated, out_w, out_h)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,4 +164,14 @@ mod tests {
         ];
         assert_eq!(output, expected);
     }
+
+    #[test]
+    fn exif_transpose_swaps_axes() {
+        // 3x2 image, orientation 5 (transpose): pixel (x, y) moves to (y, x)
+        let input: Vec<u8> = (0..6).flat_map(|i| [i, 0, 0, 255]).collect();
+        let (output, w, h) = apply_exif_orientation(&input, 3, 2, 5).unwrap();
+        assert_eq!((w, h), (2, 3));
+        let reds: Vec<u8> = output.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(reds, vec![0, 3, 1, 4, 2, 5]);
+    }
 }
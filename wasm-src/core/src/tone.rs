@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use photo_editor_common::Error;
+
+use crate::utils;
+
+/// Tone adjustment, serialized as
+/// `{"levels":{"inputBlack":0.05,"inputWhite":0.95,"gamma":1.2},"curve":[[0,0],[0.5,0.6],[1,1]]}`.
+///
+/// All values are normalized to 0.0..=1.0 so one spec gives the same result
+/// on 8-bit and 16-bit pixels. Levels are applied before the curve; alpha is
+/// never changed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ToneSpec {
+    #[serde(default)]
+    pub levels: Option<Levels>,
+    #[serde(default)]
+    pub curve: Option<Vec<[f32; 2]>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Levels {
+    pub input_black: f32,
+    pub input_white: f32,
+    pub gamma: f32,
+    pub output_black: f32,
+    pub output_white: f32,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            input_black: 0.0,
+            input_white: 1.0,
+            gamma: 1.0,
+            output_black: 0.0,
+            output_white: 1.0,
+        }
+    }
+}
+
+impl Levels {
+    fn apply(&self, x: f32) -> f32 {
+        let range = (self.input_white - self.input_black).max(f32::EPSILON);
+        let v = ((x - self.input_black) / range).clamp(0.0, 1.0);
+        let v = v.powf(1.0 / self.gamma.max(0.01));
+        self.output_black + v * (self.output_white - self.output_black)
+    }
+}
+
+/// Monotone cubic (Fritsch-Carlson) interpolation through the control
+/// points, so the curve never overshoots between them.
+struct Curve {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    tangents: Vec<f32>,
+}
+
+impl Curve {
+    fn new(points: &[[f32; 2]]) -> Option<Curve> {
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        points.dedup_by(|a, b| (a[0] - b[0]).abs() < 1e-6);
+        if points.len() < 2 {
+            return None;
+        }
+
+        let xs: Vec<f32> = points.iter().map(|p| p[0]).collect();
+        let ys: Vec<f32> = points.iter().map(|p| p[1]).collect();
+        let n = xs.len();
+        let secants: Vec<f32> = (0..n - 1).map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k])).collect();
+
+        let mut tangents = vec![0f32; n];
+        tangents[0] = secants[0];
+        tangents[n - 1] = secants[n - 2];
+        for k in 1..n - 1 {
+            if secants[k - 1] * secants[k] > 0.0 {
+                tangents[k] = (secants[k - 1] + secants[k]) / 2.0;
+            }
+        }
+        for k in 0..n - 1 {
+            if secants[k] == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[k] / secants[k];
+            let b = tangents[k + 1] / secants[k];
+            let s = a * a + b * b;
+            if s > 9.0 {
+                let t = 3.0 / s.sqrt();
+                tangents[k] = t * a * secants[k];
+                tangents[k + 1] = t * b * secants[k];
+            }
+        }
+
+        Some(Curve { xs, ys, tangents })
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+
+        let k = self.xs.partition_point(|&px| px <= x) - 1;
+        let h = self.xs[k + 1] - self.xs[k];
+        let t = (x - self.xs[k]) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * self.ys[k]
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[k]
+            + (-2.0 * t3 + 3.0 * t2) * self.ys[k + 1]
+            + (t3 - t2) * h * self.tangents[k + 1]
+    }
+}
+
+pub fn parse_tone(json: &str) -> Result<ToneSpec, Error> {
+    serde_json::from_str(json).map_err(|e| Error::invalid_parameter(format!("Invalid tone spec: {}", e)))
+}
+
+/// Lookup table with `size` entries mapping normalized input to output.
+fn build_lut(spec: &ToneSpec, size: usize) -> Vec<f32> {
+    let curve = spec.curve.as_deref().and_then(Curve::new);
+    (0..size)
+        .map(|i| {
+            let mut v = i as f32 / (size - 1) as f32;
+            if let Some(levels) = &spec.levels {
+                v = levels.apply(v);
+            }
+            if let Some(curve) = &curve {
+                v = curve.apply(v);
+            }
+            v.clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Apply levels and a tone curve to 8-bit RGBA pixels.
+pub fn adjust_tone(input: &[u8], width: u32, height: u32, spec: &ToneSpec, output: &mut [u8]) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    let lut: Vec<u8> = build_lut(spec, 256).iter().map(|v| (v * 255.0).round() as u8).collect();
+    for (i, (src, dst)) in input.iter().zip(output.iter_mut()).enumerate() {
+        *dst = if i % 4 == 3 { *src } else { lut[*src as usize] };
+    }
+    Ok(out_len)
+}
+
+/// Apply levels and a tone curve to 16-bit RGBA pixels. Steep curves keep
+/// smooth gradients here where the 8-bit variant would band.
+pub fn adjust_tone_16(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    spec: &ToneSpec,
+    output: &mut [u16],
+) -> Result<usize, Error> {
+    let out_len = utils::validate_input(input, width, height)?;
+    utils::validate_output(output, out_len)?;
+
+    let lut: Vec<u16> = build_lut(spec, 65536).iter().map(|v| (v * 65535.0).round() as u16).collect();
+    for (i, (src, dst)) in input.iter().zip(output.iter_mut()).enumerate() {
+        *dst = if i % 4 == 3 { *src } else { lut[*src as usize] };
+    }
+    Ok(out_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn curve_passes_through_points_without_overshoot() {
+        let curve = Curve::new(&[[0.0, 0.0], [0.5, 0.8], [0.6, 0.8], [1.0, 1.0]]).unwrap();
+        assert!((curve.apply(0.5) - 0.8).abs() < 1e-6);
+        assert!((0..=100).all(|i| curve.apply(0.5 + i as f32 / 1000.0) <= 0.8 + 1e-6));
+    }
+
+    #[test]
+    fn sixteen_bit_levels_avoid_banding() {
+        // A narrow slice of a sky gradient stretched to the full range
+        let spec = parse_tone(r#"{"levels":{"inputBlack":0.40,"inputWhite":0.42}}"#).unwrap();
+        let width = 1024u32;
+
+        let input16: Vec<u16> = (0..width)
+            .flat_map(|x| {
+                let v = ((0.40 + 0.02 * x as f32 / (width - 1) as f32) * 65535.0).round() as u16;
+                [v, v, v, 65535]
+            })
+            .collect();
+        let mut output16 = vec![0u16; input16.len()];
+        adjust_tone_16(&input16, width, 1, &spec, &mut output16).unwrap();
+
+        let input8: Vec<u8> = input16.iter().map(|&v| (v / 257) as u8).collect();
+        let mut output8 = vec![0u8; input8.len()];
+        adjust_tone(&input8, width, 1, &spec, &mut output8).unwrap();
+
+        let levels16: HashSet<u16> = output16.chunks(4).map(|p| p[0]).collect();
+        let levels8: HashSet<u8> = output8.chunks(4).map(|p| p[0]).collect();
+        assert!(levels8.len() <= 8);
+        assert!(levels16.len() > 256);
+    }
+}
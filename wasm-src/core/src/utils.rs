@@ -1,31 +1,31 @@
-use wasm_bindgen::prelude::*;
+use photo_editor_common::Error;
 
-pub fn checked_len(width: u32, height: u32) -> Result<usize, JsValue> {
+pub fn checked_len(width: u32, height: u32) -> Result<usize, Error> {
     let pixels = width
         .checked_mul(height)
-        .ok_or_else(|| JsValue::from_str("Image dimensions overflow"))?;
+        .ok_or_else(|| Error::invalid_dimensions("Image dimensions overflow"))?;
     let len = pixels
         .checked_mul(4)
-        .ok_or_else(|| JsValue::from_str("Image buffer length overflow"))?;
+        .ok_or_else(|| Error::invalid_dimensions("Image buffer length overflow"))?;
     Ok(len as usize)
 }
 
-pub fn validate_input(input: &[u8], width: u32, height: u32) -> Result<usize, JsValue> {
+pub fn validate_input<T>(input: &[T], width: u32, height: u32) -> Result<usize, Error> {
     let expected = checked_len(width, height)?;
     if input.len() != expected {
-        return Err(JsValue::from_str("Input buffer length mismatch"));
+        return Err(Error::input_size_mismatch("Input buffer length mismatch"));
     }
     Ok(expected)
 }
 
-pub fn validate_output(output: &mut [u8], expected: usize) -> Result<(), JsValue> {
+pub fn validate_output<T>(output: &mut [T], expected: usize) -> Result<(), Error> {
     if output.len() < expected {
-        return Err(JsValue::from_str("Output buffer too small"));
+        return Err(Error::output_too_small("Output buffer too small"));
     }
     Ok(())
 }
 
-pub fn copy_into_output(output: &mut [u8], data: &[u8]) -> Result<usize, JsValue> {
+pub fn copy_into_output<T: Copy>(output: &mut [T], data: &[T]) -> Result<usize, Error> {
     validate_output(output, data.len())?;
     output[..data.len()].copy_from_slice(data);
     Ok(data.len())
@@ -0,0 +1,223 @@
+// JavaScript exports: same operations as the native API in lib.rs, filling
+// caller-provided buffers so the browser can reuse its typed arrays
+
+use photo_editor_common::{Progress, ProgressToken};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    annotate, color_profile, crop, decode, depth, redact, resize, retouch, rotate, tone, utils,
+};
+use crate::{BrushParams, CropRect, DecodedImage, DecodedImage16, FlipDirection, InpaintMethod, ResizeQuality, RotateAngle};
+
+#[wasm_bindgen]
+pub fn crop_image(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    crop_rect: &CropRect,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(crop::crop_image(input, width, height, crop_rect, output)?)
+}
+
+#[wasm_bindgen]
+pub fn rotate_image(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    angle: RotateAngle,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(rotate::rotate_image(input, width, height, angle, output)?)
+}
+
+#[wasm_bindgen]
+pub fn flip_image(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    direction: FlipDirection,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(rotate::flip_image(input, width, height, direction, output)?)
+}
+
+/// Pass a `ProgressToken` to follow progress and to cancel large resizes;
+/// a cancelled call throws with `ErrorCode.Cancelled`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn resize_image(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    quality: ResizeQuality,
+    output: &mut [u8],
+    progress: Option<ProgressToken>,
+) -> Result<usize, JsValue> {
+    let mut progress = Progress::from_token(progress.as_ref());
+    Ok(resize::resize_image(input, width, height, new_width, new_height, quality, output, &mut progress)?)
+}
+
+/// Resize 16-bit RGBA pixels (see `decode_image_16`).
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn resize_image_16(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    quality: ResizeQuality,
+    output: &mut [u16],
+    progress: Option<ProgressToken>,
+) -> Result<usize, JsValue> {
+    let mut progress = Progress::from_token(progress.as_ref());
+    Ok(resize::resize_image_16(input, width, height, new_width, new_height, quality, output, &mut progress)?)
+}
+
+/// Apply a JSON levels/curve spec (normalized 0-1 values) to 8-bit pixels.
+#[wasm_bindgen]
+pub fn adjust_tone(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    tone_json: &str,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    let spec = tone::parse_tone(tone_json)?;
+    Ok(tone::adjust_tone(input, width, height, &spec, output)?)
+}
+
+/// Apply a JSON levels/curve spec to 16-bit pixels.
+#[wasm_bindgen]
+pub fn adjust_tone_16(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    tone_json: &str,
+    output: &mut [u16],
+) -> Result<usize, JsValue> {
+    let spec = tone::parse_tone(tone_json)?;
+    Ok(tone::adjust_tone_16(input, width, height, &spec, output)?)
+}
+
+/// Draw a JSON list of vector annotations (rectangles, rounded rectangles,
+/// ellipses, lines, arrows and freehand strokes) onto a copy of the image.
+#[wasm_bindgen]
+pub fn draw_annotations(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    annotations_json: &str,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    let annotations = annotate::parse_annotations(annotations_json)?;
+    Ok(annotate::draw_annotations(input, width, height, &annotations, output)?)
+}
+
+/// Irreversibly obscure rectangles or polygons by pixelation, strong blur
+/// or opaque fill, as described by a JSON redaction spec.
+#[wasm_bindgen]
+pub fn redact_regions(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    spec_json: &str,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    let spec = redact::parse_redaction(spec_json)?;
+    Ok(redact::redact_regions(input, width, height, &spec, output)?)
+}
+
+/// Fill the pixels selected by a one-byte-per-pixel mask (non-zero = remove)
+/// from the surrounding image.
+#[wasm_bindgen]
+pub fn inpaint(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    mask: &[u8],
+    method: InpaintMethod,
+    radius: u32,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(crate::inpaint::inpaint(input, width, height, mask, method, radius, output)?)
+}
+
+/// Apply a clone-stamp or healing-brush stroke. `path` is a flat list of
+/// x, y pairs in pixel coordinates.
+#[wasm_bindgen]
+pub fn brush_stroke(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    path: &[f32],
+    params: &BrushParams,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(retouch::brush_stroke(input, width, height, path, params, output)?)
+}
+
+/// Decode a JPEG, PNG or WebP file (plus BMP, GIF, TIFF and ICO when the
+/// matching cargo features are enabled) into RGBA pixels. `auto_orient`
+/// rotates/flips the pixels according to the EXIF Orientation tag, and
+/// `to_srgb` converts them from the embedded ICC profile to sRGB.
+#[wasm_bindgen]
+pub fn decode_image(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage, JsValue> {
+    Ok(decode::decode_image(bytes, auto_orient, to_srgb)?)
+}
+
+/// Decode an image file into 16-bit RGBA working pixels, keeping the full
+/// precision of 16-bit PNG/TIFF sources. 8-bit sources are widened.
+#[wasm_bindgen]
+pub fn decode_image_16(bytes: &[u8], auto_orient: bool, to_srgb: bool) -> Result<DecodedImage16, JsValue> {
+    Ok(decode::decode_image_16(bytes, auto_orient, to_srgb)?)
+}
+
+/// Widen 8-bit RGBA pixels to 16 bits per channel.
+#[wasm_bindgen]
+pub fn convert_to_rgba16(input: &[u8], width: u32, height: u32, output: &mut [u16]) -> Result<usize, JsValue> {
+    Ok(depth::convert_to_rgba16(input, width, height, output)?)
+}
+
+/// Narrow 16-bit RGBA pixels to 8 bits per channel, optionally dithered to
+/// hide banding. Use for previews; the compress module narrows on export.
+#[wasm_bindgen]
+pub fn convert_to_rgba8(
+    input: &[u16],
+    width: u32,
+    height: u32,
+    dither: bool,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(depth::convert_to_rgba8(input, width, height, dither, output)?)
+}
+
+/// Rotate/flip pixels according to an EXIF Orientation value (1-8).
+/// Orientations 5-8 swap width and height.
+#[wasm_bindgen]
+pub fn apply_exif_orientation(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    orientation: u8,
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    let (data, _, _) = rotate::apply_exif_orientation(input, width, height, orientation)?;
+    Ok(utils::copy_into_output(output, &data)?)
+}
+
+/// Convert RGBA pixels from the color space of an RGB ICC profile (e.g.
+/// Display P3 or Adobe RGB) to sRGB.
+#[wasm_bindgen]
+pub fn convert_to_srgb(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    icc_profile: &[u8],
+    output: &mut [u8],
+) -> Result<usize, JsValue> {
+    Ok(color_profile::convert_to_srgb(input, width, height, icc_profile, output)?)
+}